@@ -0,0 +1,58 @@
+//! Compares collecting the owning ordered iterator with `drain_ordered_into()`
+//! for a 64-byte element type. Both include cloning the reservoir.
+//!
+//! Run with `cargo bench --bench drain_ordered`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use infinity_sampler::heapless::Vec;
+use infinity_sampler::SamplingReservoir;
+
+const N: usize = 1024;
+const ROUNDS: usize = 20_000;
+
+type Element = [u64; 8];
+
+fn time(name: &str, mut f: impl FnMut() -> u64) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0u64;
+    for _ in 0..ROUNDS {
+        checksum = checksum.wrapping_add(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:>20}: {:>8.1} ns per pass (checksum {})",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64,
+        checksum
+    );
+    elapsed
+}
+
+fn main() {
+    let mut reservoir = SamplingReservoir::<Element, N>::new();
+    // Stop mid-pattern so that all three spacing regimes are present
+    for i in 0..(N as u64 * 37 + 12_345) {
+        let _ = reservoir.sample([i; 8]);
+    }
+    let reservoir = black_box(&reservoir);
+    let mut out = Vec::<Element, N>::new();
+
+    let clone = time("clone only", || {
+        let reservoir = black_box(reservoir.clone());
+        reservoir.as_unordered_slice()[N - 1][0]
+    });
+    let collect = time("into_ordered_iter()", || {
+        out = reservoir.clone().into_ordered_iter().collect();
+        black_box(&out)[N - 1][0]
+    });
+    let drain = time("drain_ordered_into()", || {
+        reservoir.clone().drain_ordered_into(&mut out);
+        black_box(&out)[N - 1][0]
+    });
+    println!(
+        "drain_ordered_into() speedup without the clone: {:.2}x",
+        (collect - clone).as_secs_f64() / (drain - clone).as_secs_f64()
+    );
+}
@@ -0,0 +1,61 @@
+//! Compares stepping through the ordered iterator with internal iteration.
+//!
+//! Run with `cargo bench --bench ordered_iter`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use infinity_sampler::SamplingReservoir;
+
+const ROUNDS: usize = 20_000;
+
+fn time(name: &str, rounds: usize, mut f: impl FnMut() -> u64) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0u64;
+    for _ in 0..rounds {
+        checksum = checksum.wrapping_add(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:>8}: {:>8.1} ns per pass (checksum {})",
+        name,
+        elapsed.as_nanos() as f64 / rounds as f64,
+        checksum
+    );
+    elapsed
+}
+
+fn run<const N: usize>() {
+    println!("N = {}", N);
+    let rounds = ROUNDS * 1024 / N;
+    let mut reservoir = SamplingReservoir::<u64, N>::new();
+    // Stop mid-pattern so that all three spacing regimes are present
+    for i in 0..(N as u64 * 37 + 12_345) {
+        let _ = reservoir.sample(i);
+    }
+    let reservoir = black_box(&reservoir);
+
+    let next = time("next()", rounds, || {
+        let mut iter = reservoir.ordered_iter();
+        let mut sum = 0u64;
+        #[allow(clippy::while_let_on_iterator)]
+        while let Some(value) = iter.next() {
+            sum = sum.wrapping_add(*value);
+        }
+        sum
+    });
+    let fold = time("fold()", rounds, || {
+        reservoir
+            .ordered_iter()
+            .fold(0u64, |sum, value| sum.wrapping_add(*value))
+    });
+    println!(
+        "fold() speedup: {:.2}x",
+        next.as_secs_f64() / fold.as_secs_f64()
+    );
+}
+
+fn main() {
+    run::<1024>();
+    run::<4096>();
+}
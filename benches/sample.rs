@@ -0,0 +1,64 @@
+//! Measures the cost of a single `sample()` call, in the discard-heavy steady state
+//! and while every value is still stored, with the computed and the lookup table indexes.
+//!
+//! Run with `cargo bench --bench sample`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use infinity_sampler::{LutSamplingReservoir, SamplingReservoir};
+
+const N: usize = 64;
+const OBSERVATIONS: u32 = 50_000_000;
+
+fn time(name: &str, mut f: impl FnMut() -> u32) -> Duration {
+    let start = Instant::now();
+    let stored = f();
+    let elapsed = start.elapsed();
+    println!(
+        "{:>10}: {:>6.2} ns per sample() ({} stored)",
+        name,
+        elapsed.as_nanos() as f64 / OBSERVATIONS as f64,
+        stored
+    );
+    elapsed
+}
+
+fn main() {
+    // Warm up to a high divisor, so that nearly every observation is discarded
+    let mut reservoir = SamplingReservoir::<u32, N>::new();
+    for i in 0..(N as u32 * 4096) {
+        let _ = reservoir.sample(i);
+    }
+    time("discards", || {
+        let mut stored = 0;
+        for i in 0..OBSERVATIONS {
+            stored += reservoir.sample(black_box(i)).is_consumed() as u32;
+        }
+        stored
+    });
+
+    // Fresh reservoirs filled past the initial pattern, so that most observations are stored
+    time("stores", || {
+        let mut stored = 0;
+        for chunk in 0..OBSERVATIONS / (N as u32 * 4) {
+            let mut reservoir = SamplingReservoir::<u32, N>::new();
+            for i in 0..N as u32 * 4 {
+                stored += reservoir.sample(black_box(chunk + i)).is_consumed() as u32;
+            }
+            black_box(&reservoir);
+        }
+        stored
+    });
+    time("lut stores", || {
+        let mut stored = 0;
+        for chunk in 0..OBSERVATIONS / (N as u32 * 4) {
+            let mut reservoir = LutSamplingReservoir::<u32, N>::new();
+            for i in 0..N as u32 * 4 {
+                stored += reservoir.sample(black_box(chunk + i)).is_consumed() as u32;
+            }
+            black_box(&reservoir);
+        }
+        stored
+    });
+}
@@ -0,0 +1,58 @@
+//! Compares sampling a large block with `sample_slice()` against calling `sample()` per value.
+//!
+//! Run with `cargo bench --bench sample_slice`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use infinity_sampler::SamplingReservoir;
+
+const N: usize = 64;
+const BLOCK: usize = 64 * 1024;
+const ROUNDS: usize = 200;
+
+fn time(name: &str, mut f: impl FnMut() -> u64) -> Duration {
+    let start = Instant::now();
+    let mut checksum = 0u64;
+    for _ in 0..ROUNDS {
+        checksum = checksum.wrapping_add(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:>14}: {:>10.1} ns per block (checksum {})",
+        name,
+        elapsed.as_nanos() as f64 / ROUNDS as f64,
+        checksum
+    );
+    elapsed
+}
+
+fn main() {
+    let block: Vec<u64> = (0..BLOCK as u64).collect();
+    let block = black_box(&block[..]);
+
+    // Warm up to a high divisor, so that most of each block is discarded
+    let mut warm = SamplingReservoir::<u64, N>::new();
+    for i in 0..(N as u64 * 4096) {
+        let _ = warm.sample(i);
+    }
+    println!("divisor: {}", warm.sampling_rate().divisor());
+
+    let mut naive = warm.clone();
+    let loop_time = time("sample() loop", || {
+        for value in block {
+            let _ = naive.sample(*value);
+        }
+        naive.samples_seen() as u64
+    });
+    let mut sliced = warm.clone();
+    let slice_time = time("sample_slice()", || {
+        sliced.sample_slice(block);
+        sliced.samples_seen() as u64
+    });
+    assert_eq!(naive.as_unordered_slice(), sliced.as_unordered_slice());
+    println!(
+        "sample_slice() speedup: {:.2}x",
+        loop_time.as_secs_f64() / slice_time.as_secs_f64()
+    );
+}
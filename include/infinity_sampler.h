@@ -0,0 +1,57 @@
+/* Bindings for the `ffi` feature of the infinity-sampler crate, see src/ffi.rs. */
+
+#ifndef INFINITY_SAMPLER_H
+#define INFINITY_SAMPLER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct InfinitySamplerF32x64 InfinitySamplerF32x64;
+typedef struct InfinitySamplerF32x256 InfinitySamplerF32x256;
+typedef struct InfinitySamplerF32x1024 InfinitySamplerF32x1024;
+typedef struct InfinitySamplerU32x64 InfinitySamplerU32x64;
+typedef struct InfinitySamplerU32x256 InfinitySamplerU32x256;
+typedef struct InfinitySamplerU32x1024 InfinitySamplerU32x1024;
+
+/* The _sample functions return 0 if the value is discarded, 1 if it is stored,
+   2 if it is stored and the rate is reduced, and -1 for a null handle. */
+
+InfinitySamplerF32x64 *infinity_sampler_new_f32_64(void);
+int32_t infinity_sampler_sample_f32_64(InfinitySamplerF32x64 *handle, float value);
+size_t infinity_sampler_ordered_copy_f32_64(const InfinitySamplerF32x64 *handle, float *out, size_t out_len);
+void infinity_sampler_free_f32_64(InfinitySamplerF32x64 *handle);
+
+InfinitySamplerF32x256 *infinity_sampler_new_f32_256(void);
+int32_t infinity_sampler_sample_f32_256(InfinitySamplerF32x256 *handle, float value);
+size_t infinity_sampler_ordered_copy_f32_256(const InfinitySamplerF32x256 *handle, float *out, size_t out_len);
+void infinity_sampler_free_f32_256(InfinitySamplerF32x256 *handle);
+
+InfinitySamplerF32x1024 *infinity_sampler_new_f32_1024(void);
+int32_t infinity_sampler_sample_f32_1024(InfinitySamplerF32x1024 *handle, float value);
+size_t infinity_sampler_ordered_copy_f32_1024(const InfinitySamplerF32x1024 *handle, float *out, size_t out_len);
+void infinity_sampler_free_f32_1024(InfinitySamplerF32x1024 *handle);
+
+InfinitySamplerU32x64 *infinity_sampler_new_u32_64(void);
+int32_t infinity_sampler_sample_u32_64(InfinitySamplerU32x64 *handle, uint32_t value);
+size_t infinity_sampler_ordered_copy_u32_64(const InfinitySamplerU32x64 *handle, uint32_t *out, size_t out_len);
+void infinity_sampler_free_u32_64(InfinitySamplerU32x64 *handle);
+
+InfinitySamplerU32x256 *infinity_sampler_new_u32_256(void);
+int32_t infinity_sampler_sample_u32_256(InfinitySamplerU32x256 *handle, uint32_t value);
+size_t infinity_sampler_ordered_copy_u32_256(const InfinitySamplerU32x256 *handle, uint32_t *out, size_t out_len);
+void infinity_sampler_free_u32_256(InfinitySamplerU32x256 *handle);
+
+InfinitySamplerU32x1024 *infinity_sampler_new_u32_1024(void);
+int32_t infinity_sampler_sample_u32_1024(InfinitySamplerU32x1024 *handle, uint32_t value);
+size_t infinity_sampler_ordered_copy_u32_1024(const InfinitySamplerU32x1024 *handle, uint32_t *out, size_t out_len);
+void infinity_sampler_free_u32_1024(InfinitySamplerU32x1024 *handle);
+
+#ifdef __cplusplus
+}  /* extern "C" */
+#endif
+
+#endif  /* INFINITY_SAMPLER_H */
@@ -0,0 +1,39 @@
+use rand_core::RngCore;
+
+/// A probabilistic sampler that accepts each step with probability `1/divisor`.
+///
+/// Has the same shape as [SamplingRate](crate::SamplingRate), but avoids aliasing
+/// with periodic inputs. Deterministic for a given RNG seed.
+#[derive(Clone)]
+pub struct BernoulliRate<R> {
+    rng: R,
+    divisor: u32,
+}
+
+impl<R: RngCore> BernoulliRate<R> {
+    pub const fn new(rng: R, divisor: u32) -> Self {
+        assert!(divisor > 0);
+        Self { rng, divisor }
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        // Accept if x < 2^32 / divisor, without a division
+        (self.rng.next_u32() as u64 * self.divisor as u64) >> 32 == 0
+    }
+
+    /// Reduce the acceptance probability by a ratio.
+    pub fn div(&mut self, ratio: u32) {
+        assert!(ratio > 0);
+        self.divisor *= ratio;
+    }
+
+    pub fn divisor(&self) -> u32 {
+        self.divisor
+    }
+
+    /// Consume self and return the RNG.
+    pub fn into_inner(self) -> R {
+        self.rng
+    }
+}
@@ -0,0 +1,89 @@
+use crate::SamplingRate;
+
+/// A sampler that accepts at most `budget` steps per window and adapts its divisor
+/// to the number of observations in the previous window.
+///
+/// A window ends automatically after `window_len` observations, or earlier when
+/// [end_window()](Self::end_window) is called (e.g. from a timer tick). The next window
+/// then uses a divisor of `⌈observations / budget⌉`, so the budget is spread over the
+/// whole window if the input rate stays the same.
+///
+/// The first window starts with a divisor of 1, i.e. it accepts the first `budget`
+/// observations. Regardless of the divisor, no more than `budget` steps are ever
+/// accepted within one window.
+///
+/// ```
+/// use infinity_sampler::BudgetedRate;
+///
+/// let mut sampler = BudgetedRate::new(2, 10);
+/// let first: Vec<_> = (0..10).filter(|_| sampler.step()).collect();
+/// assert_eq!(first, [0, 1]);
+/// assert_eq!(sampler.divisor(), 1);
+///
+/// let second: Vec<_> = (10..20).filter(|_| sampler.step()).collect();
+/// assert_eq!(second, [14, 19]);
+/// assert_eq!(sampler.divisor(), 5);
+/// ```
+#[derive(Copy, Clone)]
+pub struct BudgetedRate {
+    budget: u32,
+    window_len: u32,
+    rate: SamplingRate,
+    observed: u32,
+    accepted: u32,
+}
+
+impl BudgetedRate {
+    /// Panics if `budget` or `window_len` is zero.
+    pub const fn new(budget: u32, window_len: u32) -> Self {
+        assert!(budget > 0);
+        assert!(window_len > 0);
+        Self {
+            budget,
+            window_len,
+            rate: SamplingRate::new(1),
+            observed: 0,
+            accepted: 0,
+        }
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        if self.observed == self.window_len {
+            self.end_window();
+        }
+        self.observed += 1;
+        if self.accepted == self.budget || !self.rate.step() {
+            return false;
+        }
+        self.accepted += 1;
+        true
+    }
+
+    /// Ends the current window and adapts the divisor for the next one.
+    pub fn end_window(&mut self) {
+        let divisor = self.observed.div_ceil(self.budget).max(1);
+        self.rate = SamplingRate::new(divisor);
+        self.observed = 0;
+        self.accepted = 0;
+    }
+
+    /// Returns the divisor currently in effect.
+    pub fn divisor(&self) -> u32 {
+        self.rate.divisor()
+    }
+
+    /// Returns the number of steps accepted in the current window.
+    pub fn accepted_in_window(&self) -> u32 {
+        self.accepted
+    }
+
+    /// Returns the number of steps taken in the current window.
+    pub fn observed_in_window(&self) -> u32 {
+        self.observed
+    }
+
+    pub fn budget(&self) -> u32 {
+        self.budget
+    }
+}
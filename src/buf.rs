@@ -1,32 +1,66 @@
-use core::mem::MaybeUninit;
+use core::iter::{FromIterator, FusedIterator, Rev};
+use core::mem::{ManuallyDrop, MaybeUninit};
 use heapless::Vec;
 
+use crate::iter::{InfinitySamplerIndexer, InvalidState};
+use crate::math::{
+    observations_for_stored, order_equivalent_stored, rate_reductions_for, reduces_rate_at,
+    samples_accepted_for, samples_accepted_saturating,
+};
+use crate::rate::ReservoirRate;
 pub use crate::rate::SamplingRate;
+use crate::timed::{Now, TimedSamplingRate};
+use crate::SampleStats;
 
 /// # Infinity Sampler
 ///
 /// See the [top-level doc](crate) for an example.
 ///
 /// The sampling rate gets halved after every `N/2` stored values, which is the same
-/// as every `N*2^X` values observed by the sampler.
+/// as every `N*2^X` values observed by the sampler. With the smallest buffer, `N = 2`,
+/// that is every value stored once the buffer is full, so the reservoir holds the first
+/// observation and the newest power of two.
 ///
 /// Feed the values into the reservoir using [sample()](Self::sample) and then
 /// turn it into an ordered iterator with [into_ordered_iter()](Self::into_ordered_iter).
 ///
 /// The buffer size must be a power of two.
-#[derive(Clone)]
-pub struct SamplingReservoir<T, const N: usize> {
-    buf: Option<Vec<T, N>>,
-    sample_rate: SamplingRate,
-    inner_index: usize,
+///
+/// The rate representation can be swapped through the `R` parameter,
+/// e.g. for [PowerOfTwoRate](crate::PowerOfTwoRate).
+///
+/// With a zero-sized `T` such as `()`, the buffer takes no space and the reservoir is
+/// just its counters, e.g. to decimate events by the outcomes of [sample()](Self::sample):
+///
+/// ```
+/// use core::mem::size_of;
+/// use infinity_sampler::SamplingReservoir;
+///
+/// assert_eq!(
+///     size_of::<SamplingReservoir<(), 4096>>(),
+///     size_of::<SamplingReservoir<(), 2>>()
+/// );
+/// ```
+pub struct SamplingReservoir<T, const N: usize, R = SamplingRate> {
+    buf: Vec<T, N>,
+    sample_rate: R,
+    // The position of the indexer is the only count of the stored values. It isn't
+    // derived from `outer_index`, which stops at the ceiling while values are still
+    // accepted, and deriving it would cost a division on every sample. Below the ceiling,
+    // the two follow the schedule, which `debug_validate()` checks.
+    indexer: InfinitySamplerIndexer<N>,
+    /// The number of observed values.
     outer_index: usize,
 }
 
-impl<T, const N: usize> SamplingReservoir<T, N> {
-    const LOG_N: u32 = N.trailing_zeros();
-
-    // For panic-free `x % (N / 2) == 0` operation
-    const WRAPAROUND_MASK: usize = N / 2 - 1;
+impl<T, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// The number of observations at which [samples_seen()](Self::samples_seen) stops counting.
+    ///
+    /// Past it, the reservoir keeps sampling and storing exactly as before and the ordered
+    /// iterators still yield the stored values in chronological order, but the observation
+    /// indexes they report no longer match the actual observations.
+    /// See [is_saturated()](Self::is_saturated).
+    pub const MAX_SAMPLES_SEEN: usize = usize::MAX / 2;
 
     /// Creates a empty reservoir, allocating an uninitialized buffer.
     /// Panics if `N` is not a power of two.
@@ -37,9 +71,9 @@ impl<T, const N: usize> SamplingReservoir<T, N> {
             "Buffer capacity must be a power of two"
         );
         Self {
-            buf: Some(Vec::new()),
-            sample_rate: SamplingRate::new(1),
-            inner_index: 0,
+            buf: Vec::new(),
+            sample_rate: R::INITIAL,
+            indexer: InfinitySamplerIndexer::new(),
             outer_index: 0,
         }
     }
@@ -51,7 +85,7 @@ impl<T, const N: usize> SamplingReservoir<T, N> {
 
     /// Get the number of currently stored items. Can be from 0 to N-1 and never decreases.
     pub fn len(&self) -> usize {
-        unsafe { self.buf.as_ref().unwrap_unchecked() }.len()
+        self.buf.len()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -59,218 +93,1691 @@ impl<T, const N: usize> SamplingReservoir<T, N> {
     }
 
     /// Consume self and return the internal item buffer.
-    pub fn into_inner(mut self) -> Vec<T, N> {
-        let buf = self.buf.take();
-        unsafe { buf.unwrap_unchecked() }
+    pub fn into_inner(self) -> Vec<T, N> {
+        self.buf
     }
 
     /// Get a view into the occupied part of the internal buffer.
     pub fn as_unordered_slice(&self) -> &[T] {
-        // SAFETY: values up to fill_level are initialized
-        let buf = unsafe { self.buf.as_ref().unwrap_unchecked() };
-        &(buf)[..self.len()]
+        &self.buf
     }
 
     /// Return an iterator over
     /// the items in chronological order - *O(N)*.
-    pub fn ordered_iter(&self) -> impl Iterator<Item = &T> {
-        ReservoirOrderedIter2 {
-            inner: ReservoirOrderedIndexIter {
-                pos: 0,
-                len: self.len(),
-                samples_seen: self.samples_seen(),
-                samples_stored: self.samples_stored(),
+    pub fn ordered_iter(&self) -> ReservoirOrderedIter<'_, T, N> {
+        ReservoirOrderedIter {
+            inner: self.ordered_indices(),
+            buf: self.as_unordered_slice(),
+        }
+    }
+
+    /// Same as [ordered_iter_enumerated()](Self::ordered_iter_enumerated), but allows
+    /// looking at the next item without consuming it.
+    pub fn ordered_peekable(&self) -> OrderedPeekable<'_, T, N> {
+        OrderedPeekable {
+            inner: ReservoirOrderedEnumeratedIter {
+                inner: self.ordered_indices(),
+                buf: self.as_unordered_slice(),
             },
-            buf: self,
+        }
+    }
+
+    /// Return an iterator over the items from the newest to the oldest one - *O(N)*.
+    ///
+    /// Same as `ordered_iter().rev()`, e.g. to take the last few entries.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 8>::new();
+    /// for i in 0..100 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let latest: Vec<_> = reservoir.ordered_iter_rev().take(3).collect();
+    /// assert_eq!(latest, [&96, &80, &64]);
+    /// ```
+    pub fn ordered_iter_rev(&self) -> Rev<ReservoirOrderedIter<'_, T, N>> {
+        self.ordered_iter().rev()
+    }
+
+    /// Return an iterator over the items in chronological order together with
+    /// their original observation indexes - *O(N)*.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<char, 4>::new();
+    /// for c in 'a'..='h' {
+    ///     let _ = reservoir.sample(c);
+    /// }
+    /// let samples: Vec<_> = reservoir.ordered_iter_enumerated().collect();
+    /// assert_eq!(samples, [(0, &'a'), (2, &'c'), (4, &'e'), (6, &'g')]);
+    /// ```
+    pub fn ordered_iter_enumerated(&self) -> impl ExactSizeIterator<Item = (usize, &T)> {
+        ReservoirOrderedEnumeratedIter {
+            inner: self.ordered_indices(),
+            buf: self.as_unordered_slice(),
+        }
+    }
+
+    /// Return an iterator over the items in chronological order together with their
+    /// timestamps, for observations that arrive every `period_ticks` - *O(N)*.
+    ///
+    /// The first observation is at _0_, see [ordered_iter_timed_from()](Self::ordered_iter_timed_from).
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..8 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let samples: Vec<_> = reservoir.ordered_iter_timed(10).collect();
+    /// assert_eq!(samples, [(0, &0), (20, &2), (40, &4), (60, &6)]);
+    /// ```
+    pub fn ordered_iter_timed(
+        &self,
+        period_ticks: u64,
+    ) -> impl ExactSizeIterator<Item = (u64, &T)> {
+        self.ordered_iter_timed_from(0, period_ticks)
+    }
+
+    /// Same as [ordered_iter_timed()](Self::ordered_iter_timed), but with the first
+    /// observation at `start_ticks`. Timestamps wrap around like a tick counter would.
+    pub fn ordered_iter_timed_from(
+        &self,
+        start_ticks: u64,
+        period_ticks: u64,
+    ) -> impl ExactSizeIterator<Item = (u64, &T)> {
+        self.ordered_iter_enumerated()
+            .map(move |(outer_index, value)| {
+                let offset = (outer_index as u64).wrapping_mul(period_ticks);
+                (start_ticks.wrapping_add(offset), value)
+            })
+    }
+
+    /// Return an iterator over groups of `M` items in chronological order - *O(N)*.
+    /// The last group is shorter if `M` doesn't divide [len()](Self::len).
+    ///
+    /// Panics if `M` is zero.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 8>::new();
+    /// for i in 0..7 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let chunks: Vec<_> = reservoir.ordered_chunks::<3>().collect();
+    /// assert_eq!(chunks, [&[&0, &1, &2][..], &[&3, &4, &5], &[&6]]);
+    /// ```
+    pub fn ordered_chunks<const M: usize>(&self) -> impl ExactSizeIterator<Item = Vec<&T, M>> {
+        assert!(M > 0);
+        ReservoirOrderedChunks {
+            inner: self.ordered_iter(),
+        }
+    }
+
+    /// Return an iterator over the items in chronological order, each with the number
+    /// of observations it stands for - *O(N)*.
+    ///
+    /// The weight of an item is the number of observations from it up to the next stored
+    /// item, or up to the latest observation for the newest item, so the weights always
+    /// add up to [samples_seen()](Self::samples_seen).
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..7 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let weighted: Vec<_> = reservoir.ordered_iter_weighted().collect();
+    /// assert_eq!(weighted, [(&0, 2), (&2, 2), (&4, 2), (&6, 1)]);
+    /// ```
+    pub fn ordered_iter_weighted(&self) -> impl ExactSizeIterator<Item = (&T, usize)> {
+        let mut inner = ReservoirOrderedEnumeratedIter {
+            inner: self.ordered_indices(),
+            buf: self.as_unordered_slice(),
+        };
+        ReservoirOrderedWeighted {
+            next: inner.next(),
+            inner,
+            samples_seen: self.samples_seen(),
+        }
+    }
+
+    /// Return an iterator over consecutive pairs of items in chronological order, along
+    /// with the number of observations between them - *O(N)*.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..6 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let pairs: Vec<_> = reservoir.ordered_pairs().collect();
+    /// assert_eq!(pairs, [(&0, &2, 2), (&2, &3, 1), (&3, &4, 1)]);
+    /// ```
+    pub fn ordered_pairs(&self) -> impl ExactSizeIterator<Item = (&T, &T, usize)> {
+        let mut inner = ReservoirOrderedEnumeratedIter {
+            inner: self.ordered_indices(),
+            buf: self.as_unordered_slice(),
+        };
+        ReservoirOrderedPairs {
+            prev: inner.next(),
+            inner,
+        }
+    }
+
+    /// Return an iterator over the maximal contiguous runs of the internal storage,
+    /// in chronological order - *O(N)*.
+    ///
+    /// Concatenated, the runs yield the same items as [ordered_iter()](Self::ordered_iter).
+    ///
+    /// Chronologically adjacent items `d` observations apart are `d mod (N-1)` slots apart,
+    /// so they are only contiguous when `d` is a power of `N`. Thus, the number of runs
+    /// cycles with every `log2(N)` rate reductions:
+    /// * 1 while the buffer is filling up and right after the items become evenly spaced
+    ///   by a power of `N`, i.e. after _N<sup>k</sup>_ observations.
+    /// * While the smaller spacing is a power of `N`, the items spaced by it form runs
+    ///   broken only at the end of the storage, and the others are single-item runs.
+    /// * Otherwise, every item is a run of its own, i.e. up to `N` runs.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 8>::new();
+    /// for i in 0..9 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let runs: Vec<_> = reservoir.ordered_runs().collect();
+    /// assert_eq!(runs, [&[0][..], &[2, 3, 4, 5, 6, 7], &[8]]);
+    /// ```
+    pub fn ordered_runs(&self) -> impl Iterator<Item = &[T]> {
+        ReservoirOrderedRuns {
+            inner: self.ordered_indices(),
+            next: None,
+            buf: self.as_unordered_slice(),
         }
     }
 
     /// This is irreversible and consumes the reservoir.
     pub fn into_ordered_iter(self) -> impl Iterator<Item = T> {
-        OwningReservoirOrderedIter {
-            inner: ReservoirOrderedIndexIter {
-                pos: 0,
-                len: self.len(),
-                samples_seen: self.samples_seen(),
-                samples_stored: self.samples_stored(),
-            },
-            buf: self.buf,
+        let inner = self.ordered_indices();
+        OwningReservoirOrderedIter::new(self.buf, inner)
+    }
+
+    /// Consumes the reservoir and moves its items into `out` in chronological order - *O(N)*.
+    ///
+    /// The previous contents of `out` are dropped. Every item is moved exactly once,
+    /// straight from its slot to its place in `out`, which is cheaper than collecting
+    /// [into_ordered_iter()](Self::into_ordered_iter) for large `T`.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..16 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let mut out = infinity_sampler::heapless::Vec::<u32, 4>::new();
+    /// reservoir.drain_ordered_into(&mut out);
+    /// assert_eq!(out, [0, 4, 8, 12]);
+    /// ```
+    pub fn drain_ordered_into(self, out: &mut Vec<T, N>) {
+        out.clear();
+        let indices = self.ordered_indices();
+        let buf = ManuallyDrop::new(self.buf);
+        let src = buf.as_ptr();
+        indices.fold((), |(), idx| {
+            // SAFETY: every stored slot is yielded exactly once, so each item is read once,
+            // and the buffer is never dropped. `out` was cleared and holds at most N items
+            unsafe { out.push_unchecked(core::ptr::read(src.add(idx))) };
+        });
+    }
+
+    /// Consumes a full reservoir and returns its items in chronological order - *O(N)*.
+    ///
+    /// Returns the reservoir unchanged if it holds fewer than `N` items.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..3 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let mut reservoir = reservoir.into_ordered_array().unwrap_err();
+    /// for i in 3..8 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// assert_eq!(reservoir.into_ordered_array().ok(), Some([0, 2, 4, 6]));
+    /// ```
+    pub fn into_ordered_array(self) -> Result<[T; N], Self> {
+        if self.len() < N {
+            return Err(self);
         }
+        let mut iter = self.into_ordered_iter();
+        Ok(core::array::from_fn(|_| {
+            iter.next().expect("the reservoir is full")
+        }))
+    }
+
+    /// Checks that the counters, the rate divisor and the buffer length are consistent
+    /// with each other - *O(1)*.
+    ///
+    /// Always succeeds for a reservoir built through the public API. Meant for
+    /// assertions after restoring or generating a reservoir.
+    pub fn debug_validate(&self) -> Result<(), InvalidState> {
+        if self.is_saturated() || self.sample_rate.is_saturated() {
+            // The counts drift apart from the schedule once either of them stops
+            let samples_accepted = self.samples_accepted();
+            let reductions = rate_reductions_for::<N>(samples_accepted);
+            let divisor_log = self.sample_rate.divisor().trailing_zeros() as usize;
+            if self.len() != samples_accepted.min(N) || divisor_log > reductions {
+                return Err(InvalidState);
+            }
+            return Ok(());
+        }
+        let samples_accepted = samples_accepted_for::<N>(self.samples_seen());
+        if self.samples_accepted() != samples_accepted || self.len() != samples_accepted.min(N) {
+            return Err(InvalidState);
+        }
+        let reductions = rate_reductions_for::<N>(samples_accepted);
+        if reductions >= u64::BITS as usize || self.sample_rate.divisor() != 1 << reductions {
+            return Err(InvalidState);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn ordered_indices(&self) -> ReservoirOrderedIndexIter<N> {
+        ordered_indices_for::<N>(
+            self.samples_seen(),
+            self.samples_accepted(),
+            self.is_saturated() || self.sample_rate.is_saturated(),
+        )
     }
 
     /// Returns a reference to the current sampling rate.
-    pub fn sampling_rate(&self) -> &SamplingRate {
+    pub fn sampling_rate(&self) -> &R {
         &self.sample_rate
     }
 
-    /// Returns the total number of samples written into the buffer since the beginning.
+    /// Returns true once the sampling rate can't be reduced any further,
+    /// which for [SamplingRate] is at a divisor of 2<sup>31</sup>.
+    ///
+    /// From then on, the reservoir keeps sampling at that terminal rate and no longer
+    /// returns [ConsumedAndRateReduced](SamplingOutcome::ConsumedAndRateReduced). The
+    /// ordered iterators still yield the stored values in chronological order, but the
+    /// observation indexes they report follow the unsaturated schedule.
+    pub fn is_rate_saturated(&self) -> bool {
+        self.sample_rate.is_saturated()
+    }
+
+    pub(crate) fn sampling_rate_mut(&mut self) -> &mut R {
+        &mut self.sample_rate
+    }
+
+    /// Returns the total number of samples accepted into the buffer since the beginning,
+    /// including the ones that were overwritten since.
+    ///
+    /// This is not the number of items currently held, which is [len()](Self::len):
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..16 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// assert_eq!(reservoir.samples_seen(), 16);
+    /// // All of the first 4 values, then fewer and fewer as the rate halves
+    /// assert_eq!(reservoir.samples_accepted(), 8);
+    /// assert_eq!(reservoir.len(), 4);
+    /// ```
+    pub fn samples_accepted(&self) -> usize {
+        self.indexer.position()
+    }
+
+    /// Returns the same as [samples_accepted()](Self::samples_accepted), which is more than
+    /// the number of stored items once the buffer has been overwritten.
+    #[deprecated(
+        since = "0.3.0",
+        note = "use `samples_accepted()`, or `len()` for the number of stored items"
+    )]
     pub fn samples_stored(&self) -> usize {
-        self.inner_index
+        self.samples_accepted()
     }
 
-    /// Returns the total number of samples observed by the sampler since the beginning.
+    /// Returns the total number of samples observed by the sampler since the beginning,
+    /// up to [MAX_SAMPLES_SEEN](Self::MAX_SAMPLES_SEEN).
     pub fn samples_seen(&self) -> usize {
         self.outer_index
     }
 
-    pub(crate) fn storage_index_for_outer_index(outer_index: usize) -> usize {
+    /// Returns true once [samples_seen()](Self::samples_seen) has stopped at
+    /// [MAX_SAMPLES_SEEN](Self::MAX_SAMPLES_SEEN).
+    pub fn is_saturated(&self) -> bool {
+        self.outer_index == Self::MAX_SAMPLES_SEEN
+    }
+
+    /// Assembles a reservoir from its raw parts, without validating them.
+    #[cfg(test)]
+    pub(crate) fn from_parts(
+        buf: Vec<T, N>,
+        sample_rate: R,
+        samples_accepted: usize,
+        samples_seen: usize,
+    ) -> Self {
+        Self {
+            buf,
+            sample_rate,
+            indexer: InfinitySamplerIndexer::from_position(samples_accepted),
+            outer_index: samples_seen,
+        }
+    }
+
+    /// Adds `n` observations to the count, stopping at the ceiling.
+    #[inline(always)]
+    fn count_observations(&mut self, n: usize) {
+        // Can't overflow, both are at most usize::MAX / 2
+        self.outer_index = (self.outer_index + n).min(Self::MAX_SAMPLES_SEEN);
+    }
+
+    /// Returns the storage index at which the observation with the given (zero-based)
+    /// index is written, if it is sampled.
+    ///
+    /// This is the closed-form equivalent of the [InfinitySamplerIndexer] sequence,
+    /// keyed by the observation index instead of the number of stored values.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// type Reservoir = SamplingReservoir<u32, 8>;
+    ///
+    /// let indices: Vec<_> = (0..256)
+    ///     .filter(|i| Reservoir::should_sample(*i))
+    ///     .map(Reservoir::storage_index_for_outer_index)
+    ///     .collect();
+    /// assert_eq!(
+    ///     indices,
+    ///     [0, 1, 2, 3, 4, 5, 6, 7, 1, 3, 5, 7, 2, 6, 3, 7, 4, 5, 6, 7, 1, 3, 5, 7, 2, 6, 3, 7]
+    /// );
+    ///
+    /// const SLOT: usize = Reservoir::storage_index_for_outer_index(48);
+    /// assert_eq!(SLOT, 6);
+    /// ```
+    pub const fn storage_index_for_outer_index(outer_index: usize) -> usize {
         match outer_index {
             0 => 0,
             i => ((i - 1) % (N - 1)) + 1,
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn should_sample(outer_index: usize) -> bool {
-        let significant_bits = usize::BITS - outer_index.leading_zeros();
-        let counter_bits = significant_bits.saturating_sub(Self::LOG_N);
-        let mask = (1 << counter_bits) - 1;
-        outer_index & mask == 0
+    /// Returns true if the observation with the given (zero-based) index is stored
+    /// by the reservoir rather than discarded.
+    ///
+    /// Derived from the schedule [sample()](Self::sample) follows: the observation is sampled
+    /// if its index is a multiple of the divisor in effect, which has been doubled once for
+    /// every rate reduction so far. Equivalently, the most significant _log<sub>2</sub>N_ bits
+    /// of the index are dropped and the observation is sampled if the remaining bits are all zero.
+    ///
+    /// Describes the schedule before the rate [saturates](Self::is_rate_saturated).
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// type Reservoir = SamplingReservoir<u32, 16>;
+    ///
+    /// let sampled: Vec<_> = (0..128).filter(|i| Reservoir::should_sample(*i)).collect();
+    /// assert_eq!(
+    ///     sampled,
+    ///     [
+    ///         0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 18, 20, 22, 24, 26, 28,
+    ///         30, 32, 36, 40, 44, 48, 52, 56, 60, 64, 72, 80, 88, 96, 104, 112, 120
+    ///     ]
+    /// );
+    ///
+    /// const SAMPLED: bool = Reservoir::should_sample(130);
+    /// assert!(!SAMPLED);
+    /// ```
+    pub const fn should_sample(outer_index: usize) -> bool {
+        let reductions = rate_reductions_for::<N>(samples_accepted_for::<N>(outer_index));
+        outer_index & ((1 << reductions) - 1) == 0
     }
 
     /// Unconditionally stores a value in the reservoir.
-    pub(crate) fn write_at_outer_index(&mut self, outer_index: usize, value: T) {
-        let insert_index = Self::storage_index_for_outer_index(outer_index);
-
-        let buf = unsafe { self.buf.as_mut().unwrap_unchecked() };
-        if insert_index == buf.len() {
-            let _ = buf.push(value);
+    ///
+    /// Branches on the length rather than on the index, so that a value can't be dropped:
+    /// it's either appended while there's room, or overwrites a slot of the full buffer.
+    pub(crate) fn write_at_index(&mut self, insert_index: usize, value: T) {
+        if self.buf.len() < N {
+            // The indexer yields every index below N once before revisiting any
+            debug_assert_eq!(insert_index, self.buf.len());
+            #[cfg(not(feature = "strict"))]
+            // SAFETY: checked above that the buffer isn't full
+            unsafe {
+                self.buf.push_unchecked(value)
+            };
+            #[cfg(feature = "strict")]
+            if self.buf.push(value).is_err() {
+                unreachable!();
+            }
         } else {
-            buf[insert_index] = value;
+            self.buf[insert_index] = value;
+        }
+    }
+
+    /// Checks that the counters agree with each other and with the buffer length, and that
+    /// the divisor is the power of two the number of stored values leads to - *O(1)*.
+    ///
+    /// Unlike [debug_validate()](Self::debug_validate), doesn't tie the counters to the
+    /// exact schedule, only checks what the writes rely on.
+    #[cfg(feature = "strict")]
+    fn check_invariants(&self) -> Result<(), InvariantViolation> {
+        let samples_accepted = self.samples_accepted();
+        if samples_accepted > self.samples_seen() || self.len() != samples_accepted.min(N) {
+            return Err(InvariantViolation);
         }
+        let divisor = self.sample_rate.divisor();
+        let divisor_log = divisor.trailing_zeros() as usize;
+        let reductions = rate_reductions_for::<N>(samples_accepted);
+        if !divisor.is_power_of_two()
+            || divisor_log > reductions
+            || (divisor_log < reductions && !self.sample_rate.is_saturated())
+        {
+            return Err(InvariantViolation);
+        }
+        Ok(())
+    }
+
+    /// Returns the occupied slot at `index` of the internal storage.
+    #[cfg(feature = "pool")]
+    pub(crate) fn slot_mut(&mut self, index: usize) -> &mut T {
+        &mut self.buf[index]
     }
 
     /// Observe a value and possibly store it - *O(1)*.
     ///
     /// Performs a sampling "step", consuming the value and storing it into the buffer,
     /// or returning it back if it's discarded due to the sampling rate.
-    #[inline(never)]
+    ///
+    /// Only the rate check is inlined into the caller, storing an accepted value is
+    /// an out-of-line call. At high divisors nearly every observation is a discard,
+    /// which then costs no call at all (see `benches/sample.rs`).
+    ///
+    /// With the `strict` feature, the state is checked before every write and a violation
+    /// panics, see [sample_checked()](Self::sample_checked).
+    #[inline]
+    #[must_use]
     pub fn sample(&mut self, value: T) -> SamplingOutcome<T> {
-        self.outer_index += 1;
+        self.count_observations(1);
         if !self.sample_rate.step() {
             return SamplingOutcome::Discarded(value);
         }
+        self.store_accepted(value)
+    }
+
+    /// Same as [sample()](Self::sample), but returns [InvariantViolation] instead of
+    /// panicking if the insertion index, the divisor or the counters are inconsistent.
+    ///
+    /// A reservoir only gets there through a bug or memory corruption, in which case
+    /// the offered value is dropped.
+    #[cfg(feature = "strict")]
+    pub fn sample_checked(&mut self, value: T) -> Result<SamplingOutcome<T>, InvariantViolation> {
+        self.count_observations(1);
+        if !self.sample_rate.step() {
+            return Ok(SamplingOutcome::Discarded(value));
+        }
+        self.try_store_accepted(value)
+    }
+
+    /// The slow path of [sample()](Self::sample), after the rate has accepted the value.
+    #[inline(never)]
+    fn store_accepted(&mut self, value: T) -> SamplingOutcome<T> {
+        #[cfg(feature = "strict")]
+        match self.try_store_accepted(value) {
+            Ok(outcome) => outcome,
+            Err(violation) => panic!("{}", violation),
+        }
+        #[cfg(not(feature = "strict"))]
+        {
+            let (insert_index, result) = self.accept();
+            self.write_at_index(insert_index, value);
+            result
+        }
+    }
+
+    #[cfg(feature = "strict")]
+    fn try_store_accepted(&mut self, value: T) -> Result<SamplingOutcome<T>, InvariantViolation> {
+        let (insert_index, result) = self.accept();
+        // The next free slot while filling up, any slot afterwards
+        if insert_index >= N || (self.buf.len() < N && insert_index != self.buf.len()) {
+            return Err(InvariantViolation);
+        }
+        self.write_at_index(insert_index, value);
+        self.check_invariants()?;
+        Ok(result)
+    }
+
+    /// Counts an observation without its value. If it's accepted, returns the storage index
+    /// the caller must write it at, and the outcome.
+    #[cfg(feature = "pool")]
+    #[inline(always)]
+    pub(crate) fn observe<U>(&mut self) -> Option<(usize, SamplingOutcome<U>)> {
+        self.count_observations(1);
+        if !self.sample_rate.step() {
+            return None;
+        }
+        Some(self.accept())
+    }
+
+    /// Reserves the storage index for an observation the rate has accepted,
+    /// reducing the rate if the reservoir has wrapped around.
+    #[inline(always)]
+    fn accept<U>(&mut self) -> (usize, SamplingOutcome<U>) {
         let mut result = SamplingOutcome::Consumed;
 
-        if self.inner_index >= N && (self.inner_index - N) & Self::WRAPAROUND_MASK == 0 {
-            self.sample_rate.div(2);
-            result = SamplingOutcome::ConsumedAndRateReduced { factor: 2 };
+        if reduces_rate_at(N, self.indexer.position()) && !self.sample_rate.is_saturated() {
+            let old_divisor = self.sample_rate.divisor();
+            self.sample_rate.halve();
+            result = SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor: self.sample_rate.divisor(),
+            };
+        }
+        (self.indexer.next_index(), result)
+    }
+
+    /// Observe a value and pass it to [sample()](Self::sample) only if `rate` accepts it.
+    ///
+    /// Values rejected by the timed rate are not counted as observed,
+    /// so the reservoir's own schedule applies on top of the time-thinned stream.
+    #[must_use]
+    pub fn sample_timed<C: Now>(
+        &mut self,
+        value: T,
+        rate: &mut TimedSamplingRate<C>,
+    ) -> SamplingOutcome<T> {
+        if !rate.step() {
+            return SamplingOutcome::Discarded(value);
         }
-        self.inner_index += 1;
-        self.write_at_outer_index(self.outer_index - 1, value);
-        result
+        self.sample(value)
     }
 }
 
-struct ReservoirOrderedIndexIter<const N: usize> {
-    pos: usize,
-    len: usize,
-    samples_stored: usize,
-    samples_seen: usize,
+impl<T, const N: usize> SamplingReservoir<T, N> {
+    /// Returns how many observations will be discarded before the next accepted one - *O(1)*.
+    ///
+    /// If the next accepted observation reduces the rate, the reduction only applies to
+    /// the observations after it, so the gap after that one is twice as long:
+    ///
+    /// ```
+    /// use infinity_sampler::{SamplingOutcome, SamplingReservoir};
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..4 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// assert_eq!(reservoir.next_accepted_in(), 0);
+    /// assert!(matches!(
+    ///     reservoir.sample(4),
+    ///     SamplingOutcome::ConsumedAndRateReduced { .. }
+    /// ));
+    /// assert_eq!(reservoir.next_accepted_in(), 1);
+    /// ```
+    pub fn next_accepted_in(&self) -> usize {
+        self.sample_rate.samples_until_next() as usize
+    }
+
+    /// Counts all the observations that would be discarded before the next accepted one
+    /// without passing their values - *O(1)*, so that the next [sample()](Self::sample)
+    /// call stores its value.
+    ///
+    /// Returns the number of skipped observations, the same as [next_accepted_in()](Self::next_accepted_in).
+    ///
+    /// ```
+    /// use infinity_sampler::{ReservoirSampleExt, SamplingReservoir};
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// let mut i = 0;
+    /// while i < 1000 {
+    ///     i += reservoir.skip_discarded() as u32;
+    ///     let _ = reservoir.sample(i);
+    ///     i += 1;
+    /// }
+    /// assert_eq!(reservoir.samples_seen(), i as usize);
+    /// assert!(reservoir.ordered_iter().eq((0..i).sample_reservoir::<4>().ordered_iter()));
+    /// ```
+    pub fn skip_discarded(&mut self) -> usize {
+        let skipped = self.sample_rate.advance_to_next_sample() as usize;
+        self.count_observations(skipped);
+        skipped
+    }
+
+    /// Observes a block of values - *O(accepted values)*.
+    ///
+    /// Same result as calling [sample()](Self::sample) on every value, but jumps straight
+    /// to the accepted offsets within the block and only clones those.
+    ///
+    /// ```
+    /// use infinity_sampler::{ReservoirSampleExt, SamplingReservoir};
+    ///
+    /// let block: Vec<u32> = (0..4096).collect();
+    /// let mut reservoir = SamplingReservoir::<u32, 8>::new();
+    /// let stats = reservoir.sample_slice(&block);
+    ///
+    /// let mut naive = SamplingReservoir::<u32, 8>::new();
+    /// assert_eq!(block.iter().copied().sample_into(&mut naive), stats);
+    /// assert_eq!(reservoir.as_unordered_slice(), naive.as_unordered_slice());
+    /// ```
+    pub fn sample_slice(&mut self, block: &[T]) -> SampleStats
+    where
+        T: Clone,
+    {
+        let mut stats = SampleStats {
+            seen: block.len(),
+            ..Default::default()
+        };
+        let mut offset = 0;
+        loop {
+            let left = block.len() - offset;
+            let skip = self.next_accepted_in();
+            if skip >= left {
+                // The rest of the block is discarded
+                let discarded = self.sample_rate.step_n(left as u32);
+                debug_assert_eq!(discarded, 0);
+                self.count_observations(left);
+                return stats;
+            }
+            offset += self.skip_discarded();
+            match self.sample(block[offset].clone()) {
+                SamplingOutcome::Consumed => stats.stored += 1,
+                SamplingOutcome::ConsumedAndRateReduced { .. } => {
+                    stats.stored += 1;
+                    stats.rate_reductions += 1;
+                }
+                SamplingOutcome::Discarded(_) => unreachable!(),
+            }
+            offset += 1;
+        }
+    }
+
+    /// Rebuilds a reservoir from the number of observed values and the stored values
+    /// in chronological order, as yielded by [into_ordered_iter()](Self::into_ordered_iter) - *O(N)*.
+    ///
+    /// The result continues sampling exactly like the original reservoir would.
+    /// Returns [InvalidState] if the number of values doesn't match `samples_seen`.
+    ///
+    /// At [MAX_SAMPLES_SEEN](Self::MAX_SAMPLES_SEEN), which doesn't tell how many values
+    /// were accepted, the fewest possible are assumed.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::from_ordered(8, [0, 2, 4, 6]).unwrap();
+    /// for i in 8..16 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// assert_eq!(reservoir.into_ordered_iter().collect::<Vec<_>>(), [0, 4, 8, 12]);
+    ///
+    /// assert!(SamplingReservoir::<u32, 4>::from_ordered(8, [0, 2]).is_err());
+    /// ```
+    pub fn from_ordered(
+        samples_seen: usize,
+        values: impl IntoIterator<Item = T>,
+    ) -> Result<Self, InvalidState> {
+        let samples_accepted = samples_accepted_saturating::<N>(samples_seen);
+        let len = samples_accepted.min(N);
+
+        let saturated = samples_seen == Self::MAX_SAMPLES_SEEN
+            || rate_reductions_for::<N>(samples_accepted) >= TERMINAL_REDUCTIONS;
+        let mut slots: [Option<T>; N] = core::array::from_fn(|_| None);
+        let mut indices = ordered_indices_for::<N>(samples_seen, samples_accepted, saturated);
+        for value in values {
+            let idx = indices.next().ok_or(InvalidState)?;
+            slots[idx] = Some(value);
+        }
+        if indices.len() > 0 {
+            return Err(InvalidState);
+        }
+        // The occupied slots are always the first `len` ones
+        let buf = IntoIterator::into_iter(slots).take(len).flatten().collect();
+        Self::from_unordered(samples_seen, samples_accepted, buf)
+    }
+
+    /// Same as [from_ordered()](Self::from_ordered), but takes the values in storage order,
+    /// as returned by [as_unordered_slice()](Self::as_unordered_slice), and the number of
+    /// accepted values, which only `samples_seen` pins down below
+    /// [MAX_SAMPLES_SEEN](Self::MAX_SAMPLES_SEEN).
+    ///
+    /// At the ceiling, the phase of the rate is left at zero for the caller to restore.
+    pub(crate) fn from_unordered(
+        samples_seen: usize,
+        samples_accepted: usize,
+        buf: Vec<T, N>,
+    ) -> Result<Self, InvalidState> {
+        let expected = samples_accepted_saturating::<N>(samples_seen);
+        let at_ceiling = samples_seen == Self::MAX_SAMPLES_SEEN;
+        // Values are still accepted after the observed count stops
+        if samples_seen > Self::MAX_SAMPLES_SEEN
+            || (at_ceiling && samples_accepted < expected)
+            || (!at_ceiling && samples_accepted != expected)
+            || buf.len() != samples_accepted.min(N)
+        {
+            return Err(InvalidState);
+        }
+
+        // The accepted observation indexes are multiples of the divisor since the last
+        // reduction, which stops at the terminal one
+        let reductions = rate_reductions_for::<N>(samples_accepted);
+        let divisor = 1u32 << reductions.min(TERMINAL_REDUCTIONS);
+        let mut sample_rate = SamplingRate::new(divisor);
+        if !at_ceiling {
+            sample_rate.set_phase((samples_seen.saturating_sub(1) % divisor as usize) as u32);
+        }
+
+        Ok(Self {
+            buf,
+            sample_rate,
+            indexer: InfinitySamplerIndexer::from_position(samples_accepted),
+            outer_index: samples_seen,
+        })
+    }
 }
 
-impl<const N: usize> ExactSizeIterator for ReservoirOrderedIndexIter<N> {}
+// Not derived, to pin down what a panicking `T::clone` leaves behind
+// rather than rely on the buffer's implementation
+impl<T: Clone, const N: usize, R: Clone> Clone for SamplingReservoir<T, N, R> {
+    fn clone(&self) -> Self {
+        // Acts as the guard: it only owns the values cloned so far,
+        // so a panic drops exactly those and leaves `self` untouched
+        let mut buf = Vec::new();
+        for value in &self.buf {
+            // SAFETY: `buf` holds fewer values than `self.buf`, which holds at most N
+            unsafe { buf.push_unchecked(value.clone()) };
+        }
+        Self {
+            buf,
+            sample_rate: self.sample_rate.clone(),
+            indexer: self.indexer.clone(),
+            outer_index: self.outer_index,
+        }
+    }
+}
 
-impl<const N: usize> Iterator for ReservoirOrderedIndexIter<N> {
-    type Item = usize;
+impl<T, const N: usize, R: ReservoirRate> Default for SamplingReservoir<T, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos == self.len {
-            return None;
+/// Only generates internally consistent states, which pass
+/// [debug_validate()](SamplingReservoir::debug_validate).
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>, const N: usize> arbitrary::Arbitrary<'a>
+    for SamplingReservoir<T, N>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        // A number of observed values, then the stored values for it
+        let samples_seen = u.int_in_range(0..=u32::MAX as usize)?;
+        let samples_accepted = samples_accepted_saturating::<N>(samples_seen);
+        let mut buf = Vec::new();
+        for _ in 0..samples_accepted.min(N) {
+            let _ = buf.push(T::arbitrary(u)?);
+        }
+        Self::from_unordered(samples_seen, samples_accepted, buf)
+            .map_err(|_| arbitrary::Error::IncorrectFormat)
+    }
+}
+
+/// Samples every item into a new reservoir, same as
+/// [sample_reservoir()](crate::ReservoirSampleExt::sample_reservoir).
+impl<T, const N: usize, R: ReservoirRate> FromIterator<T> for SamplingReservoir<T, N, R> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut reservoir = Self::new();
+        for value in iter {
+            let _ = reservoir.sample(value);
         }
+        reservoir
+    }
+}
+
+/// Logs a summary: the counters, the divisor and the oldest and newest values.
+#[cfg(feature = "defmt")]
+impl<T: defmt::Format, const N: usize, R: ReservoirRate> defmt::Format
+    for SamplingReservoir<T, N, R>
+{
+    fn format(&self, f: defmt::Formatter) {
+        let mut iter = self.ordered_iter();
+        defmt::write!(
+            f,
+            "SamplingReservoir {{ len: {=usize}, samples_seen: {=usize}, samples_accepted: {=usize}, divisor: {=u64}, first: {}, last: {} }}",
+            self.len(),
+            self.samples_seen(),
+            self.samples_accepted(),
+            self.sample_rate.divisor(),
+            iter.next(),
+            iter.next_back(),
+        );
+    }
+}
 
-        if self.samples_seen < N {
-            self.pos += 1;
-            return Some(self.pos - 1);
+/// Number of halvings after which a [SamplingRate] stops at its terminal divisor.
+const TERMINAL_REDUCTIONS: usize = u32::BITS as usize - 1;
+
+/// Returns the storage slots in chronological order for the given counts, where `saturated`
+/// tells whether either the observed count or the rate has stopped.
+fn ordered_indices_for<const N: usize>(
+    samples_seen: usize,
+    samples_accepted: usize,
+    saturated: bool,
+) -> ReservoirOrderedIndexIter<N> {
+    let (samples_seen, samples_accepted) = if saturated {
+        saturated_order_counts::<N>(samples_accepted)
+    } else {
+        (samples_seen, samples_accepted)
+    };
+    ReservoirOrderedIndexIter::new(samples_seen, samples_accepted)
+}
+
+/// Returns the smallest consistent pair of observed and stored counts
+/// with the same order as `samples_accepted`.
+///
+/// The order only depends on the stored count, which past saturation
+/// no longer matches the observed count.
+#[cold]
+#[inline(never)]
+fn saturated_order_counts<const N: usize>(samples_accepted: usize) -> (usize, usize) {
+    let samples_accepted = order_equivalent_stored::<N>(samples_accepted);
+    (
+        observations_for_stored::<N>(samples_accepted),
+        samples_accepted,
+    )
+}
+
+/// The state of a [ReservoirOrderedIndexIter], with the capacity passed in as `n`,
+/// so that its methods are shared by all capacities with the `small-code` feature.
+#[derive(Clone)]
+pub(crate) struct OrderedIndexCursor {
+    pos: usize,
+    end: usize,
+    // The items are split into three regimes: positions before `leading_end` and from
+    // `trailing_start` on are spaced by `step_upper`, the ones in between by `step_lower`
+    leading_end: usize,
+    trailing_start: usize,
+    step_lower: usize,
+    step_upper: usize,
+    /// Observation index of the items at `leading_end` and `trailing_start`
+    middle_base: usize,
+    trailing_base: usize,
+}
+
+impl OrderedIndexCursor {
+    #[cfg_attr(feature = "small-code", inline(never))]
+    fn new(n: usize, samples_seen: usize, samples_accepted: usize) -> Self {
+        let end = samples_accepted.min(n);
+        if samples_seen < n {
+            // Nothing was discarded yet, a single regime with a step of 1
+            return Self {
+                pos: 0,
+                end,
+                leading_end: 0,
+                trailing_start: n,
+                step_lower: 1,
+                step_upper: 1,
+                middle_base: 0,
+                trailing_base: n,
+            };
         }
 
-        let log = usize::BITS - ((self.samples_seen - 1) / (N - 1)).leading_zeros() - 1;
+        let log = usize::BITS - ((samples_seen - 1) / (n - 1)).leading_zeros() - 1;
         let step_lower = 1 << log;
         let step_upper = step_lower << 1;
+        let n_upper_steps = samples_accepted % (n / 2);
+        let middle_base = n_upper_steps * step_upper;
+        Self {
+            pos: 0,
+            end,
+            leading_end: n_upper_steps,
+            trailing_start: n - n_upper_steps,
+            step_lower,
+            step_upper,
+            middle_base,
+            trailing_base: middle_base + (n - n_upper_steps * 2) * step_lower,
+        }
+    }
 
-        let n_upper_steps = self.samples_stored % (N / 2);
+    /// Returns the observation index of the item at the chronological position `pos`.
+    fn outer_index_at(&self, pos: usize) -> usize {
+        if pos < self.leading_end {
+            pos * self.step_upper
+        } else if pos < self.trailing_start {
+            self.middle_base + (pos - self.leading_end) * self.step_lower
+        } else {
+            self.trailing_base + (pos - self.trailing_start) * self.step_upper
+        }
+    }
 
-        let outer_index = if self.pos < n_upper_steps {
-            self.pos * step_upper
-        } else if self.pos < N - n_upper_steps {
-            n_upper_steps * step_upper + (self.pos - n_upper_steps) * step_lower
+    /// Inverse of [outer_index_at()](Self::outer_index_at) for the outer index
+    /// of an item that is currently stored.
+    fn position_of_outer_index(&self, outer_index: usize) -> usize {
+        if outer_index < self.middle_base {
+            outer_index / self.step_upper
+        } else if outer_index < self.trailing_base {
+            self.leading_end + (outer_index - self.middle_base) / self.step_lower
         } else {
-            n_upper_steps * step_upper
-                + (N - n_upper_steps * 2) * step_lower
-                + (self.pos - (N - n_upper_steps)) * step_upper
+            self.trailing_start + (outer_index - self.trailing_base) / self.step_upper
+        }
+    }
+
+    /// Returns the observation index and storage index pair at `pos`.
+    fn enumerated_at(&self, n: usize, pos: usize) -> (usize, usize) {
+        let outer_index = self.outer_index_at(pos);
+        let idx = match outer_index {
+            0 => 0,
+            i => ((i - 1) % (n - 1)) + 1,
         };
-        let idx = SamplingReservoir::<(), N>::storage_index_for_outer_index(outer_index);
+        (outer_index, idx)
+    }
+
+    /// Returns the next observation index and storage index pair without advancing.
+    #[cfg_attr(feature = "small-code", inline(never))]
+    #[cfg_attr(not(feature = "small-code"), inline)]
+    fn peek_enumerated(&self, n: usize) -> Option<(usize, usize)> {
+        if self.pos == self.end {
+            return None;
+        }
+        Some(self.enumerated_at(n, self.pos))
+    }
+
+    /// Returns the next observation index and storage index pair.
+    #[cfg_attr(feature = "small-code", inline(never))]
+    #[cfg_attr(not(feature = "small-code"), inline)]
+    fn next_enumerated(&mut self, n: usize) -> Option<(usize, usize)> {
+        if self.pos == self.end {
+            return None;
+        }
         self.pos += 1;
+        Some(self.enumerated_at(n, self.pos - 1))
+    }
+
+    /// Folds the remaining observation index and storage index pairs - *O(1)* per item.
+    ///
+    /// Walks the three spacing regimes with running indexes instead of
+    /// recomputing them for every position.
+    #[inline]
+    fn fold_enumerated<B, F: FnMut(B, (usize, usize)) -> B>(
+        self,
+        n: usize,
+        init: B,
+        mut f: F,
+    ) -> B {
+        let mut acc = init;
+        if self.step_upper == 1 {
+            for pos in self.pos..self.end {
+                acc = f(acc, (pos, pos));
+            }
+            return acc;
+        }
+
+        let mut pos = self.pos;
+        let mut outer_index = self.outer_index_at(pos);
+        // (outer_index - 1) mod (n - 1), so that the storage index is `residue + 1`
+        if outer_index == 0 && pos < self.end {
+            // The only item not following `residue + 1`
+            acc = f(acc, (0, 0));
+            pos += 1;
+            outer_index = self.outer_index_at(pos);
+        }
+        let mut residue = (outer_index + n - 2) % (n - 1);
+        for (regime_end, step) in [
+            (self.leading_end, self.step_upper),
+            (self.trailing_start, self.step_lower),
+            (n, self.step_upper),
+        ] {
+            let step_residue = step % (n - 1);
+            let regime_end = regime_end.min(self.end);
+            while pos < regime_end {
+                acc = f(acc, (outer_index, residue + 1));
+                outer_index += step;
+                residue += step_residue;
+                if residue >= n - 1 {
+                    residue -= n - 1;
+                }
+                pos += 1;
+            }
+        }
+        acc
+    }
+
+    /// Returns the next observation index and storage index pair from the back.
+    #[cfg_attr(feature = "small-code", inline(never))]
+    #[cfg_attr(not(feature = "small-code"), inline)]
+    fn next_back_enumerated(&mut self, n: usize) -> Option<(usize, usize)> {
+        if self.pos == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(self.enumerated_at(n, self.end))
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ReservoirOrderedIndexIter<const N: usize> {
+    cursor: OrderedIndexCursor,
+}
+
+impl<const N: usize> ReservoirOrderedIndexIter<N> {
+    pub(crate) fn new(samples_seen: usize, samples_accepted: usize) -> Self {
+        Self {
+            cursor: OrderedIndexCursor::new(N, samples_seen, samples_accepted),
+        }
+    }
+
+    /// Returns the observation index of the item at the chronological position `pos`.
+    pub(crate) fn outer_index_at(&self, pos: usize) -> usize {
+        self.cursor.outer_index_at(pos)
+    }
+
+    /// Inverse of [outer_index_at()](Self::outer_index_at) for the outer index
+    /// of an item that is currently stored.
+    pub(crate) fn position_of_outer_index(&self, outer_index: usize) -> usize {
+        self.cursor.position_of_outer_index(outer_index)
+    }
+
+    /// Returns the next observation index and storage index pair without advancing.
+    fn peek_enumerated(&self) -> Option<(usize, usize)> {
+        self.cursor.peek_enumerated(N)
+    }
+
+    /// Returns the next observation index and storage index pair.
+    fn next_enumerated(&mut self) -> Option<(usize, usize)> {
+        self.cursor.next_enumerated(N)
+    }
 
-        Some(idx)
+    /// Folds the remaining observation index and storage index pairs - *O(1)* per item.
+    fn fold_enumerated<B, F: FnMut(B, (usize, usize)) -> B>(self, init: B, f: F) -> B {
+        self.cursor.fold_enumerated(N, init, f)
+    }
+
+    /// Returns the next observation index and storage index pair from the back.
+    fn next_back_enumerated(&mut self) -> Option<(usize, usize)> {
+        self.cursor.next_back_enumerated(N)
+    }
+
+    fn remaining(&self) -> usize {
+        self.cursor.end - self.cursor.pos
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for ReservoirOrderedIndexIter<N> {}
+
+impl<const N: usize> FusedIterator for ReservoirOrderedIndexIter<N> {}
+
+impl<const N: usize> Iterator for ReservoirOrderedIndexIter<N> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_enumerated().map(|(_, idx)| idx)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len - self.pos, Some(self.len - self.pos))
+        (self.remaining(), Some(self.remaining()))
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.cursor.pos += n.min(self.remaining());
+        self.next()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
+
+    fn fold<B, F: FnMut(B, Self::Item) -> B>(self, init: B, mut f: F) -> B {
+        self.fold_enumerated(init, |acc, (_, idx)| f(acc, idx))
     }
 }
 
-struct ReservoirOrderedIter2<'a, T, const N: usize> {
-    buf: &'a SamplingReservoir<T, N>,
+impl<const N: usize> DoubleEndedIterator for ReservoirOrderedIndexIter<N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.next_back_enumerated().map(|(_, idx)| idx)
+    }
+}
+
+/// Iterator over the items of a [SamplingReservoir] in chronological order,
+/// returned by [ordered_iter()](SamplingReservoir::ordered_iter).
+///
+/// Iterating from the back yields the newest items first.
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// for i in 0..16 {
+///     let _ = reservoir.sample(i);
+/// }
+/// let mut iter = reservoir.ordered_iter();
+/// assert_eq!(iter.next_back(), Some(&12));
+/// assert_eq!(iter.next(), Some(&0));
+/// assert_eq!(iter.rev().collect::<Vec<_>>(), [&8, &4]);
+/// ```
+pub struct ReservoirOrderedIter<'a, T, const N: usize> {
+    buf: &'a [T],
     inner: ReservoirOrderedIndexIter<N>,
 }
 
-impl<T, const N: usize> ExactSizeIterator for ReservoirOrderedIter2<'_, T, N> {}
+// Not derived, as that would require T: Clone
+impl<T, const N: usize> Clone for ReservoirOrderedIter<'_, T, N> {
+    fn clone(&self) -> Self {
+        Self {
+            buf: self.buf,
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for ReservoirOrderedIter<'_, T, N> {}
+
+impl<T, const N: usize> FusedIterator for ReservoirOrderedIter<'_, T, N> {}
 
-impl<'a, T, const N: usize> Iterator for ReservoirOrderedIter2<'a, T, N> {
+impl<'a, T, const N: usize> Iterator for ReservoirOrderedIter<'a, T, N> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<Self::Item> {
         let idx = self.inner.next()?;
-        Some(&self.buf.as_unordered_slice()[idx])
+        Some(&self.buf[idx])
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let idx = self.inner.nth(n)?;
+        Some(&self.buf[idx])
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
+
+    fn fold<B, F: FnMut(B, Self::Item) -> B>(self, init: B, mut f: F) -> B {
+        let buf = self.buf;
+        self.inner.fold(init, |acc, idx| f(acc, &buf[idx]))
+    }
 }
 
-struct OwningReservoirOrderedIter<T, const N: usize> {
-    buf: Option<Vec<T, N>>,
+impl<T, const N: usize> DoubleEndedIterator for ReservoirOrderedIter<'_, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let idx = self.inner.next_back()?;
+        Some(&self.buf[idx])
+    }
+}
+
+struct ReservoirOrderedChunks<'a, T, const N: usize, const M: usize> {
+    inner: ReservoirOrderedIter<'a, T, N>,
+}
+
+impl<T, const N: usize, const M: usize> ExactSizeIterator for ReservoirOrderedChunks<'_, T, N, M> {}
+
+impl<'a, T, const N: usize, const M: usize> Iterator for ReservoirOrderedChunks<'a, T, N, M> {
+    type Item = Vec<&'a T, M>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.inner.len() == 0 {
+            return None;
+        }
+        Some(self.inner.by_ref().take(M).collect())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.inner.len().div_ceil(M);
+        (len, Some(len))
+    }
+}
+
+struct ReservoirOrderedEnumeratedIter<'a, T, const N: usize> {
+    buf: &'a [T],
     inner: ReservoirOrderedIndexIter<N>,
 }
 
-impl<T, const N: usize> ExactSizeIterator for OwningReservoirOrderedIter<T, N> {}
+impl<T, const N: usize> ExactSizeIterator for ReservoirOrderedEnumeratedIter<'_, T, N> {}
+
+impl<T, const N: usize> FusedIterator for ReservoirOrderedEnumeratedIter<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for ReservoirOrderedEnumeratedIter<'a, T, N> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (outer_index, idx) = self.inner.next_enumerated()?;
+        Some((outer_index, &self.buf[idx]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+
+    fn fold<B, F: FnMut(B, Self::Item) -> B>(self, init: B, mut f: F) -> B {
+        let buf = self.buf;
+        self.inner.fold_enumerated(init, |acc, (outer_index, idx)| {
+            f(acc, (outer_index, &buf[idx]))
+        })
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for ReservoirOrderedEnumeratedIter<'_, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (outer_index, idx) = self.inner.next_back_enumerated()?;
+        Some((outer_index, &self.buf[idx]))
+    }
+}
+
+struct ReservoirOrderedPairs<'a, T, const N: usize> {
+    prev: Option<(usize, &'a T)>,
+    inner: ReservoirOrderedEnumeratedIter<'a, T, N>,
+}
+
+impl<T, const N: usize> ExactSizeIterator for ReservoirOrderedPairs<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for ReservoirOrderedPairs<'a, T, N> {
+    type Item = (&'a T, &'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (outer_index, value) = self.inner.next()?;
+        let (prev_outer_index, prev) = self.prev.replace((outer_index, value))?;
+        Some((prev, value, outer_index - prev_outer_index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+struct ReservoirOrderedRuns<'a, T, const N: usize> {
+    inner: ReservoirOrderedIndexIter<N>,
+    /// The storage index following the last run, if already taken from `inner`.
+    next: Option<usize>,
+    buf: &'a [T],
+}
+
+impl<T, const N: usize> FusedIterator for ReservoirOrderedRuns<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for ReservoirOrderedRuns<'a, T, N> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.next.take().or_else(|| self.inner.next())?;
+        let mut end = start + 1;
+        for idx in self.inner.by_ref() {
+            if idx != end {
+                self.next = Some(idx);
+                break;
+            }
+            end += 1;
+        }
+        Some(&self.buf[start..end])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.inner.len() + self.next.is_some() as usize;
+        (left.min(1), Some(left))
+    }
+}
+
+struct ReservoirOrderedWeighted<'a, T, const N: usize> {
+    next: Option<(usize, &'a T)>,
+    inner: ReservoirOrderedEnumeratedIter<'a, T, N>,
+    samples_seen: usize,
+}
+
+impl<T, const N: usize> ExactSizeIterator for ReservoirOrderedWeighted<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for ReservoirOrderedWeighted<'a, T, N> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (outer_index, value) = self.next.take()?;
+        self.next = self.inner.next();
+        let end = self.next.map_or(self.samples_seen, |(next, _)| next);
+        Some((value, end - outer_index))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.inner.len() + self.next.is_some() as usize;
+        (len, Some(len))
+    }
+}
+
+/// Iterator over the items of a [SamplingReservoir] in chronological order together with
+/// their observation indexes, returned by [ordered_peekable()](SamplingReservoir::ordered_peekable).
+///
+/// Useful for merging the samples with other time-ordered data:
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+///
+/// let mut reservoir = SamplingReservoir::<char, 4>::new();
+/// for c in 'a'..='h' {
+///     let _ = reservoir.sample(c);
+/// }
+///
+/// // Take the items observed before some point in time
+/// let mut iter = reservoir.ordered_peekable();
+/// let mut early = Vec::new();
+/// while let Some((_, c)) = iter.next_if(|i, _| i < 4) {
+///     early.push(*c);
+/// }
+/// assert_eq!(early, ['a', 'c']);
+/// assert_eq!(iter.peek(), Some((4, &'e')));
+/// ```
+pub struct OrderedPeekable<'a, T, const N: usize> {
+    inner: ReservoirOrderedEnumeratedIter<'a, T, N>,
+}
+
+impl<'a, T, const N: usize> OrderedPeekable<'a, T, N> {
+    /// Returns the next observation index and item without consuming them.
+    pub fn peek(&self) -> Option<(usize, &'a T)> {
+        let (outer_index, idx) = self.inner.inner.peek_enumerated()?;
+        Some((outer_index, &self.inner.buf[idx]))
+    }
+
+    /// Consumes and returns the next observation index and item if `f` returns true for them.
+    pub fn next_if(&mut self, f: impl FnOnce(usize, &T) -> bool) -> Option<(usize, &'a T)> {
+        let (outer_index, value) = self.peek()?;
+        if !f(outer_index, value) {
+            return None;
+        }
+        self.next()
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for OrderedPeekable<'_, T, N> {}
+
+impl<T, const N: usize> FusedIterator for OrderedPeekable<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for OrderedPeekable<'a, T, N> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+struct OwningReservoirOrderedIter<T, const N: usize> {
+    /// The items moved out of the reservoir's buffer. The slots not yielded by
+    /// `inner` yet are initialized, the rest are not.
+    slots: [MaybeUninit<T>; N],
+    /// The number of initialized slots before any item was taken, which are the first ones.
+    len: usize,
+    inner: ReservoirOrderedIndexIter<N>,
+}
 
 impl<T, const N: usize> OwningReservoirOrderedIter<T, N> {
-    fn get_item_ref(&mut self, idx: usize) -> &mut MaybeUninit<T> {
-        unsafe {
-            &mut *(self.buf.as_mut().unwrap_unchecked().as_mut_ptr().add(idx)
-                as *mut MaybeUninit<T>)
+    fn new(buf: Vec<T, N>, inner: ReservoirOrderedIndexIter<N>) -> Self {
+        let len = buf.len();
+        let mut slots = core::array::from_fn(|_| MaybeUninit::uninit());
+        for (slot, value) in slots.iter_mut().zip(buf) {
+            slot.write(value);
         }
+        Self { slots, len, inner }
+    }
+
+    fn take_item(&mut self, idx: usize) -> T {
+        // SAFETY: `idx` has just been yielded by `inner`, so the slot is initialized
+        // and won't be visited again
+        unsafe { self.slots[idx].assume_init_read() }
     }
 }
 
+impl<T, const N: usize> ExactSizeIterator for OwningReservoirOrderedIter<T, N> {}
+
+impl<T, const N: usize> FusedIterator for OwningReservoirOrderedIter<T, N> {}
+
 impl<T, const N: usize> Iterator for OwningReservoirOrderedIter<T, N> {
     type Item = T;
 
     fn next(&mut self) -> Option<Self::Item> {
         let idx = self.inner.next()?;
-        Some(unsafe {
-            core::mem::replace(self.get_item_ref(idx), MaybeUninit::uninit()).assume_init()
-        })
+        Some(self.take_item(idx))
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
         self.inner.size_hint()
     }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if core::mem::needs_drop::<T>() {
+            // Skipped items still have to be dropped
+            for _ in 0..n {
+                self.next()?;
+            }
+            self.next()
+        } else {
+            let idx = self.inner.nth(n)?;
+            Some(self.take_item(idx))
+        }
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        // The rest is dropped along with self
+        let idx = self.inner.next_back()?;
+        Some(self.take_item(idx))
+    }
+
+    fn count(self) -> usize {
+        self.len()
+    }
 }
 
 impl<T, const N: usize> Drop for OwningReservoirOrderedIter<T, N> {
     fn drop(&mut self) {
-        // Consume remaining items
-        for _ in self.by_ref() {}
-        core::mem::forget(self.buf.take());
+        if !core::mem::needs_drop::<T>() {
+            return;
+        }
+        // Nothing was taken yet, so the occupied slots can be dropped in storage order
+        // without the ordered index math
+        let in_storage_order = self.inner.len() == self.len;
+        let mut guard = DropRemaining {
+            iter: self,
+            dropped: 0,
+            in_storage_order,
+        };
+        if in_storage_order {
+            while guard.dropped < guard.iter.len {
+                guard.dropped += 1;
+                // SAFETY: the first `len` slots are initialized and none was taken
+                unsafe { guard.iter.slots[guard.dropped - 1].assume_init_drop() };
+            }
+        } else {
+            guard.iter.inner.clone().fold((), |(), idx| {
+                guard.dropped += 1;
+                // SAFETY: the slots not yielded yet are initialized and visited once
+                unsafe { guard.iter.slots[idx].assume_init_drop() };
+            });
+        }
+        core::mem::forget(guard);
+    }
+}
+
+/// Keeps dropping the items of an [OwningReservoirOrderedIter] after one of them panicked,
+/// so that the rest isn't leaked.
+struct DropRemaining<'a, T, const N: usize> {
+    iter: &'a mut OwningReservoirOrderedIter<T, N>,
+    /// The number of items whose drop was started, including the one that panicked.
+    dropped: usize,
+    /// The items are dropped in storage order rather than in chronological order.
+    in_storage_order: bool,
+}
+
+impl<T, const N: usize> Drop for DropRemaining<'_, T, N> {
+    fn drop(&mut self) {
+        if self.in_storage_order {
+            let len = self.iter.len;
+            for slot in &mut self.iter.slots[self.dropped..len] {
+                // SAFETY: the slots past the panicking one are initialized
+                unsafe { slot.assume_init_drop() };
+            }
+            return;
+        }
+        if self.dropped > 0 {
+            self.iter.inner.nth(self.dropped - 1);
+        }
+        for idx in &mut self.iter.inner {
+            // SAFETY: the slots past the panicking one are initialized and visited once
+            unsafe { self.iter.slots[idx].assume_init_drop() };
+        }
+    }
+}
+
+/// Returned by [SamplingReservoir::sample_checked()] when the reservoir's state is
+/// inconsistent, with the `strict` feature.
+#[cfg(feature = "strict")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvariantViolation;
+
+#[cfg(feature = "strict")]
+impl core::fmt::Display for InvariantViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("sampler invariant violated")
     }
 }
 
+/// The result of offering a value to a reservoir.
+///
+/// ```
+/// use infinity_sampler::{SamplingOutcome, SamplingReservoir};
+///
+/// let mut reservoir = SamplingReservoir::<u32, 2>::new();
+/// assert_eq!(reservoir.sample(1), SamplingOutcome::Consumed);
+/// assert!(reservoir.sample(2).is_consumed());
+///
+/// let mut discarded = Vec::new();
+/// for i in 3..10 {
+///     if let Some(value) = reservoir.sample(i).into_discarded() {
+///         discarded.push(value);
+///     }
+/// }
+/// assert_eq!(discarded, [4, 6, 7, 8]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum SamplingOutcome<T> {
+    /// The value was stored.
     Consumed,
-    ConsumedAndRateReduced { factor: u32 },
+    /// The value was stored and the sampling rate was reduced,
+    /// i.e. only one in `new_divisor` values will be stored from now on.
+    ConsumedAndRateReduced { old_divisor: u64, new_divisor: u64 },
+    /// The value was rejected by the sampling rate and is handed back.
     Discarded(T),
 }
+
+impl<T> SamplingOutcome<T> {
+    /// Returns true if the value was stored.
+    pub fn is_consumed(&self) -> bool {
+        !self.is_discarded()
+    }
+
+    /// Returns true if the value was handed back.
+    pub fn is_discarded(&self) -> bool {
+        matches!(self, Self::Discarded(_))
+    }
+
+    /// Returns the value if it was handed back.
+    pub fn into_discarded(self) -> Option<T> {
+        match self {
+            Self::Discarded(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value carried by the outcome, if any.
+    ///
+    /// Same as [into_discarded()](Self::into_discarded) for now, as only a discarded value
+    /// is handed back. Once an outcome also hands back the value evicted by an overwrite,
+    /// i.e. a `Replaced` variant, this returns that value too while `into_discarded()` doesn't.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingOutcome;
+    ///
+    /// assert_eq!(SamplingOutcome::Discarded(5).value(), Some(5));
+    /// assert_eq!(SamplingOutcome::<u8>::Consumed.value(), None);
+    /// ```
+    pub fn value(self) -> Option<T> {
+        self.into_discarded()
+    }
+
+    /// Maps the carried value, keeping everything else unchanged.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingOutcome;
+    ///
+    /// let outcome = SamplingOutcome::Discarded("text");
+    /// assert_eq!(outcome.map(str::len), SamplingOutcome::Discarded(4));
+    ///
+    /// let outcome = SamplingOutcome::<&str>::ConsumedAndRateReduced {
+    ///     old_divisor: 2,
+    ///     new_divisor: 4,
+    /// };
+    /// assert_eq!(
+    ///     outcome.map(str::len),
+    ///     SamplingOutcome::ConsumedAndRateReduced {
+    ///         old_divisor: 2,
+    ///         new_divisor: 4,
+    ///     }
+    /// );
+    /// ```
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> SamplingOutcome<U> {
+        match self {
+            Self::Consumed => SamplingOutcome::Consumed,
+            Self::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            } => SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            },
+            Self::Discarded(value) => SamplingOutcome::Discarded(f(value)),
+        }
+    }
+
+    /// Borrows the carried value.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingOutcome;
+    ///
+    /// let outcome = SamplingOutcome::Discarded([0u8; 1024]);
+    /// if let SamplingOutcome::Discarded(buf) = outcome.as_ref() {
+    ///     assert_eq!(buf.len(), 1024);
+    /// }
+    /// assert!(outcome.is_discarded());
+    /// ```
+    pub fn as_ref(&self) -> SamplingOutcome<&T> {
+        match self {
+            Self::Consumed => SamplingOutcome::Consumed,
+            Self::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            } => SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor: *old_divisor,
+                new_divisor: *new_divisor,
+            },
+            Self::Discarded(value) => SamplingOutcome::Discarded(value),
+        }
+    }
+}
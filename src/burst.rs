@@ -0,0 +1,69 @@
+use crate::SamplingRate;
+
+/// A [SamplingRate] wrapper that can temporarily accept every step.
+///
+/// After [grant_burst(k)](Self::grant_burst), the next `k` steps are accepted
+/// unconditionally. The phase of the wrapped rate is reset when the burst ends,
+/// so the first regular accepted step is the `divisor`-th one after the burst.
+///
+/// ```
+/// use infinity_sampler::BurstRate;
+///
+/// let mut sampler = BurstRate::new(4);
+/// sampler.grant_burst(2);
+/// let accepted: Vec<_> = (0..6).map(|_| sampler.step()).collect();
+/// assert_eq!(accepted, [true, true, false, false, false, true]);
+/// ```
+#[derive(Copy, Clone)]
+pub struct BurstRate {
+    rate: SamplingRate,
+    burst: u32,
+}
+
+impl BurstRate {
+    pub const fn new(divisor: u32) -> Self {
+        Self {
+            rate: SamplingRate::new(divisor),
+            burst: 0,
+        }
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        if self.burst > 0 {
+            self.burst -= 1;
+            if self.burst == 0 {
+                self.rate.reset();
+            }
+            return true;
+        }
+        self.rate.step()
+    }
+
+    /// Makes the next `k` steps accepted unconditionally.
+    /// Replaces any burst still in progress.
+    pub fn grant_burst(&mut self, k: u32) {
+        self.burst = k;
+    }
+
+    /// Returns the number of steps left in the current burst.
+    pub fn burst_remaining(&self) -> u32 {
+        self.burst
+    }
+
+    /// Reduce the sampling rate by a ratio.
+    ///
+    /// During a burst, the new divisor takes effect once the burst ends.
+    pub fn div(&mut self, ratio: u32) {
+        self.rate.div(ratio);
+    }
+
+    pub fn divisor(&self) -> u32 {
+        self.rate.divisor()
+    }
+
+    /// Returns a reference to the wrapped rate.
+    pub fn inner(&self) -> &SamplingRate {
+        &self.rate
+    }
+}
@@ -0,0 +1,312 @@
+use core::convert::TryInto;
+use core::fmt;
+use heapless::Vec;
+
+use crate::math::samples_accepted_saturating;
+use crate::SamplingReservoir;
+
+const MAGIC: [u8; 4] = *b"ISMP";
+const VERSION: u8 = 1;
+
+/// A value with a fixed-size little-endian encoding, used by
+/// [SamplingReservoir::to_bytes()] and [SamplingReservoir::from_bytes()].
+///
+/// Implemented for the primitive integers and floats.
+pub trait LeBytes: Copy {
+    /// The encoded size in bytes.
+    const SIZE: usize;
+
+    /// Writes the value into `out`, which is exactly [SIZE](Self::SIZE) bytes long.
+    fn write_le(&self, out: &mut [u8]);
+
+    /// Reads a value from `bytes`, which is exactly [SIZE](Self::SIZE) bytes long.
+    fn read_le(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_le_bytes {
+    ($($t:ty),*) => {
+        $(
+            impl LeBytes for $t {
+                const SIZE: usize = core::mem::size_of::<$t>();
+
+                fn write_le(&self, out: &mut [u8]) {
+                    out.copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_le(bytes: &[u8]) -> Self {
+                    // The caller passes exactly SIZE bytes
+                    Self::from_le_bytes(bytes.try_into().unwrap())
+                }
+            }
+        )*
+    };
+}
+
+impl_le_bytes!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/// Returned by [SamplingReservoir::to_bytes()] if the output buffer can't hold the encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooSmall;
+
+impl fmt::Display for BufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("output buffer is too small")
+    }
+}
+
+/// Returned by [SamplingReservoir::from_bytes()] for an invalid encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ends before the header or the values.
+    Truncated,
+    /// The input continues after the values.
+    TrailingBytes,
+    /// The input doesn't start with the expected magic bytes.
+    BadMagic,
+    /// The encoding version is not supported.
+    UnsupportedVersion(u8),
+    /// The encoded reservoir has a different capacity `N`.
+    CapacityMismatch,
+    /// The encoded values have a different size.
+    ValueSizeMismatch,
+    /// The counters and the rate don't describe a reachable reservoir state.
+    InvalidState,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("input is truncated"),
+            Self::TrailingBytes => f.write_str("input has trailing bytes"),
+            Self::BadMagic => f.write_str("input is not an encoded reservoir"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            Self::CapacityMismatch => f.write_str("reservoir capacity doesn't match"),
+            Self::ValueSizeMismatch => f.write_str("value size doesn't match"),
+            Self::InvalidState => f.write_str("inconsistent reservoir state"),
+        }
+    }
+}
+
+/// The counters of a [SamplingReservoir] needed to reinterpret its stored values,
+/// as written in the header of [to_bytes()](SamplingReservoir::to_bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservoirState {
+    /// The encoded size of a value in bytes.
+    pub value_size: u16,
+    /// The capacity `N`.
+    pub capacity: u32,
+    /// See [SamplingReservoir::samples_seen()].
+    pub samples_seen: u64,
+    /// See [SamplingReservoir::samples_accepted()].
+    pub samples_accepted: u64,
+    /// See [SamplingRate::divisor()](crate::SamplingRate::divisor).
+    pub divisor: u32,
+    /// See [SamplingRate::counter()](crate::SamplingRate::counter).
+    pub counter: u32,
+}
+
+impl ReservoirState {
+    /// The size of the encoded header.
+    ///
+    /// Layout: magic, version, value size (u16), N (u32), samples seen (u64),
+    /// samples accepted (u64), rate divisor (u32), rate counter (u32). Everything is little-endian.
+    pub const HEADER_LEN: usize = 4 + 1 + 2 + 4 + 8 + 8 + 4 + 4;
+
+    /// Writes the header into the first [HEADER_LEN](Self::HEADER_LEN) bytes of `out`.
+    /// Panics if `out` is shorter.
+    pub fn write_header(&self, out: &mut [u8]) {
+        let mut writer = Writer(&mut out[..Self::HEADER_LEN]);
+        writer.put(&MAGIC);
+        writer.put(&[VERSION]);
+        writer.put(&self.value_size.to_le_bytes());
+        writer.put(&self.capacity.to_le_bytes());
+        writer.put(&self.samples_seen.to_le_bytes());
+        writer.put(&self.samples_accepted.to_le_bytes());
+        writer.put(&self.divisor.to_le_bytes());
+        writer.put(&self.counter.to_le_bytes());
+    }
+
+    /// Reads a header written by [write_header()](Self::write_header) from the start of `bytes`.
+    ///
+    /// Only the magic and the version are checked here, the counters are validated
+    /// when the reservoir is rebuilt.
+    pub fn read_header(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let header = bytes
+            .get(..Self::HEADER_LEN)
+            .ok_or(DecodeError::Truncated)?;
+        let mut reader = Reader(header);
+        if reader.take::<4>() != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let [version] = reader.take::<1>();
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        Ok(Self {
+            value_size: u16::from_le_bytes(reader.take()),
+            capacity: u32::from_le_bytes(reader.take()),
+            samples_seen: u64::from_le_bytes(reader.take()),
+            samples_accepted: u64::from_le_bytes(reader.take()),
+            divisor: u32::from_le_bytes(reader.take()),
+            counter: u32::from_le_bytes(reader.take()),
+        })
+    }
+
+    /// Checks the state against the expected value size and capacity, and returns
+    /// the number of stored values.
+    pub(crate) fn stored_len<const N: usize>(
+        &self,
+        value_size: usize,
+    ) -> Result<usize, DecodeError> {
+        if self.value_size as usize != value_size {
+            return Err(DecodeError::ValueSizeMismatch);
+        }
+        if self.capacity as usize != N {
+            return Err(DecodeError::CapacityMismatch);
+        }
+        let samples_seen: usize = self
+            .samples_seen
+            .try_into()
+            .map_err(|_| DecodeError::InvalidState)?;
+        let expected = samples_accepted_saturating::<N>(samples_seen) as u64;
+        // The observed count stops at the ceiling while values are still accepted
+        let consistent = if samples_seen == SamplingReservoir::<(), N>::MAX_SAMPLES_SEEN {
+            self.samples_accepted >= expected
+        } else {
+            self.samples_accepted == expected
+        };
+        if !consistent {
+            return Err(DecodeError::InvalidState);
+        }
+        Ok((self.samples_accepted as usize).min(N))
+    }
+}
+
+/// Returns an error unless `values` holds exactly `len` values of `value_size` bytes.
+pub(crate) fn check_values_len(
+    values: &[u8],
+    len: usize,
+    value_size: usize,
+) -> Result<(), DecodeError> {
+    match values.len().cmp(&(len * value_size)) {
+        core::cmp::Ordering::Less => Err(DecodeError::Truncated),
+        core::cmp::Ordering::Greater => Err(DecodeError::TrailingBytes),
+        core::cmp::Ordering::Equal => Ok(()),
+    }
+}
+
+impl<T, const N: usize> SamplingReservoir<T, N> {
+    /// Returns the counters, for values encoded with `value_size` bytes.
+    pub(crate) fn state(&self, value_size: usize) -> ReservoirState {
+        ReservoirState {
+            value_size: value_size as u16,
+            capacity: N as u32,
+            samples_seen: self.samples_seen() as u64,
+            samples_accepted: self.samples_accepted() as u64,
+            divisor: self.sampling_rate().divisor(),
+            counter: self.sampling_rate().counter(),
+        }
+    }
+
+    /// Rebuilds a reservoir from a state checked by [ReservoirState::stored_len()]
+    /// and the stored values in storage order.
+    pub(crate) fn from_state(state: ReservoirState, buf: Vec<T, N>) -> Result<Self, DecodeError> {
+        let samples_accepted = state
+            .samples_accepted
+            .try_into()
+            .map_err(|_| DecodeError::InvalidState)?;
+        let mut reservoir =
+            Self::from_unordered(state.samples_seen as usize, samples_accepted, buf)
+                .map_err(|_| DecodeError::InvalidState)?;
+        let at_ceiling = reservoir.is_saturated();
+        let rate = reservoir.sampling_rate_mut();
+        if rate.divisor() != state.divisor || state.counter >= state.divisor {
+            return Err(DecodeError::InvalidState);
+        }
+        // The phase follows from the observed count, except at the ceiling
+        if at_ceiling {
+            rate.set_phase(state.counter);
+        } else if rate.counter() != state.counter {
+            return Err(DecodeError::InvalidState);
+        }
+        Ok(reservoir)
+    }
+}
+
+impl<T: LeBytes, const N: usize> SamplingReservoir<T, N> {
+    /// Returns the number of bytes written by [to_bytes()](Self::to_bytes).
+    pub fn encoded_len(&self) -> usize {
+        ReservoirState::HEADER_LEN + self.len() * T::SIZE
+    }
+
+    /// Encodes the reservoir state into `out` and returns the number of bytes written.
+    ///
+    /// The encoding is a small versioned header, see [ReservoirState], followed by
+    /// the occupied part of the buffer in storage order, restored by [from_bytes()](Self::from_bytes).
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u16, 8>::new();
+    /// for i in 0..100 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let mut bytes = [0; 64];
+    /// let len = reservoir.to_bytes(&mut bytes).unwrap();
+    /// assert_eq!(len, reservoir.encoded_len());
+    ///
+    /// let restored = SamplingReservoir::<u16, 8>::from_bytes(&bytes[..len]).unwrap();
+    /// assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+    /// assert!(SamplingReservoir::<u16, 16>::from_bytes(&bytes[..len]).is_err());
+    /// ```
+    pub fn to_bytes(&self, out: &mut [u8]) -> Result<usize, BufferTooSmall> {
+        let len = self.encoded_len();
+        let out = out.get_mut(..len).ok_or(BufferTooSmall)?;
+        let (header, values) = out.split_at_mut(ReservoirState::HEADER_LEN);
+
+        self.state(T::SIZE).write_header(header);
+        for (chunk, value) in values
+            .chunks_exact_mut(T::SIZE)
+            .zip(self.as_unordered_slice())
+        {
+            value.write_le(chunk);
+        }
+        Ok(len)
+    }
+
+    /// Decodes a reservoir encoded by [to_bytes()](Self::to_bytes).
+    ///
+    /// Every header field is validated, including that the counters and the rate
+    /// match the deterministic schedule for the given number of observed values.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let state = ReservoirState::read_header(bytes)?;
+        let len = state.stored_len::<N>(T::SIZE)?;
+        let values = &bytes[ReservoirState::HEADER_LEN..];
+        check_values_len(values, len, T::SIZE)?;
+
+        let buf: Vec<T, N> = values.chunks_exact(T::SIZE).map(T::read_le).collect();
+        Self::from_state(state, buf)
+    }
+}
+
+struct Writer<'a>(&'a mut [u8]);
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let (head, tail) = core::mem::take(&mut self.0).split_at_mut(bytes.len());
+        head.copy_from_slice(bytes);
+        self.0 = tail;
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const L: usize>(&mut self) -> [u8; L] {
+        let (head, tail) = self.0.split_at(L);
+        self.0 = tail;
+        head.try_into().unwrap()
+    }
+}
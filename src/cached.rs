@@ -0,0 +1,130 @@
+use core::iter::FusedIterator;
+
+use crate::rate::ReservoirRate;
+use crate::SamplingReservoir;
+
+/// A cache of the chronological order of the items of a [SamplingReservoir], for
+/// reading the same reservoir in order many times between writes.
+///
+/// The order only changes when a value is stored, so it is recomputed only when
+/// [samples_accepted()](SamplingReservoir::samples_accepted) has changed since the last read.
+/// Holds the storage indexes as `u16`, so `N` must be at most _2<sup>16</sup>_.
+///
+/// ```
+/// use infinity_sampler::{CachedOrder, SamplingReservoir};
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// let mut order = CachedOrder::new();
+/// for i in 0..16 {
+///     let _ = reservoir.sample(i);
+///     // Recomputed only if the value was stored
+///     assert!(order.ordered_iter(&reservoir).eq(reservoir.ordered_iter()));
+/// }
+/// assert_eq!(order.indices(&reservoir), [0, 1, 2, 3]);
+/// ```
+#[derive(Clone)]
+pub struct CachedOrder<const N: usize> {
+    indices: [u16; N],
+    len: usize,
+    /// The number of stored values the indexes were computed for.
+    samples_accepted: Option<usize>,
+}
+
+impl<const N: usize> CachedOrder<N> {
+    /// Creates an empty cache.
+    /// Panics if `N` is above _2<sup>16</sup>_.
+    pub const fn new() -> Self {
+        assert!(
+            N <= u16::MAX as usize + 1,
+            "Buffer capacity must fit the u16 indexes"
+        );
+        Self {
+            indices: [0; N],
+            len: 0,
+            samples_accepted: None,
+        }
+    }
+
+    /// Returns the storage indexes of the items of `reservoir` in chronological order,
+    /// recomputing them only if it has stored a value since the last call - *O(N)*
+    /// after a write, *O(1)* otherwise.
+    pub fn indices<T, R: ReservoirRate>(
+        &mut self,
+        reservoir: &SamplingReservoir<T, N, R>,
+    ) -> &[u16] {
+        let samples_accepted = reservoir.samples_accepted();
+        if self.samples_accepted != Some(samples_accepted) {
+            self.len = 0;
+            for idx in reservoir.ordered_indices() {
+                self.indices[self.len] = idx as u16;
+                self.len += 1;
+            }
+            self.samples_accepted = Some(samples_accepted);
+        }
+        &self.indices[..self.len]
+    }
+
+    /// Returns an iterator over the items of `reservoir` in chronological order,
+    /// see [indices()](Self::indices).
+    pub fn ordered_iter<'a, T, R: ReservoirRate>(
+        &'a mut self,
+        reservoir: &'a SamplingReservoir<T, N, R>,
+    ) -> CachedOrderedIter<'a, T> {
+        CachedOrderedIter {
+            buf: reservoir.as_unordered_slice(),
+            indices: self.indices(reservoir).iter(),
+        }
+    }
+
+    /// Forgets the cached order, e.g. when switching to another reservoir.
+    pub fn invalidate(&mut self) {
+        self.samples_accepted = None;
+    }
+}
+
+impl<const N: usize> Default for CachedOrder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the items of a [SamplingReservoir] in chronological order,
+/// returned by [CachedOrder::ordered_iter()].
+pub struct CachedOrderedIter<'a, T> {
+    buf: &'a [T],
+    indices: core::slice::Iter<'a, u16>,
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for CachedOrderedIter<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            buf: self.buf,
+            indices: self.indices.clone(),
+        }
+    }
+}
+
+impl<T> ExactSizeIterator for CachedOrderedIter<'_, T> {}
+
+impl<T> FusedIterator for CachedOrderedIter<'_, T> {}
+
+impl<'a, T> Iterator for CachedOrderedIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let idx = *self.indices.next()?;
+        Some(&self.buf[idx as usize])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.indices.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for CachedOrderedIter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let idx = *self.indices.next_back()?;
+        Some(&self.buf[idx as usize])
+    }
+}
@@ -0,0 +1,150 @@
+use core::convert::TryFrom;
+use heapless::Vec;
+use minicbor::decode::{self, Decoder};
+use minicbor::encode::{self, Encoder, Write};
+use minicbor::{Decode, Encode};
+
+use crate::math::samples_accepted_saturating;
+use crate::rate::ReservoirRate;
+use crate::{DecodeError, OrderedExport, OrderedImport, ReservoirState, SamplingReservoir};
+
+/// The full state: `[N, samples_seen, samples_accepted, divisor, counter, [values]]`,
+/// with the values in storage order.
+///
+/// Decoding validates the counters like [from_bytes()](SamplingReservoir::from_bytes).
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+///
+/// let mut reservoir = SamplingReservoir::<u16, 8>::new();
+/// for i in 0..100 {
+///     let _ = reservoir.sample(i);
+/// }
+/// let mut bytes = [0; 64];
+/// minicbor::encode(&reservoir, &mut bytes[..]).unwrap();
+///
+/// let restored: SamplingReservoir<u16, 8> = minicbor::decode(&bytes).unwrap();
+/// assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+/// ```
+impl<C, T: Encode<C>, const N: usize> Encode<C> for SamplingReservoir<T, N> {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), encode::Error<W::Error>> {
+        let rate = self.sampling_rate();
+        e.array(6)?
+            .u32(N as u32)?
+            .u64(self.samples_seen() as u64)?
+            .u64(self.samples_accepted() as u64)?
+            .u32(rate.divisor())?
+            .u32(rate.counter())?
+            .array(self.len() as u64)?;
+        for value in self.as_unordered_slice() {
+            value.encode(e, ctx)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'b, C, T: Decode<'b, C>, const N: usize> Decode<'b, C> for SamplingReservoir<T, N> {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut C) -> Result<Self, decode::Error> {
+        expect_array(d, 6)?;
+        let position = d.position();
+        // The values are self-describing, so there's no value size to check
+        let state = ReservoirState {
+            value_size: 0,
+            capacity: d.u32()?,
+            samples_seen: d.u64()?,
+            samples_accepted: d.u64()?,
+            divisor: d.u32()?,
+            counter: d.u32()?,
+        };
+        let len = state
+            .stored_len::<N>(0)
+            .map_err(|err| invalid(err).at(position))?;
+
+        expect_array(d, len)?;
+        let mut buf = Vec::<T, N>::new();
+        for _ in 0..len {
+            // Can't overflow, len is at most N
+            let _ = buf.push(d.decode_with(ctx)?);
+        }
+        Self::from_state(state, buf).map_err(|err| invalid(err).at(position))
+    }
+}
+
+/// `[samples_seen, [values]]`, with the values in chronological order.
+///
+/// ```
+/// use infinity_sampler::{OrderedImport, SamplingReservoir};
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// for i in 0..8 {
+///     let _ = reservoir.sample(i);
+/// }
+/// let mut bytes = [0; 16];
+/// minicbor::encode(reservoir.ordered_export(), &mut bytes[..]).unwrap();
+/// assert_eq!(bytes[..8], [0x82, 0x08, 0x84, 0, 2, 4, 6, 0]);
+///
+/// let import: OrderedImport<u32, 4> = minicbor::decode(&bytes).unwrap();
+/// assert_eq!(import.values, [0, 2, 4, 6]);
+/// ```
+impl<C, T: Encode<C>, const N: usize, R: ReservoirRate> Encode<C> for OrderedExport<'_, T, N, R> {
+    fn encode<W: Write>(
+        &self,
+        e: &mut Encoder<W>,
+        ctx: &mut C,
+    ) -> Result<(), encode::Error<W::Error>> {
+        e.array(2)?
+            .u64(self.reservoir.samples_seen() as u64)?
+            .array(self.reservoir.len() as u64)?;
+        for value in self.reservoir.ordered_iter() {
+            value.encode(e, ctx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Decoding checks that `samples_seen` is at most
+/// [MAX_SAMPLES_SEEN](SamplingReservoir::MAX_SAMPLES_SEEN) and that the number of values
+/// matches it and `N`, so [into_reservoir()](OrderedImport::into_reservoir) can't fail afterwards.
+impl<'b, C, T: Decode<'b, C>, const N: usize> Decode<'b, C> for OrderedImport<T, N> {
+    fn decode(d: &mut Decoder<'b>, ctx: &mut C) -> Result<Self, decode::Error> {
+        expect_array(d, 2)?;
+        let position = d.position();
+        let samples_seen = usize::try_from(d.u64()?)
+            .ok()
+            .filter(|&seen| seen <= SamplingReservoir::<T, N>::MAX_SAMPLES_SEEN)
+            .ok_or_else(|| invalid(DecodeError::InvalidState).at(position))?;
+        let len = samples_accepted_saturating::<N>(samples_seen).min(N);
+
+        expect_array(d, len)?;
+        let mut values = Vec::new();
+        for _ in 0..len {
+            // Can't overflow, len is at most N
+            let _ = values.push(d.decode_with(ctx)?);
+        }
+        Ok(Self {
+            samples_seen,
+            values,
+        })
+    }
+}
+
+/// Reads a definite-length array header of exactly `len` items.
+fn expect_array(d: &mut Decoder<'_>, len: usize) -> Result<(), decode::Error> {
+    let position = d.position();
+    match d.array()? {
+        Some(actual) if actual == len as u64 => Ok(()),
+        Some(_) => Err(decode::Error::message("unexpected number of items").at(position)),
+        None => Err(decode::Error::message("expected a definite-length array").at(position)),
+    }
+}
+
+fn invalid(err: DecodeError) -> decode::Error {
+    decode::Error::message(match err {
+        DecodeError::CapacityMismatch => "reservoir capacity doesn't match",
+        _ => "inconsistent reservoir state",
+    })
+}
@@ -0,0 +1,70 @@
+use crate::SamplingRate;
+
+/// Two [SamplingRate] stages applied one after another.
+///
+/// Created by [SamplingRate::chain()]. The first stage steps on every call and the
+/// second stage only steps when the first one accepted, so each stage keeps its own
+/// phase counted in its own input. A step is accepted when both stages accept,
+/// which for fresh stages first happens on call number `first.divisor() * second.divisor()`.
+///
+/// ```
+/// use infinity_sampler::SamplingRate;
+///
+/// let mut sampler = SamplingRate::new(2).chain(SamplingRate::new(3));
+/// assert_eq!(sampler.effective_divisor(), 6);
+/// let accepted: Vec<_> = (1..=12).filter(|_| sampler.step()).collect();
+/// assert_eq!(accepted, [6, 12]);
+/// ```
+#[derive(Copy, Clone)]
+pub struct ChainedRate {
+    first: SamplingRate,
+    second: SamplingRate,
+}
+
+impl ChainedRate {
+    /// Returns true if both stages accept.
+    pub fn step(&mut self) -> bool {
+        self.first.step() && self.second.step()
+    }
+
+    /// Reduce the overall sampling rate by a ratio. Applies to the second stage.
+    pub fn div(&mut self, ratio: u32) {
+        self.second.div(ratio);
+    }
+
+    /// Returns the product of both stage divisors.
+    pub fn effective_divisor(&self) -> u64 {
+        self.first.divisor() as u64 * self.second.divisor() as u64
+    }
+
+    pub fn first(&self) -> &SamplingRate {
+        &self.first
+    }
+
+    pub fn first_mut(&mut self) -> &mut SamplingRate {
+        &mut self.first
+    }
+
+    pub fn second(&self) -> &SamplingRate {
+        &self.second
+    }
+
+    pub fn second_mut(&mut self) -> &mut SamplingRate {
+        &mut self.second
+    }
+
+    /// Consume self and return both stages.
+    pub fn into_inner(self) -> (SamplingRate, SamplingRate) {
+        (self.first, self.second)
+    }
+}
+
+impl SamplingRate {
+    /// Composes two rates into one, feeding the steps accepted by `self` into `other`.
+    pub fn chain(self, other: SamplingRate) -> ChainedRate {
+        ChainedRate {
+            first: self,
+            second: other,
+        }
+    }
+}
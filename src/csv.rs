@@ -0,0 +1,68 @@
+use core::fmt::{self, Display, Write};
+
+use crate::rate::ReservoirRate;
+use crate::SamplingReservoir;
+
+/// Options for [SamplingReservoir::write_csv()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// The column separator, `,` by default.
+    pub separator: char,
+    /// Whether to write a header row first, true by default.
+    pub header: bool,
+    /// Whether to write the `chronological_pos` and `outer_index` columns before
+    /// the value, true by default.
+    pub indices: bool,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        Self {
+            separator: ',',
+            header: true,
+            indices: true,
+        }
+    }
+}
+
+impl<T: Display, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// Writes the items in chronological order as CSV, one line per item with the columns
+    /// `chronological_pos`, `outer_index` and `value` - *O(N)*, without allocating.
+    ///
+    /// ```
+    /// use infinity_sampler::{CsvOptions, SamplingReservoir};
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// for i in 0..8 {
+    ///     let _ = reservoir.sample(i * 10);
+    /// }
+    ///
+    /// let mut csv = String::new();
+    /// reservoir.write_csv(&mut csv, CsvOptions::default()).unwrap();
+    /// assert_eq!(
+    ///     csv,
+    ///     "chronological_pos,outer_index,value\n0,0,0\n1,2,20\n2,4,40\n3,6,60\n"
+    /// );
+    ///
+    /// let mut csv = String::new();
+    /// let options = CsvOptions { header: false, indices: false, ..Default::default() };
+    /// reservoir.write_csv(&mut csv, options).unwrap();
+    /// assert_eq!(csv, "0\n20\n40\n60\n");
+    /// ```
+    pub fn write_csv<W: Write>(&self, w: &mut W, opts: CsvOptions) -> fmt::Result {
+        let sep = opts.separator;
+        if opts.header {
+            if opts.indices {
+                write!(w, "chronological_pos{}outer_index{}", sep, sep)?;
+            }
+            w.write_str("value\n")?;
+        }
+        for (pos, (outer_index, value)) in self.ordered_iter_enumerated().enumerate() {
+            if opts.indices {
+                write!(w, "{}{}{}{}", pos, sep, outer_index, sep)?;
+            }
+            writeln!(w, "{}", value)?;
+        }
+        Ok(())
+    }
+}
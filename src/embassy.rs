@@ -0,0 +1,66 @@
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::channel::Receiver;
+
+use crate::SamplingReservoir;
+
+/// A [SamplingReservoir] shared between embassy tasks.
+pub type SharedReservoir<M, T, const N: usize> = Mutex<M, RefCell<SamplingReservoir<T, N>>>;
+
+/// The number of values sampled between the yields of [drain_channel_into()].
+const YIELD_EVERY: usize = 64;
+
+/// Receives values from an embassy channel forever and samples them into `reservoir`.
+///
+/// Yields to the executor every 64 values, so a producer that keeps the channel
+/// full doesn't starve the other tasks. The reservoir is only locked while
+/// sampling a value, read it from other tasks with [snapshot()].
+///
+/// ```
+/// use core::cell::RefCell;
+/// use embassy_futures::select::select;
+/// use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+/// use embassy_sync::blocking_mutex::Mutex;
+/// use embassy_sync::channel::Channel;
+/// use infinity_sampler::{drain_channel_into, snapshot, SamplingReservoir};
+///
+/// let channel = Channel::<NoopRawMutex, u32, 8>::new();
+/// let reservoir = Mutex::new(RefCell::new(SamplingReservoir::<u32, 4>::new()));
+///
+/// embassy_futures::block_on(select(
+///     drain_channel_into(channel.receiver(), &reservoir),
+///     async {
+///         for i in 0..8 {
+///             channel.send(i).await;
+///         }
+///         while !channel.is_empty() {
+///             embassy_futures::yield_now().await;
+///         }
+///     },
+/// ));
+/// let values: Vec<_> = snapshot(&reservoir).into_ordered_iter().collect();
+/// assert_eq!(values, [0, 2, 4, 6]);
+/// ```
+pub async fn drain_channel_into<M: RawMutex, T, const C: usize, const N: usize>(
+    rx: Receiver<'_, M, T, C>,
+    reservoir: &SharedReservoir<M, T, N>,
+) -> ! {
+    loop {
+        for _ in 0..YIELD_EVERY {
+            let value = rx.receive().await;
+            reservoir.lock(|cell| {
+                let _ = cell.borrow_mut().sample(value);
+            });
+        }
+        embassy_futures::yield_now().await;
+    }
+}
+
+/// Returns a copy of a reservoir shared with [drain_channel_into()].
+pub fn snapshot<M: RawMutex, T: Clone, const N: usize>(
+    reservoir: &SharedReservoir<M, T, N>,
+) -> SamplingReservoir<T, N> {
+    reservoir.lock(|cell| cell.borrow().clone())
+}
@@ -0,0 +1,105 @@
+use heapless::Vec;
+#[cfg(feature = "serde")]
+use serde::ser::SerializeStruct;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::iter::InvalidState;
+use crate::rate::ReservoirRate;
+use crate::SamplingReservoir;
+
+/// Serializable view of the samples in a [SamplingReservoir], returned by
+/// [ordered_export()](SamplingReservoir::ordered_export).
+///
+/// Only the data is serialized, not the internal layout: `samples_seen` followed by
+/// the `values` in chronological order, as a struct with serde or a two-element array
+/// with minicbor. Together with `N`, that's enough to reconstruct the observation index
+/// of every value, see [math](crate::math), or the whole reservoir with [OrderedImport].
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// for i in 0..8 {
+///     let _ = reservoir.sample(i);
+/// }
+/// # #[cfg(feature = "serde")]
+/// assert_eq!(
+///     serde_json::to_string(&reservoir.ordered_export()).unwrap(),
+///     r#"{"samples_seen":8,"values":[0,2,4,6]}"#
+/// );
+/// ```
+pub struct OrderedExport<'a, T, const N: usize, R> {
+    pub(crate) reservoir: &'a SamplingReservoir<T, N, R>,
+}
+
+#[cfg(feature = "serde")]
+impl<T: Serialize, const N: usize, R: ReservoirRate> Serialize for OrderedExport<'_, T, N, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("OrderedExport", 2)?;
+        state.serialize_field("samples_seen", &self.reservoir.samples_seen())?;
+        state.serialize_field("values", &OrderedValues(self.reservoir))?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+pub(crate) struct OrderedValues<'a, T, const N: usize, R>(
+    pub(crate) &'a SamplingReservoir<T, N, R>,
+);
+
+#[cfg(feature = "serde")]
+impl<T: Serialize, const N: usize, R: ReservoirRate> Serialize for OrderedValues<'_, T, N, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.ordered_iter())
+    }
+}
+
+/// Decoded form of an [OrderedExport].
+///
+/// ```
+/// use infinity_sampler::{OrderedImport, SamplingReservoir};
+///
+/// # #[cfg(feature = "serde")] {
+/// let import: OrderedImport<u32, 4> =
+///     serde_json::from_str(r#"{"samples_seen":8,"values":[0,2,4,6]}"#).unwrap();
+/// let mut reservoir = import.into_reservoir().unwrap();
+/// for i in 8..16 {
+///     let _ = reservoir.sample(i);
+/// }
+/// assert_eq!(reservoir.into_ordered_iter().collect::<Vec<_>>(), [0, 4, 8, 12]);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+pub struct OrderedImport<T, const N: usize> {
+    /// The number of values observed by the exported reservoir.
+    pub samples_seen: usize,
+    /// The stored values in chronological order.
+    pub values: Vec<T, N>,
+}
+
+impl<T, const N: usize> OrderedImport<T, N> {
+    /// Rebuilds the exported reservoir, see [SamplingReservoir::from_ordered()].
+    pub fn into_reservoir(self) -> Result<SamplingReservoir<T, N>, InvalidState> {
+        SamplingReservoir::from_ordered(self.samples_seen, self.values)
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// Returns a serializable view of the samples in chronological order, see [OrderedExport].
+    pub fn ordered_export(&self) -> OrderedExport<'_, T, N, R> {
+        OrderedExport { reservoir: self }
+    }
+
+    /// Serializes the samples in chronological order, see [OrderedExport].
+    ///
+    /// Can be used with `#[serde(serialize_with = "SamplingReservoir::serialize_ordered")]`.
+    #[cfg(feature = "serde")]
+    pub fn serialize_ordered<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+    {
+        self.ordered_export().serialize(serializer)
+    }
+}
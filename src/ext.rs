@@ -0,0 +1,61 @@
+use crate::{ReservoirRate, SamplingOutcome, SamplingReservoir};
+
+/// Counters returned by [ReservoirSampleExt::sample_into()].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SampleStats {
+    /// The number of values taken from the iterator.
+    pub seen: usize,
+    /// The number of values stored into the reservoir.
+    pub stored: usize,
+    /// The number of times the sampling rate was reduced.
+    pub rate_reductions: usize,
+}
+
+/// Feeds any iterator into a [SamplingReservoir].
+///
+/// ```
+/// use infinity_sampler::ReservoirSampleExt;
+///
+/// let summary = (0..10_000_000).sample_reservoir::<64>();
+/// assert_eq!(summary.len(), 64);
+/// assert_eq!(summary.ordered_iter().nth(1), Some(&262_144));
+/// ```
+pub trait ReservoirSampleExt: Iterator + Sized {
+    /// Samples every item into a new reservoir.
+    fn sample_reservoir<const N: usize>(self) -> SamplingReservoir<Self::Item, N> {
+        let mut reservoir = SamplingReservoir::new();
+        self.sample_into(&mut reservoir);
+        reservoir
+    }
+
+    /// Samples every item into an existing reservoir.
+    ///
+    /// ```
+    /// use infinity_sampler::{ReservoirSampleExt, SampleStats, SamplingReservoir};
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+    /// let stats = (0..6).sample_into(&mut reservoir);
+    /// assert_eq!(stats, SampleStats { seen: 6, stored: 5, rate_reductions: 1 });
+    /// ```
+    fn sample_into<const N: usize, R: ReservoirRate>(
+        self,
+        reservoir: &mut SamplingReservoir<Self::Item, N, R>,
+    ) -> SampleStats {
+        let mut stats = SampleStats::default();
+        for item in self {
+            stats.seen += 1;
+            match reservoir.sample(item) {
+                SamplingOutcome::Consumed => stats.stored += 1,
+                SamplingOutcome::ConsumedAndRateReduced { .. } => {
+                    stats.stored += 1;
+                    stats.rate_reductions += 1;
+                }
+                SamplingOutcome::Discarded(_) => {}
+            }
+        }
+        stats
+    }
+}
+
+impl<I: Iterator> ReservoirSampleExt for I {}
@@ -0,0 +1,136 @@
+//! `extern "C"` bindings for a fixed set of reservoir types, declared in
+//! `include/infinity_sampler.h`.
+//!
+//! Each type comes with `infinity_sampler_{new,sample,ordered_copy,free}_<T>_<N>()` functions.
+//! Build a static library with `cargo rustc --release --features ffi --crate-type staticlib`.
+
+use std::boxed::Box;
+use std::panic::catch_unwind;
+
+use crate::{SamplingOutcome, SamplingReservoir};
+
+// Returned by the `_sample` functions
+const DISCARDED: i32 = 0;
+const CONSUMED: i32 = 1;
+const CONSUMED_AND_RATE_REDUCED: i32 = 2;
+const NULL_HANDLE: i32 = -1;
+
+macro_rules! ffi_bindings {
+    ($(
+        $handle:ident($t:ident, $n:literal):
+            $new:ident, $sample:ident, $ordered_copy:ident, $free:ident;
+    )*) => {
+        $(
+            #[doc = concat!("Opaque handle to a `SamplingReservoir<", stringify!($t), ", ", stringify!($n), ">`.")]
+            pub struct $handle(SamplingReservoir<$t, $n>);
+
+            /// Allocates an empty reservoir, or returns null if that fails.
+            #[no_mangle]
+            pub extern "C" fn $new() -> *mut $handle {
+                catch_unwind(|| Box::into_raw(Box::new($handle(SamplingReservoir::new()))))
+                    .unwrap_or(core::ptr::null_mut())
+            }
+
+            /// Observes a value. Returns 0 if it's discarded, 1 if it's stored,
+            /// 2 if it's stored and the rate is reduced, and -1 for a null handle.
+            ///
+            /// # Safety
+            ///
+            #[doc = concat!("`handle` must be null or returned by `", stringify!($new), "()` and not freed.")]
+            #[no_mangle]
+            pub unsafe extern "C" fn $sample(handle: *mut $handle, value: $t) -> i32 {
+                let Some(handle) = handle.as_mut() else {
+                    return NULL_HANDLE;
+                };
+                match handle.0.sample(value) {
+                    SamplingOutcome::Consumed => CONSUMED,
+                    SamplingOutcome::ConsumedAndRateReduced { .. } => CONSUMED_AND_RATE_REDUCED,
+                    SamplingOutcome::Discarded(_) => DISCARDED,
+                }
+            }
+
+            /// Copies up to `out_len` stored values in chronological order into `out`
+            /// and returns the number of values copied. Returns 0 for a null handle or output.
+            ///
+            /// # Safety
+            ///
+            #[doc = concat!("`handle` must be null or returned by `", stringify!($new), "()` and not freed,")]
+            /// and `out` must be null or valid for `out_len` writes.
+            #[no_mangle]
+            pub unsafe extern "C" fn $ordered_copy(
+                handle: *const $handle,
+                out: *mut $t,
+                out_len: usize,
+            ) -> usize {
+                let Some(handle) = handle.as_ref() else {
+                    return 0;
+                };
+                if out.is_null() {
+                    return 0;
+                }
+                let out = core::slice::from_raw_parts_mut(out, out_len);
+                let mut copied = 0;
+                for (slot, value) in out.iter_mut().zip(handle.0.ordered_iter()) {
+                    *slot = *value;
+                    copied += 1;
+                }
+                copied
+            }
+
+            /// Frees a reservoir. Does nothing for a null handle.
+            ///
+            /// # Safety
+            ///
+            #[doc = concat!("`handle` must be null or returned by `", stringify!($new), "()` and not freed.")]
+            #[no_mangle]
+            pub unsafe extern "C" fn $free(handle: *mut $handle) {
+                if !handle.is_null() {
+                    drop(Box::from_raw(handle));
+                }
+            }
+        )*
+
+        /// The C prototypes of the bindings, which must match the header.
+        #[cfg(test)]
+        pub(crate) const PROTOTYPES: &[&str] = &[$(
+            concat!(stringify!($handle), " *", stringify!($new), "(void);"),
+            concat!("int32_t ", stringify!($sample), "(", stringify!($handle), " *handle, ", c_type!($t), " value);"),
+            concat!(
+                "size_t ", stringify!($ordered_copy), "(const ", stringify!($handle),
+                " *handle, ", c_type!($t), " *out, size_t out_len);"
+            ),
+            concat!("void ", stringify!($free), "(", stringify!($handle), " *handle);"),
+        )*];
+    };
+}
+
+#[cfg(test)]
+macro_rules! c_type {
+    (f32) => {
+        "float"
+    };
+    (u32) => {
+        "uint32_t"
+    };
+}
+
+ffi_bindings! {
+    InfinitySamplerF32x64(f32, 64):
+        infinity_sampler_new_f32_64, infinity_sampler_sample_f32_64,
+        infinity_sampler_ordered_copy_f32_64, infinity_sampler_free_f32_64;
+    InfinitySamplerF32x256(f32, 256):
+        infinity_sampler_new_f32_256, infinity_sampler_sample_f32_256,
+        infinity_sampler_ordered_copy_f32_256, infinity_sampler_free_f32_256;
+    InfinitySamplerF32x1024(f32, 1024):
+        infinity_sampler_new_f32_1024, infinity_sampler_sample_f32_1024,
+        infinity_sampler_ordered_copy_f32_1024, infinity_sampler_free_f32_1024;
+    InfinitySamplerU32x64(u32, 64):
+        infinity_sampler_new_u32_64, infinity_sampler_sample_u32_64,
+        infinity_sampler_ordered_copy_u32_64, infinity_sampler_free_u32_64;
+    InfinitySamplerU32x256(u32, 256):
+        infinity_sampler_new_u32_256, infinity_sampler_sample_u32_256,
+        infinity_sampler_ordered_copy_u32_256, infinity_sampler_free_u32_256;
+    InfinitySamplerU32x1024(u32, 1024):
+        infinity_sampler_new_u32_1024, infinity_sampler_sample_u32_1024,
+        infinity_sampler_ordered_copy_u32_1024, infinity_sampler_free_u32_1024;
+}
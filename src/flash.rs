@@ -0,0 +1,272 @@
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use heapless::Vec;
+
+use crate::{LeBytes, ReservoirState, SamplingReservoir};
+
+const MAGIC: [u8; 4] = *b"ISFS";
+
+/// Layout: magic, payload offset from the record start (u32), payload length (u32),
+/// CRC-32 of the payload (u32), all little-endian, padded to the flash write granularity
+/// and followed by the [to_bytes()](SamplingReservoir::to_bytes) encoding. The record header
+/// is written last, so a torn save leaves no valid record.
+const RECORD_HEADER_LEN: usize = 4 + 4 + 4 + 4;
+
+/// The largest supported flash read and write granularity, and the size of the stack buffers.
+const CHUNK: usize = 256;
+
+impl<T: LeBytes, const N: usize> SamplingReservoir<T, N> {
+    /// Persists the reservoir into NOR flash at `offset`, which must be at the start
+    /// of an erase sector.
+    ///
+    /// The sectors covering the record are erased first, so the previous record at `offset`
+    /// is lost even if the save is interrupted; alternate between two offsets to always keep
+    /// a valid one. Uses a fixed 256-byte buffer, and panics if the flash read or write
+    /// granularity or `T::SIZE` is larger.
+    ///
+    /// ```
+    /// # use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+    /// # struct Flash([u8; 1024]);
+    /// # impl ErrorType for Flash {
+    /// #     type Error = NorFlashErrorKind;
+    /// # }
+    /// # impl ReadNorFlash for Flash {
+    /// #     const READ_SIZE: usize = 1;
+    /// #     fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+    /// #         let offset = offset as usize;
+    /// #         bytes.copy_from_slice(&self.0[offset..offset + bytes.len()]);
+    /// #         Ok(())
+    /// #     }
+    /// #     fn capacity(&self) -> usize {
+    /// #         self.0.len()
+    /// #     }
+    /// # }
+    /// # impl NorFlash for Flash {
+    /// #     const WRITE_SIZE: usize = 4;
+    /// #     const ERASE_SIZE: usize = 256;
+    /// #     fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+    /// #         self.0[from as usize..to as usize].fill(0xff);
+    /// #         Ok(())
+    /// #     }
+    /// #     fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+    /// #         let offset = offset as usize;
+    /// #         self.0[offset..offset + bytes.len()].copy_from_slice(bytes);
+    /// #         Ok(())
+    /// #     }
+    /// # }
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut flash = Flash([0xff; 1024]);
+    /// assert!(SamplingReservoir::<u32, 16>::load(&mut flash, 256).unwrap().is_none());
+    ///
+    /// let mut reservoir = SamplingReservoir::<u32, 16>::new();
+    /// for i in 0..100 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// reservoir.save(&mut flash, 256).unwrap();
+    ///
+    /// let restored = SamplingReservoir::<u32, 16>::load(&mut flash, 256).unwrap().unwrap();
+    /// assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+    /// ```
+    pub fn save<S: NorFlash>(&self, flash: &mut S, offset: u32) -> Result<(), S::Error> {
+        let align = S::READ_SIZE.max(S::WRITE_SIZE);
+        assert!(
+            CHUNK.is_multiple_of(align) && T::SIZE <= CHUNK,
+            "unsupported flash granularity or value size"
+        );
+        let header_span = round_up(RECORD_HEADER_LEN, align);
+        let payload_len = self.encoded_len();
+        let end = offset as usize + header_span + round_up(payload_len, align);
+        flash.erase(offset, round_up(end, S::ERASE_SIZE) as u32)?;
+
+        let mut writer = FlashWriter {
+            offset: offset + header_span as u32,
+            align,
+            buf: [0; CHUNK],
+            len: 0,
+            crc: CRC_INIT,
+        };
+        let mut header = [0; ReservoirState::HEADER_LEN];
+        self.state(T::SIZE).write_header(&mut header);
+        writer.put(flash, &header)?;
+        let mut value_bytes = [0; CHUNK];
+        for value in self.as_unordered_slice() {
+            value.write_le(&mut value_bytes[..T::SIZE]);
+            writer.put(flash, &value_bytes[..T::SIZE])?;
+        }
+        let crc = writer.finish(flash)?;
+
+        let mut record = [0xff; CHUNK];
+        record[..4].copy_from_slice(&MAGIC);
+        record[4..8].copy_from_slice(&(header_span as u32).to_le_bytes());
+        record[8..12].copy_from_slice(&(payload_len as u32).to_le_bytes());
+        record[12..16].copy_from_slice(&crc.to_le_bytes());
+        flash.write(offset, &record[..header_span])
+    }
+
+    /// Loads a reservoir persisted by [save()](Self::save) at `offset`.
+    ///
+    /// Returns `Ok(None)` if there's no complete record at `offset`, e.g. after a torn write,
+    /// or if it holds a reservoir of a different type or capacity.
+    pub fn load<S: ReadNorFlash>(flash: &mut S, offset: u32) -> Result<Option<Self>, S::Error> {
+        let align = S::READ_SIZE;
+        assert!(
+            CHUNK.is_multiple_of(align) && T::SIZE <= CHUNK,
+            "unsupported flash granularity or value size"
+        );
+        let mut record = [0; CHUNK];
+        flash.read(offset, &mut record[..round_up(RECORD_HEADER_LEN, align)])?;
+        if record[..4] != MAGIC {
+            return Ok(None);
+        }
+        let field = |i: usize| {
+            u32::from_le_bytes([record[i], record[i + 1], record[i + 2], record[i + 3]]) as usize
+        };
+        let (header_span, payload_len, crc) = (field(4), field(8), field(12) as u32);
+        if header_span < RECORD_HEADER_LEN
+            || !header_span.is_multiple_of(align)
+            || payload_len < ReservoirState::HEADER_LEN
+            || payload_len > ReservoirState::HEADER_LEN + N * T::SIZE
+        {
+            return Ok(None);
+        }
+
+        let payload_offset = offset + header_span as u32;
+        let mut reader = FlashReader::new(payload_offset, payload_len, align);
+        let mut chunk = [0; CHUNK];
+        let mut actual_crc = CRC_INIT;
+        let mut left = payload_len;
+        while left > 0 {
+            let len = left.min(CHUNK);
+            reader.read_exact(flash, &mut chunk[..len])?;
+            actual_crc = crc32_update(actual_crc, &chunk[..len]);
+            left -= len;
+        }
+        if !actual_crc != crc {
+            return Ok(None);
+        }
+
+        let mut reader = FlashReader::new(payload_offset, payload_len, align);
+        let mut header = [0; ReservoirState::HEADER_LEN];
+        reader.read_exact(flash, &mut header)?;
+        let state = match ReservoirState::read_header(&header) {
+            Ok(state) => state,
+            Err(_) => return Ok(None),
+        };
+        let len = match state.stored_len::<N>(T::SIZE) {
+            Ok(len) if payload_len == ReservoirState::HEADER_LEN + len * T::SIZE => len,
+            _ => return Ok(None),
+        };
+        let mut buf = Vec::<T, N>::new();
+        for _ in 0..len {
+            reader.read_exact(flash, &mut chunk[..T::SIZE])?;
+            // Can't overflow, len is at most N
+            let _ = buf.push(T::read_le(&chunk[..T::SIZE]));
+        }
+        Ok(Self::from_state(state, buf).ok())
+    }
+}
+
+fn round_up(value: usize, align: usize) -> usize {
+    value.div_ceil(align) * align
+}
+
+/// Buffers sequential writes into aligned chunks.
+struct FlashWriter {
+    offset: u32,
+    align: usize,
+    buf: [u8; CHUNK],
+    len: usize,
+    crc: u32,
+}
+
+impl FlashWriter {
+    fn put<S: NorFlash>(&mut self, flash: &mut S, mut bytes: &[u8]) -> Result<(), S::Error> {
+        self.crc = crc32_update(self.crc, bytes);
+        while !bytes.is_empty() {
+            let len = bytes.len().min(CHUNK - self.len);
+            self.buf[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+            self.len += len;
+            bytes = &bytes[len..];
+            if self.len == CHUNK {
+                self.flush(flash)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush<S: NorFlash>(&mut self, flash: &mut S) -> Result<(), S::Error> {
+        let len = round_up(self.len, self.align);
+        self.buf[self.len..len].fill(0xff);
+        flash.write(self.offset, &self.buf[..len])?;
+        self.offset += len as u32;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Writes the remaining bytes and returns the CRC of everything written.
+    fn finish<S: NorFlash>(mut self, flash: &mut S) -> Result<u32, S::Error> {
+        if self.len > 0 {
+            self.flush(flash)?;
+        }
+        Ok(!self.crc)
+    }
+}
+
+/// Reads `len` bytes sequentially through aligned chunks.
+struct FlashReader {
+    offset: u32,
+    /// Bytes not fetched yet, rounded up to the alignment.
+    unfetched: usize,
+    buf: [u8; CHUNK],
+    pos: usize,
+    filled: usize,
+}
+
+impl FlashReader {
+    fn new(offset: u32, len: usize, align: usize) -> Self {
+        Self {
+            offset,
+            unfetched: round_up(len, align),
+            buf: [0; CHUNK],
+            pos: 0,
+            filled: 0,
+        }
+    }
+
+    fn read_exact<S: ReadNorFlash>(
+        &mut self,
+        flash: &mut S,
+        out: &mut [u8],
+    ) -> Result<(), S::Error> {
+        let mut done = 0;
+        while done < out.len() {
+            if self.pos == self.filled {
+                // The callers never read past the record
+                let len = self.unfetched.min(CHUNK);
+                flash.read(self.offset, &mut self.buf[..len])?;
+                self.offset += len as u32;
+                self.unfetched -= len;
+                self.pos = 0;
+                self.filled = len;
+            }
+            let len = (out.len() - done).min(self.filled - self.pos);
+            out[done..done + len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+            self.pos += len;
+            done += len;
+        }
+        Ok(())
+    }
+}
+
+const CRC_INIT: u32 = !0;
+
+/// Bitwise CRC-32 (IEEE), without the final inversion.
+fn crc32_update(mut crc: u32, bytes: &[u8]) -> u32 {
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
@@ -0,0 +1,168 @@
+use core::num::NonZeroUsize;
+use heapless::Vec;
+
+use crate::buf::SamplingOutcome;
+use crate::item::Item;
+use crate::iter::InfinitySamplerIndexer;
+use crate::math::reduces_rate_at;
+use crate::rate::{ReservoirRate, SamplingRate};
+use crate::SamplingReservoir;
+
+/// # Indexed Infinity Sampler
+///
+/// Same sampling as [SamplingReservoir](crate::SamplingReservoir), but every slot is an
+/// [Item] that stores the insertion index along with the value.
+///
+/// The chronological order is recovered from the stored indexes instead of the
+/// closed-form schedule, at the cost of a `usize` per slot and an *O(N log N)* sort.
+///
+/// ```
+/// use infinity_sampler::IndexedSamplingReservoir;
+///
+/// let mut reservoir = IndexedSamplingReservoir::<char, 4>::new();
+/// for c in 'a'..='h' {
+///     let _ = reservoir.sample(c);
+/// }
+/// let samples: Vec<_> = reservoir.ordered_iter_enumerated().collect();
+/// assert_eq!(samples, [(0, &'a'), (2, &'c'), (4, &'e'), (6, &'g')]);
+/// ```
+#[derive(Clone)]
+pub struct IndexedSamplingReservoir<T, const N: usize, R = SamplingRate> {
+    items: [Item<T>; N],
+    sample_rate: R,
+    indexer: InfinitySamplerIndexer<N>,
+    outer_index: usize,
+}
+
+impl<T, const N: usize, R: ReservoirRate> IndexedSamplingReservoir<T, N, R> {
+    /// Creates an empty reservoir.
+    /// Panics if `N` is not a power of two.
+    pub const fn new() -> Self {
+        Self {
+            items: [Item::EMPTY; N],
+            sample_rate: R::INITIAL,
+            indexer: InfinitySamplerIndexer::new(),
+            outer_index: 0,
+        }
+    }
+
+    /// Returns N, the capacity of the internal buffer.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Get the number of currently stored items.
+    pub fn len(&self) -> usize {
+        self.samples_accepted().min(N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get a view into the internal storage, including the empty slots.
+    pub fn as_unordered_items(&self) -> &[Item<T>] {
+        &self.items
+    }
+
+    /// Return an iterator over the items in chronological order - *O(N log N)*.
+    pub fn ordered_iter(&self) -> impl Iterator<Item = &T> {
+        self.ordered_iter_enumerated().map(|(_, value)| value)
+    }
+
+    /// Return an iterator over the items in chronological order together with
+    /// their original observation indexes - *O(N log N)*.
+    ///
+    /// The items observed once [samples_seen()](Self::samples_seen) has stopped
+    /// share its last index, and come out in no particular order among themselves.
+    pub fn ordered_iter_enumerated(&self) -> impl Iterator<Item = (usize, &T)> {
+        let mut entries: Vec<(usize, &T), N> = self
+            .items
+            .iter()
+            .filter_map(|item| {
+                let init = item.as_init()?;
+                Some((init.insertion_index().get() - 1, &**init))
+            })
+            .collect();
+        entries.sort_unstable_by_key(|(outer_index, _)| *outer_index);
+        entries.into_iter()
+    }
+
+    /// Returns the highest [generation](Item::generation) among the slots, i.e. the number
+    /// of writes into the most rewritten one.
+    #[cfg(feature = "stats")]
+    pub fn max_generation(&self) -> u32 {
+        self.items.iter().map(Item::generation).max().unwrap_or(0)
+    }
+
+    /// Returns a reference to the current sampling rate.
+    pub fn sampling_rate(&self) -> &R {
+        &self.sample_rate
+    }
+
+    /// Returns the total number of samples accepted into the buffer since the beginning.
+    ///
+    /// See [SamplingReservoir::samples_accepted()](crate::SamplingReservoir::samples_accepted).
+    pub fn samples_accepted(&self) -> usize {
+        self.indexer.position()
+    }
+
+    #[deprecated(
+        since = "0.3.0",
+        note = "use `samples_accepted()`, or `len()` for the number of stored items"
+    )]
+    pub fn samples_stored(&self) -> usize {
+        self.samples_accepted()
+    }
+
+    /// Returns the total number of samples observed by the sampler since the beginning, up to
+    /// [MAX_SAMPLES_SEEN](crate::SamplingReservoir::MAX_SAMPLES_SEEN).
+    pub fn samples_seen(&self) -> usize {
+        self.outer_index
+    }
+
+    /// Moves the observed count, e.g. close to the ceiling.
+    #[cfg(test)]
+    pub(crate) fn set_samples_seen(&mut self, samples_seen: usize) {
+        self.outer_index = samples_seen;
+    }
+
+    /// Observe a value and possibly store it - *O(1)*.
+    ///
+    /// See [SamplingReservoir::sample()](crate::SamplingReservoir::sample).
+    #[must_use]
+    pub fn sample(&mut self, value: T) -> SamplingOutcome<T> {
+        // Stops at the same ceiling as SamplingReservoir rather than wrapping
+        self.outer_index =
+            (self.outer_index + 1).min(SamplingReservoir::<T, N, R>::MAX_SAMPLES_SEEN);
+        if !self.sample_rate.step() {
+            return SamplingOutcome::Discarded(value);
+        }
+        let mut result = SamplingOutcome::Consumed;
+
+        let stored = self.indexer.position();
+        if reduces_rate_at(N, stored) && !self.sample_rate.is_saturated() {
+            let old_divisor = self.sample_rate.divisor();
+            self.sample_rate.halve();
+            result = SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor: self.sample_rate.divisor(),
+            };
+        }
+        let insert_index = self.indexer.next_index();
+        // The count was just incremented and stops at the ceiling rather than wrapping,
+        // so it's never zero
+        #[cfg(not(feature = "strict"))]
+        let index = NonZeroUsize::new(self.outer_index).unwrap_or(NonZeroUsize::MIN);
+        #[cfg(feature = "strict")]
+        let index = NonZeroUsize::new(self.outer_index).expect("sampler invariant violated");
+        drop(self.items[insert_index].write(index, value));
+        result
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> Default for IndexedSamplingReservoir<T, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
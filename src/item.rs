@@ -0,0 +1,301 @@
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::num::NonZeroUsize;
+use core::ops::{Deref, DerefMut};
+
+/// A storage slot that remembers the insertion index of the value it holds.
+///
+/// The insertion index is the _1_-based number of the observation the value came from,
+/// i.e. its outer index plus one, so that an empty slot costs no extra space.
+///
+/// ```
+/// use core::num::NonZeroUsize;
+/// use infinity_sampler::Item;
+///
+/// let mut item = Item::new();
+/// assert_eq!(item.write(NonZeroUsize::new(1).unwrap(), 'a'), None);
+/// assert_eq!(item.write(NonZeroUsize::new(5).unwrap(), 'b'), Some('a'));
+/// assert_eq!(item.take(), Some('b'));
+/// assert_eq!(item.take(), None);
+/// ```
+///
+/// With the `stats` feature, the slot also counts how many values have been written into it,
+/// see [generation()](Self::generation).
+#[repr(C)]
+pub struct Item<T> {
+    insertion_index: Option<NonZeroUsize>,
+    value: MaybeUninit<T>,
+    #[cfg(feature = "stats")]
+    generation: u32,
+}
+
+impl<T> Item<T> {
+    /// An empty slot.
+    pub const EMPTY: Self = Self::new();
+
+    /// Creates an empty slot.
+    pub const fn new() -> Self {
+        Self {
+            insertion_index: None,
+            value: MaybeUninit::uninit(),
+            #[cfg(feature = "stats")]
+            generation: 0,
+        }
+    }
+
+    /// Stores a value with its insertion index, returning the previous value if any.
+    pub fn write(&mut self, index: NonZeroUsize, value: T) -> Option<T> {
+        let previous = self.take();
+        self.put(index, value);
+        #[cfg(feature = "stats")]
+        {
+            self.generation = self.generation.wrapping_add(1);
+        }
+        previous
+    }
+
+    /// Returns the number of [write()](Self::write) calls on this slot, including
+    /// the ones through [replace_with()](Self::replace_with).
+    ///
+    /// Taking the value out or mapping it in place doesn't change the generation,
+    /// and clones start with the same generation.
+    #[cfg(feature = "stats")]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Stores a value into an empty slot.
+    fn put(&mut self, index: NonZeroUsize, value: T) {
+        self.value.write(value);
+        self.insertion_index = Some(index);
+    }
+
+    /// Moves the value out, leaving the slot empty.
+    pub fn take(&mut self) -> Option<T> {
+        self.insertion_index.take()?;
+        // SAFETY: the value was initialized and the slot is now marked empty
+        Some(unsafe { self.value.assume_init_read() })
+    }
+
+    /// Replaces the value with the result of `f` applied to it, keeping the insertion index.
+    /// Does nothing if the slot is empty.
+    ///
+    /// If `f` panics, the value is dropped and the slot is left empty.
+    pub fn map_in_place(&mut self, f: impl FnOnce(T) -> T) {
+        if let Some(index) = self.insertion_index {
+            if let Some(value) = self.take() {
+                self.put(index, f(value));
+            }
+        }
+    }
+
+    /// Stores the result of `f` with a new insertion index, passing it the previous value if any.
+    ///
+    /// If `f` panics, the previous value is dropped and the slot is left empty.
+    ///
+    /// ```
+    /// use core::num::NonZeroUsize;
+    /// use infinity_sampler::Item;
+    ///
+    /// let mut item = Item::new();
+    /// item.replace_with(NonZeroUsize::new(1).unwrap(), |old| old.unwrap_or(0) + 10);
+    /// item.replace_with(NonZeroUsize::new(2).unwrap(), |old| old.unwrap_or(0) + 10);
+    /// assert_eq!(item.into_inner(), Some(20));
+    /// ```
+    pub fn replace_with(&mut self, index: NonZeroUsize, f: impl FnOnce(Option<T>) -> T) {
+        let value = f(self.take());
+        self.write(index, value);
+    }
+
+    /// Consumes the slot, returning the value if any.
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+
+    /// Returns a reference to the value without checking that the slot is occupied.
+    ///
+    /// # Safety
+    ///
+    /// The slot must hold a value.
+    pub unsafe fn get_unchecked(&self) -> &T {
+        self.value.assume_init_ref()
+    }
+
+    /// Returns a view of an occupied slot, or `None` if it is empty.
+    ///
+    /// ```
+    /// use core::num::NonZeroUsize;
+    /// use infinity_sampler::Item;
+    ///
+    /// let mut item = Item::new();
+    /// assert!(item.as_init().is_none());
+    /// item.write(NonZeroUsize::new(3).unwrap(), 10);
+    /// let init = item.as_init().unwrap();
+    /// assert_eq!(**init, 10);
+    /// assert_eq!(init.insertion_index().get(), 3);
+    /// ```
+    pub fn as_init(&self) -> Option<&InitializedItem<T>> {
+        self.insertion_index?;
+        // SAFETY: the slot is occupied
+        Some(unsafe { self.assume_init() })
+    }
+
+    /// Returns a mutable view of an occupied slot, or `None` if it is empty.
+    pub fn as_init_mut(&mut self) -> Option<&mut InitializedItem<T>> {
+        self.insertion_index?;
+        // SAFETY: the slot is occupied, see assume_init()
+        Some(unsafe { &mut *(self as *mut Self as *mut InitializedItem<T>) })
+    }
+
+    /// Returns a view of the slot without checking that it is occupied.
+    ///
+    /// # Safety
+    ///
+    /// The slot must hold a value.
+    pub unsafe fn assume_init(&self) -> &InitializedItem<T> {
+        // SAFETY: both structs are repr(C) with the same field order,
+        // Option<NonZeroUsize> has the layout of NonZeroUsize and
+        // MaybeUninit<T> has the layout of T. The caller guarantees that
+        // both fields are initialized.
+        &*(self as *const Self as *const InitializedItem<T>)
+    }
+
+    /// Returns the insertion index of the value, or `None` if the slot is empty.
+    pub fn insertion_index(&self) -> Option<NonZeroUsize> {
+        self.insertion_index
+    }
+
+    /// Returns true if the slot holds a value.
+    pub fn is_initialized(&self) -> bool {
+        self.insertion_index.is_some()
+    }
+
+    /// Returns a reference to the value, or `None` if the slot is empty.
+    pub fn get(&self) -> Option<&T> {
+        self.as_init().map(|init| &init.value)
+    }
+
+    /// Returns a mutable reference to the value, or `None` if the slot is empty.
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.as_init_mut().map(|init| &mut init.value)
+    }
+}
+
+impl<T> Default for Item<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Item<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Item");
+        debug.field("insertion_index", &self.insertion_index);
+        if let Some(value) = self.get() {
+            debug.field("value", value);
+        }
+        debug.finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for Item<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.insertion_index == other.insertion_index && self.get() == other.get()
+    }
+}
+
+impl<T> Drop for Item<T> {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+impl<T: Clone> Clone for Item<T> {
+    fn clone(&self) -> Self {
+        let mut item = Self::new();
+        if let Some(init) = self.as_init() {
+            item.put(init.insertion_index, init.value.clone());
+        }
+        #[cfg(feature = "stats")]
+        {
+            item.generation = self.generation;
+        }
+        item
+    }
+}
+
+/// An occupied [Item], dereferencing to the value it holds.
+///
+/// Only available by reference, through [Item::as_init()] and friends.
+#[repr(C)]
+pub struct InitializedItem<T> {
+    insertion_index: NonZeroUsize,
+    value: T,
+    #[cfg(feature = "stats")]
+    generation: u32,
+}
+
+impl<T> InitializedItem<T> {
+    /// Returns the insertion index of the value.
+    pub fn insertion_index(&self) -> NonZeroUsize {
+        self.insertion_index
+    }
+
+    /// See [Item::generation()].
+    #[cfg(feature = "stats")]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+impl<T> Deref for InitializedItem<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for InitializedItem<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// Chronological operations on a slice of [Item]s.
+///
+/// ```
+/// use core::num::NonZeroUsize;
+/// use infinity_sampler::{Item, ItemSliceExt};
+///
+/// let mut items = [Item::new(), Item::new(), Item::new()];
+/// items[0].write(NonZeroUsize::new(9).unwrap(), 'c');
+/// items[2].write(NonZeroUsize::new(4).unwrap(), 'a');
+/// items.sort_by_insertion_index();
+///
+/// let values: Vec<char> = items.initialized_prefix().iter().map(|item| **item).collect();
+/// assert_eq!(values, ['a', 'c']);
+/// ```
+pub trait ItemSliceExt<T> {
+    /// Sorts the items by insertion index in place, moving the empty ones to the end.
+    fn sort_by_insertion_index(&mut self);
+
+    /// Returns the leading run of occupied items.
+    fn initialized_prefix(&self) -> &[InitializedItem<T>];
+}
+
+impl<T> ItemSliceExt<T> for [Item<T>] {
+    fn sort_by_insertion_index(&mut self) {
+        self.sort_unstable_by_key(|item| (item.insertion_index.is_none(), item.insertion_index));
+    }
+
+    fn initialized_prefix(&self) -> &[InitializedItem<T>] {
+        let len = self
+            .iter()
+            .position(|item| !item.is_initialized())
+            .unwrap_or(self.len());
+        // SAFETY: the first `len` items are occupied, and an occupied Item<T> has
+        // the layout of InitializedItem<T>, see Item::assume_init()
+        unsafe { core::slice::from_raw_parts(self.as_ptr() as *const InitializedItem<T>, len) }
+    }
+}
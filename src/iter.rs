@@ -0,0 +1,565 @@
+//! Stateful iterators over the insertion index sequence.
+
+use core::fmt;
+use core::iter::FusedIterator;
+
+pub use crate::buf::{OrderedPeekable, ReservoirOrderedIter};
+pub use crate::cached::CachedOrderedIter;
+pub use crate::lut::LutOrderedIter;
+
+/// Indexer state shared by the const-generic and runtime-N indexers.
+#[derive(Clone, PartialEq, Eq)]
+struct IndexerState {
+    idx: usize,
+    left_offset: usize,
+    step: usize,
+    position: usize,
+}
+
+impl IndexerState {
+    const INITIAL: Self = Self {
+        idx: 0,
+        left_offset: 0,
+        step: 1,
+        position: 0,
+    };
+
+    fn at_position(n: usize, position: usize) -> Self {
+        if position < n {
+            return Self {
+                idx: position,
+                left_offset: 0,
+                step: 1,
+                position,
+            };
+        }
+
+        // Every pattern after the initial one stores N/2 values
+        let after_initial = position - n;
+        let pattern = (after_initial / (n / 2)) % n.trailing_zeros() as usize + 1;
+        let in_pattern = after_initial % (n / 2);
+
+        let step = 1 << pattern;
+        let group_len = n / step;
+        let left_offset = step / 2 + in_pattern / group_len;
+        Self {
+            idx: left_offset + (in_pattern % group_len) * step,
+            left_offset,
+            step,
+            position,
+        }
+    }
+
+    #[cfg_attr(feature = "small-code", inline(never))]
+    const fn next_index(&mut self, n: usize) -> usize {
+        let value = self.idx;
+        self.idx += self.step;
+        if self.idx >= n {
+            self.left_offset += 1;
+            if self.left_offset == self.step {
+                self.step *= 2;
+                if self.step > n {
+                    self.step = 2;
+                    self.left_offset = 1;
+                }
+            }
+            self.idx = self.left_offset;
+        }
+        self.position += 1;
+        value
+    }
+
+    fn peek_prev(&self, n: usize) -> Option<usize> {
+        self.clone().prev_index(n)
+    }
+
+    fn prev_index(&mut self, n: usize) -> Option<usize> {
+        if self.position == 0 {
+            return None;
+        }
+        self.position -= 1;
+
+        if self.idx >= self.left_offset + self.step {
+            // Still inside the current group
+            self.idx -= self.step;
+        } else if self.left_offset > self.step / 2 {
+            // Previous group of the same pattern
+            self.left_offset -= 1;
+            self.idx = Self::group_end(n, self.left_offset, self.step);
+        } else if self.step > 2 {
+            // Last group of the previous pattern
+            self.step /= 2;
+            self.left_offset = self.step - 1;
+            self.idx = Self::group_end(n, self.left_offset, self.step);
+        } else if self.position == n - 1 {
+            // Back from pattern 1 into the initial pattern
+            self.step = 1;
+            self.left_offset = 0;
+            self.idx = n - 1;
+        } else {
+            // Back from pattern 1 into the last pattern of the previous loop
+            self.step = n;
+            self.left_offset = n - 1;
+            self.idx = n - 1;
+        }
+        Some(self.idx)
+    }
+
+    /// Returns the last index of the group starting at `left_offset`.
+    fn group_end(n: usize, left_offset: usize, step: usize) -> usize {
+        left_offset + (n - 1 - left_offset) / step * step
+    }
+}
+
+/// # Infinity Sampler Indexer
+///
+/// An infinite iterator over the storage indexes at which a
+/// [SamplingReservoir](crate::SamplingReservoir) writes its accepted values, in order.
+/// See [math](crate::math) for the chart this follows.
+///
+/// The sequence is made of patterns:
+/// * Pattern _0_ visits every index from _0_ to _N-1_ once.
+/// * Pattern _p ≥ 1_ has a step of _2<sup>p</sup>_ and consists of _2<sup>p-1</sup>_ groups.
+///   Group _g_ visits the indexes _2<sup>p-1</sup> + g + k·2<sup>p</sup>_ below _N_.
+/// * After pattern _log<sub>2</sub>N_ the sequence loops back to pattern _1_.
+///
+/// Internally, `left_offset` is the first index of the current group, `step` is the
+/// pattern step and `idx` is the next index to be yielded.
+///
+/// ```
+/// use infinity_sampler::InfinitySamplerIndexer;
+///
+/// let indexes: Vec<_> = InfinitySamplerIndexer::<8>::new().take(20).collect();
+/// assert_eq!(
+///     indexes,
+///     [0, 1, 2, 3, 4, 5, 6, 7, 1, 3, 5, 7, 2, 6, 3, 7, 4, 5, 6, 7]
+/// );
+/// ```
+#[derive(Clone)]
+pub struct InfinitySamplerIndexer<const N: usize> {
+    state: IndexerState,
+}
+
+impl<const N: usize> InfinitySamplerIndexer<N> {
+    /// Panics if `N` is not a power of two.
+    pub const fn new() -> Self {
+        assert!(N > 1);
+        assert!(
+            N.is_power_of_two(),
+            "Buffer capacity must be a power of two"
+        );
+        Self {
+            state: IndexerState::INITIAL,
+        }
+    }
+
+    /// Creates an indexer that behaves as if it had already yielded `position`
+    /// indexes - *O(1)*.
+    pub fn from_position(position: usize) -> Self {
+        let mut indexer = Self::new();
+        indexer.skip_to(position);
+        indexer
+    }
+
+    /// Returns the number of indexes yielded so far.
+    pub fn position(&self) -> usize {
+        self.state.position
+    }
+
+    /// Moves the iterator to an absolute position, as if exactly `position` indexes
+    /// had been yielded since the start - *O(1)*. Can move backwards as well.
+    pub fn skip_to(&mut self, position: usize) {
+        self.state = IndexerState::at_position(N, position);
+    }
+
+    /// Returns the next index and advances the iterator - *O(1)*.
+    ///
+    /// Same as [next()](Iterator::next), but without the `Option`.
+    /// Can be used in const contexts, see [index_table()].
+    pub const fn next_index(&mut self) -> usize {
+        self.state.next_index(N)
+    }
+
+    /// Returns the index the next [next()](Iterator::next) call will return,
+    /// without advancing the iterator - *O(1)*.
+    pub fn peek(&self) -> usize {
+        self.state.idx
+    }
+
+    /// Returns the pattern of the next index, _0_ for the initial pattern.
+    /// See [math](crate::math#patterns-groups-and-loops).
+    pub fn pattern(&self) -> u32 {
+        self.state.step.trailing_zeros()
+    }
+
+    /// Returns the group of the next index within its pattern.
+    /// See [math](crate::math#patterns-groups-and-loops).
+    pub fn group(&self) -> usize {
+        self.state.left_offset - self.state.step / 2
+    }
+
+    /// Returns the number of completed loops over patterns _1..=log<sub>2</sub>N_.
+    /// See [math](crate::math#patterns-groups-and-loops).
+    ///
+    /// ```
+    /// use infinity_sampler::InfinitySamplerIndexer;
+    ///
+    /// // The loop for N=16 is 32 indexes long
+    /// let indexer = InfinitySamplerIndexer::<16>::from_position(16 + 32 + 9);
+    /// assert_eq!(indexer.loop_iteration(), 1);
+    /// assert_eq!((indexer.pattern(), indexer.group()), (2, 0));
+    /// ```
+    pub fn loop_iteration(&self) -> usize {
+        let loop_len = N / 2 * N.trailing_zeros() as usize;
+        self.state.position.saturating_sub(N) / loop_len
+    }
+
+    /// Returns the internal state for persisting, see [from_raw()](Self::from_raw).
+    pub fn to_raw(&self) -> RawIndexerState {
+        RawIndexerState {
+            idx: self.state.idx,
+            left_offset: self.state.left_offset,
+            step: self.state.step,
+            position: self.state.position,
+        }
+    }
+
+    /// Restores an indexer from a state returned by [to_raw()](Self::to_raw).
+    ///
+    /// The state is validated against the sequence rather than trusted: `step` must be
+    /// a power of two not above `N`, `left_offset` must start a group of that step,
+    /// `idx` must lie within that group, and all of them must match `position`.
+    pub fn from_raw(state: RawIndexerState) -> Result<Self, InvalidState> {
+        let RawIndexerState {
+            idx,
+            left_offset,
+            step,
+            position,
+        } = state;
+        if !step.is_power_of_two() || step > N {
+            return Err(InvalidState);
+        }
+        if left_offset >= N || left_offset >= step || (step > 1 && left_offset < step / 2) {
+            return Err(InvalidState);
+        }
+        if idx >= N || idx < left_offset || (idx - left_offset) % step != 0 {
+            return Err(InvalidState);
+        }
+        let state = IndexerState {
+            idx,
+            left_offset,
+            step,
+            position,
+        };
+        if state != IndexerState::at_position(N, position) {
+            return Err(InvalidState);
+        }
+        Ok(Self { state })
+    }
+
+    /// Moves the iterator one position back and returns the index there,
+    /// i.e. the one the last [next()](Iterator::next) call returned - *O(1)*.
+    ///
+    /// Returns `None` at position 0.
+    ///
+    /// ```
+    /// use infinity_sampler::InfinitySamplerIndexer;
+    ///
+    /// let mut indexer = InfinitySamplerIndexer::<4>::from_position(5);
+    /// assert_eq!(indexer.next(), Some(3));
+    /// assert_eq!(indexer.prev(), Some(3));
+    /// assert_eq!(indexer.prev(), Some(1));
+    /// assert_eq!(indexer.position(), 4);
+    /// ```
+    pub fn prev(&mut self) -> Option<usize> {
+        self.state.prev_index(N)
+    }
+
+    /// Turns this into an iterator going back over the already yielded indexes.
+    pub const fn reverse(self) -> ReverseInfinitySamplerIndexer<N> {
+        ReverseInfinitySamplerIndexer { state: self.state }
+    }
+}
+
+impl<const N: usize> Default for InfinitySamplerIndexer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Iterator for InfinitySamplerIndexer<N> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_index())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_to(self.state.position + n);
+        self.next()
+    }
+}
+
+impl<const N: usize> FusedIterator for InfinitySamplerIndexer<N> {}
+
+/// The already yielded part of an [InfinitySamplerIndexer] sequence, in reverse.
+///
+/// Obtained with [InfinitySamplerIndexer::reverse()]. Ends after yielding the very first index.
+///
+/// ```
+/// use infinity_sampler::InfinitySamplerIndexer;
+///
+/// let mut indexer = InfinitySamplerIndexer::<4>::new();
+/// indexer.by_ref().take(6).count();
+/// let mut reverse = indexer.reverse();
+/// assert_eq!(reverse.by_ref().take(2).collect::<Vec<_>>(), [3, 1]);
+///
+/// let mut indexer = reverse.reverse();
+/// assert_eq!(indexer.position(), 4);
+/// assert_eq!(indexer.next(), Some(1));
+/// ```
+#[derive(Clone)]
+pub struct ReverseInfinitySamplerIndexer<const N: usize> {
+    state: IndexerState,
+}
+
+impl<const N: usize> ReverseInfinitySamplerIndexer<N> {
+    /// Creates an exhausted reverse indexer, at position 0.
+    pub const fn new() -> Self {
+        InfinitySamplerIndexer::new().reverse()
+    }
+
+    /// Creates a reverse indexer that yields the first `position` indexes of
+    /// the sequence, last one first - *O(1)*.
+    pub fn from_position(position: usize) -> Self {
+        InfinitySamplerIndexer::from_position(position).reverse()
+    }
+
+    /// Returns the number of indexes left to yield.
+    pub fn position(&self) -> usize {
+        self.state.position
+    }
+
+    /// Returns the index the next [next()](Iterator::next) call will return,
+    /// without advancing the iterator - *O(1)*.
+    pub fn peek(&self) -> Option<usize> {
+        self.state.peek_prev(N)
+    }
+
+    /// Turns this back into a forward iterator at the current position.
+    pub fn reverse(self) -> InfinitySamplerIndexer<N> {
+        InfinitySamplerIndexer { state: self.state }
+    }
+}
+
+impl<const N: usize> Default for ReverseInfinitySamplerIndexer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Iterator for ReverseInfinitySamplerIndexer<N> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.prev_index(N)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.state.position, Some(self.state.position))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for ReverseInfinitySamplerIndexer<N> {}
+
+impl<const N: usize> FusedIterator for ReverseInfinitySamplerIndexer<N> {}
+
+/// Returns the first `LEN` indexes of the [InfinitySamplerIndexer] sequence for an
+/// `N`-slot buffer, for baking into a lookup table at compile time.
+///
+/// Panics if `N` is not a power of two or is above _2<sup>16</sup>_.
+///
+/// ```
+/// use infinity_sampler::iter::index_table;
+///
+/// const TABLE: [u16; 4096] = index_table::<256, 4096>();
+/// assert_eq!(TABLE[255..258], [255, 1, 3]);
+/// ```
+pub const fn index_table<const N: usize, const LEN: usize>() -> [u16; LEN] {
+    assert!(
+        N <= u16::MAX as usize + 1,
+        "Buffer capacity must fit the u16 table"
+    );
+    let mut indexer = InfinitySamplerIndexer::<N>::new();
+    let mut table = [0; LEN];
+    let mut i = 0;
+    while i < LEN {
+        table[i] = indexer.next_index() as u16;
+        i += 1;
+    }
+    table
+}
+
+/// Raw state of an [InfinitySamplerIndexer], for checkpointing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawIndexerState {
+    /// The next index to be yielded.
+    pub idx: usize,
+    /// The first index of the current group.
+    pub left_offset: usize,
+    /// The step of the current pattern.
+    pub step: usize,
+    /// The number of indexes yielded so far.
+    pub position: usize,
+}
+
+/// Returned by [InfinitySamplerIndexer::from_raw()] for an inconsistent state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidState;
+
+impl fmt::Display for InvalidState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("inconsistent sampler state")
+    }
+}
+
+/// Returned when a runtime buffer capacity is not a power of two of at least 2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidCapacity;
+
+impl fmt::Display for InvalidCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("capacity must be a power of two of at least 2")
+    }
+}
+
+/// Same as [InfinitySamplerIndexer], but with the capacity chosen at runtime.
+///
+/// ```
+/// use infinity_sampler::iter::DynInfinitySamplerIndexer;
+///
+/// let indexer = DynInfinitySamplerIndexer::new(4).unwrap();
+/// assert_eq!(indexer.take(8).collect::<Vec<_>>(), [0, 1, 2, 3, 1, 3, 2, 3]);
+/// assert!(DynInfinitySamplerIndexer::new(12).is_err());
+/// ```
+#[derive(Clone)]
+pub struct DynInfinitySamplerIndexer {
+    n: usize,
+    state: IndexerState,
+}
+
+impl DynInfinitySamplerIndexer {
+    pub fn new(n: usize) -> Result<Self, InvalidCapacity> {
+        if n < 2 || !n.is_power_of_two() {
+            return Err(InvalidCapacity);
+        }
+        Ok(Self {
+            n,
+            state: IndexerState::INITIAL,
+        })
+    }
+
+    /// Returns the buffer capacity.
+    pub fn capacity(&self) -> usize {
+        self.n
+    }
+
+    /// Returns the number of indexes yielded so far.
+    pub fn position(&self) -> usize {
+        self.state.position
+    }
+
+    /// See [InfinitySamplerIndexer::skip_to()].
+    pub fn skip_to(&mut self, position: usize) {
+        self.state = IndexerState::at_position(self.n, position);
+    }
+
+    /// See [InfinitySamplerIndexer::next_index()].
+    pub fn next_index(&mut self) -> usize {
+        self.state.next_index(self.n)
+    }
+
+    /// See [InfinitySamplerIndexer::peek()].
+    pub fn peek(&self) -> usize {
+        self.state.idx
+    }
+
+    /// See [InfinitySamplerIndexer::prev()].
+    pub fn prev(&mut self) -> Option<usize> {
+        self.state.prev_index(self.n)
+    }
+
+    /// Turns this into an iterator going back over the already yielded indexes.
+    pub fn reverse(self) -> DynReverseInfinitySamplerIndexer {
+        DynReverseInfinitySamplerIndexer {
+            n: self.n,
+            state: self.state,
+        }
+    }
+}
+
+impl Iterator for DynInfinitySamplerIndexer {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_index())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        self.skip_to(self.state.position + n);
+        self.next()
+    }
+}
+
+impl FusedIterator for DynInfinitySamplerIndexer {}
+
+/// Same as [ReverseInfinitySamplerIndexer], but with the capacity chosen at runtime.
+#[derive(Clone)]
+pub struct DynReverseInfinitySamplerIndexer {
+    n: usize,
+    state: IndexerState,
+}
+
+impl DynReverseInfinitySamplerIndexer {
+    /// Returns the number of indexes left to yield.
+    pub fn position(&self) -> usize {
+        self.state.position
+    }
+
+    /// See [ReverseInfinitySamplerIndexer::peek()].
+    pub fn peek(&self) -> Option<usize> {
+        self.state.peek_prev(self.n)
+    }
+
+    /// Turns this back into a forward iterator at the current position.
+    pub fn reverse(self) -> DynInfinitySamplerIndexer {
+        DynInfinitySamplerIndexer {
+            n: self.n,
+            state: self.state,
+        }
+    }
+}
+
+impl Iterator for DynReverseInfinitySamplerIndexer {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.prev_index(self.n)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.state.position, Some(self.state.position))
+    }
+}
+
+impl ExactSizeIterator for DynReverseInfinitySamplerIndexer {}
+
+impl FusedIterator for DynReverseInfinitySamplerIndexer {}
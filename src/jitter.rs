@@ -0,0 +1,78 @@
+use rand_core::RngCore;
+
+use crate::{SamplingOutcome, SamplingReservoir};
+
+/// A [SamplingReservoir] that takes a random observation out of every acceptance period
+/// instead of the last one, to avoid locking onto periodic structure in the input.
+///
+/// Exactly one observation is still accepted per period of `divisor` observations, so the
+/// values stay in chronological order and each one is within one period of its nominal
+/// position: a stored value the plain reservoir would have taken at observation `i` comes
+/// from one of the observations `i - divisor + 1 ..= i`.
+///
+/// The underlying reservoir stays on the nominal schedule: the accepted value is stored as
+/// if it was observed at the end of its period, so the reservoir counts the rest of the
+/// period right away and its [samples_seen()](SamplingReservoir::samples_seen) runs ahead
+/// until then. The indexes reported by
+/// [ordered_iter_enumerated()](SamplingReservoir::ordered_iter_enumerated) are the nominal
+/// ones. Deterministic for a given RNG seed.
+pub struct JitteredReservoir<T, const N: usize, G> {
+    reservoir: SamplingReservoir<T, N>,
+    rng: G,
+    /// Observations left in the current period after its accepted one,
+    /// already counted by the reservoir.
+    skip: usize,
+    /// The current period has started and its accepted observation hasn't been seen yet.
+    in_period: bool,
+    /// Observations left in the current period before its accepted one.
+    wait: u32,
+}
+
+impl<T, const N: usize, G: RngCore> JitteredReservoir<T, N, G> {
+    /// Creates an empty reservoir.
+    /// Panics if `N` is not a power of two.
+    pub const fn new(rng: G) -> Self {
+        Self {
+            reservoir: SamplingReservoir::new(),
+            rng,
+            skip: 0,
+            in_period: false,
+            wait: 0,
+        }
+    }
+
+    /// Observe a value and possibly store it - *O(1)*.
+    ///
+    /// See [SamplingReservoir::sample()].
+    #[must_use]
+    pub fn sample(&mut self, value: T) -> SamplingOutcome<T> {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return SamplingOutcome::Discarded(value);
+        }
+        if !self.in_period {
+            let period = self.reservoir.sampling_rate().samples_until_next() + 1;
+            // Uniform in 0..period, without a division
+            self.wait = ((self.rng.next_u32() as u64 * period as u64) >> 32) as u32;
+            self.in_period = true;
+        }
+        if self.wait > 0 {
+            // Before the end of the period, so always discarded
+            self.wait -= 1;
+            return self.reservoir.sample(value);
+        }
+        self.in_period = false;
+        self.skip = self.reservoir.skip_discarded();
+        self.reservoir.sample(value)
+    }
+
+    /// Returns the underlying reservoir.
+    pub fn reservoir(&self) -> &SamplingReservoir<T, N> {
+        &self.reservoir
+    }
+
+    /// Consume self and return the underlying reservoir and the RNG.
+    pub fn into_inner(self) -> (SamplingReservoir<T, N>, G) {
+        (self.reservoir, self.rng)
+    }
+}
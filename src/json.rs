@@ -0,0 +1,52 @@
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+
+use crate::export::OrderedValues;
+use crate::rate::{ReservoirRate, SamplingRate};
+use crate::{BufferTooSmall, SamplingReservoir};
+
+/// Compact JSON view of the samples in a [SamplingReservoir], returned by
+/// [json_export()](SamplingReservoir::json_export).
+///
+/// Serializes to `{"seen":1234,"values":[...]}` with the values in chronological order,
+/// written by serde-json-core into a caller-provided buffer without allocating.
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// for i in 0..8 {
+///     let _ = reservoir.sample(i);
+/// }
+/// let mut buf = [0; 64];
+/// let len = reservoir.json_export().to_slice(&mut buf).unwrap();
+/// assert_eq!(&buf[..len], br#"{"seen":8,"values":[0,2,4,6]}"#);
+///
+/// assert!(reservoir.json_export().to_slice(&mut buf[..16]).is_err());
+/// ```
+pub struct JsonOrderedExport<'a, T, const N: usize, R = SamplingRate> {
+    reservoir: &'a SamplingReservoir<T, N, R>,
+}
+
+impl<T: Serialize, const N: usize, R: ReservoirRate> JsonOrderedExport<'_, T, N, R> {
+    /// Writes the JSON into `buf` and returns the number of bytes written.
+    pub fn to_slice(&self, buf: &mut [u8]) -> Result<usize, BufferTooSmall> {
+        serde_json_core::to_slice(self, buf).map_err(|_| BufferTooSmall)
+    }
+}
+
+impl<T: Serialize, const N: usize, R: ReservoirRate> Serialize for JsonOrderedExport<'_, T, N, R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("JsonOrderedExport", 2)?;
+        state.serialize_field("seen", &self.reservoir.samples_seen())?;
+        state.serialize_field("values", &OrderedValues(self.reservoir))?;
+        state.end()
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// Returns a compact JSON view of the samples in chronological order, see [JsonOrderedExport].
+    pub fn json_export(&self) -> JsonOrderedExport<'_, T, N, R> {
+        JsonOrderedExport { reservoir: self }
+    }
+}
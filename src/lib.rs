@@ -1,21 +1,122 @@
 #![no_std]
 #![doc = include_str!("../README.md")]
 
+/// `heapless` re-export, following the `heapless-08` / `heapless-09` feature selection.
+/// `heapless-09` takes precedence, so that `--all-features` builds still work.
+#[cfg(all(feature = "heapless-08", not(feature = "heapless-09")))]
+pub extern crate heapless08 as heapless;
+/// `heapless` re-export, following the `heapless-08` / `heapless-09` feature selection.
+/// `heapless-09` takes precedence, so that `--all-features` builds still work.
+#[cfg(feature = "heapless-09")]
+pub extern crate heapless09 as heapless;
+#[cfg(not(any(feature = "heapless-08", feature = "heapless-09")))]
+compile_error!("either the `heapless-08` or the `heapless-09` feature must be enabled");
+
+#[cfg(feature = "rand_core")]
+mod bernoulli;
+mod budget;
 mod buf;
+mod burst;
+mod bytes;
+mod cached;
+#[cfg(feature = "minicbor")]
+mod cbor;
+mod chain;
+mod csv;
+#[cfg(feature = "embassy")]
+mod embassy;
+#[cfg(any(feature = "serde", feature = "minicbor"))]
+mod export;
+mod ext;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "embedded-storage")]
+mod flash;
+mod indexed;
+mod item;
+pub mod iter;
+#[cfg(feature = "rand_core")]
+mod jitter;
+#[cfg(feature = "serde-json-core")]
+mod json;
+mod lut;
+#[cfg(feature = "bytemuck")]
+mod pod;
+#[cfg(feature = "pool")]
+mod pooled;
+mod pow2;
+#[cfg(feature = "std")]
+mod prometheus;
 mod rate;
+#[cfg(feature = "log")]
+mod rate_limited;
+mod rational;
+mod schedule;
+#[cfg(feature = "cs")]
+mod shared;
+#[cfg(feature = "cs")]
+mod split;
+mod stamped;
+#[cfg(feature = "std")]
+mod std_impls;
+mod timed;
+#[cfg(feature = "tracing")]
+mod tracing_layer;
+#[cfg(feature = "ufmt")]
+mod ufmt_impls;
 
-#[cfg(doc)]
 pub mod math;
 
+#[cfg(feature = "rand_core")]
+pub use bernoulli::BernoulliRate;
+pub use budget::BudgetedRate;
+#[cfg(feature = "strict")]
+pub use buf::InvariantViolation;
 pub use buf::{SamplingOutcome, SamplingReservoir};
-pub use rate::SamplingRate;
+pub use burst::BurstRate;
+pub use bytes::{BufferTooSmall, DecodeError, LeBytes, ReservoirState};
+pub use cached::CachedOrder;
+pub use chain::ChainedRate;
+pub use csv::CsvOptions;
+#[cfg(feature = "embassy")]
+pub use embassy::{drain_channel_into, snapshot, SharedReservoir};
+#[cfg(any(feature = "serde", feature = "minicbor"))]
+pub use export::{OrderedExport, OrderedImport};
+pub use ext::{ReservoirSampleExt, SampleStats};
+pub use indexed::IndexedSamplingReservoir;
+pub use item::{InitializedItem, Item, ItemSliceExt};
+pub use iter::{InfinitySamplerIndexer, ReverseInfinitySamplerIndexer};
+#[cfg(feature = "rand_core")]
+pub use jitter::JitteredReservoir;
+#[cfg(feature = "serde-json-core")]
+pub use json::JsonOrderedExport;
+pub use lut::LutSamplingReservoir;
+#[cfg(feature = "pool")]
+pub use pooled::{ObjectPool, PooledReservoir};
+pub use pow2::PowerOfTwoRate;
+pub use rate::{RateFiltered, ReservoirRate, SamplingRate};
+#[cfg(feature = "log")]
+pub use rate_limited::RateLimitedLog;
+pub use rational::RationalRate;
+pub use schedule::{InvalidSchedule, RateSchedule};
+#[cfg(feature = "cs")]
+pub use shared::SharedSamplingReservoir;
+#[cfg(feature = "cs")]
+pub use split::{Consumer, Producer, SplitReservoir};
+pub use stamped::{InstantLike, Stamped};
+pub use timed::{Now, TimedSamplingRate};
+#[cfg(feature = "tracing")]
+pub use tracing_layer::ReservoirLayer;
+#[cfg(feature = "ufmt")]
+pub use ufmt_impls::OrderedDisplay;
 
-#[cfg(test)]
-#[macro_use]
+#[cfg(any(test, feature = "std"))]
+#[cfg_attr(test, macro_use)]
 extern crate std;
 
 #[cfg(test)]
 mod tests;
 
-/// `heapless` re-export
-pub use heapless;
+#[cfg(feature = "log")]
+#[doc(hidden)]
+pub use log as __log;
@@ -0,0 +1,279 @@
+use core::iter::FusedIterator;
+
+use heapless::Vec;
+
+use crate::buf::SamplingOutcome;
+use crate::iter::InfinitySamplerIndexer;
+use crate::math::reduces_rate_at;
+use crate::rate::{ReservoirRate, SamplingRate};
+use crate::SamplingReservoir;
+
+/// The largest capacity a [LutSamplingReservoir] supports.
+const LUT_MAX_CAPACITY: usize = 64;
+
+/// Length of the repeating part of the insertion sequence for [LUT_MAX_CAPACITY].
+const MAX_LOOP_LEN: usize = 6 * LUT_MAX_CAPACITY / 2;
+
+/// Set on the table entries that reduce the sampling rate before being written.
+const REDUCE_RATE: u8 = 0x80;
+
+/// Builds the repeating part of the insertion sequence, which follows the initial `N` indexes.
+const fn loop_table<const N: usize>() -> [u8; MAX_LOOP_LEN] {
+    assert!(
+        N <= LUT_MAX_CAPACITY,
+        "LutSamplingReservoir capacity must be at most 64"
+    );
+    assert!(N > 1);
+    assert!(
+        N.is_power_of_two(),
+        "Buffer capacity must be a power of two"
+    );
+    let mut indexer = InfinitySamplerIndexer::<N>::new();
+    let mut i = 0;
+    while i < N {
+        indexer.next_index();
+        i += 1;
+    }
+    let mut table = [0; MAX_LOOP_LEN];
+    let loop_len = N.trailing_zeros() as usize * N / 2;
+    let mut i = 0;
+    while i < loop_len {
+        table[i] = indexer.next_index() as u8;
+        if reduces_rate_at(N, N + i) {
+            table[i] |= REDUCE_RATE;
+        }
+        i += 1;
+    }
+    table
+}
+
+/// # Lookup Table Infinity Sampler
+///
+/// Same sampling as [SamplingReservoir](crate::SamplingReservoir) for `N` up to 64, with the
+/// insertion indexes and rate reductions read from a table built at compile time instead
+/// of computed, which keeps [sample()](Self::sample) short and branch-light.
+///
+/// The table takes 192 bytes per `N`. A larger `N` is rejected at compile time:
+///
+/// ```compile_fail
+/// let _ = infinity_sampler::LutSamplingReservoir::<u8, 128>::new();
+/// ```
+///
+/// ```
+/// use infinity_sampler::LutSamplingReservoir;
+///
+/// let mut reservoir = LutSamplingReservoir::<u32, 4>::new();
+/// for i in 0..16 {
+///     let _ = reservoir.sample(i);
+/// }
+/// assert!(reservoir.ordered_iter().eq(&[0, 4, 8, 12]));
+/// ```
+#[derive(Clone)]
+pub struct LutSamplingReservoir<T, const N: usize, R = SamplingRate> {
+    buf: Vec<T, N>,
+    sample_rate: R,
+    /// Position in the table, once the initial pattern is stored.
+    loop_pos: u8,
+    samples_accepted: usize,
+    outer_index: usize,
+}
+
+impl<T, const N: usize, R: ReservoirRate> LutSamplingReservoir<T, N, R> {
+    const TABLE: [u8; MAX_LOOP_LEN] = loop_table::<N>();
+    const LOOP_LEN: u8 = (N.trailing_zeros() as usize * N / 2) as u8;
+
+    /// Creates an empty reservoir.
+    /// Fails to compile if `N` is not a power of two or is above 64.
+    pub const fn new() -> Self {
+        let _ = Self::TABLE;
+        Self {
+            buf: Vec::new(),
+            sample_rate: R::INITIAL,
+            loop_pos: 0,
+            samples_accepted: 0,
+            outer_index: 0,
+        }
+    }
+
+    /// Returns N, the capacity of the internal buffer.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Get the number of currently stored items.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Get a view into the internal storage, in storage order.
+    pub fn as_unordered_slice(&self) -> &[T] {
+        &self.buf
+    }
+
+    /// Returns a reference to the current sampling rate.
+    pub fn sampling_rate(&self) -> &R {
+        &self.sample_rate
+    }
+
+    /// Returns the total number of samples accepted into the buffer since the beginning.
+    ///
+    /// See [SamplingReservoir::samples_accepted()](crate::SamplingReservoir::samples_accepted).
+    pub fn samples_accepted(&self) -> usize {
+        self.samples_accepted
+    }
+
+    #[deprecated(
+        since = "0.3.0",
+        note = "use `samples_accepted()`, or `len()` for the number of stored items"
+    )]
+    pub fn samples_stored(&self) -> usize {
+        self.samples_accepted()
+    }
+
+    /// Returns the total number of samples observed by the sampler since the beginning, up to
+    /// [MAX_SAMPLES_SEEN](crate::SamplingReservoir::MAX_SAMPLES_SEEN).
+    pub fn samples_seen(&self) -> usize {
+        self.outer_index
+    }
+
+    /// Moves the observed count, e.g. close to the ceiling.
+    #[cfg(test)]
+    pub(crate) fn set_samples_seen(&mut self, samples_seen: usize) {
+        self.outer_index = samples_seen;
+    }
+
+    /// Observe a value and possibly store it - *O(1)*.
+    ///
+    /// See [SamplingReservoir::sample()](crate::SamplingReservoir::sample).
+    #[must_use]
+    pub fn sample(&mut self, value: T) -> SamplingOutcome<T> {
+        // Stops at the same ceiling as SamplingReservoir rather than wrapping
+        self.outer_index =
+            (self.outer_index + 1).min(SamplingReservoir::<T, N, R>::MAX_SAMPLES_SEEN);
+        if !self.sample_rate.step() {
+            return SamplingOutcome::Discarded(value);
+        }
+        self.samples_accepted += 1;
+        if let Err(value) = self.buf.push(value) {
+            return self.overwrite(value);
+        }
+        SamplingOutcome::Consumed
+    }
+
+    /// Writes an accepted value once the buffer is full.
+    fn overwrite(&mut self, value: T) -> SamplingOutcome<T> {
+        let entry = Self::TABLE[self.loop_pos as usize];
+        self.loop_pos += 1;
+        if self.loop_pos == Self::LOOP_LEN {
+            self.loop_pos = 0;
+        }
+
+        let mut result = SamplingOutcome::Consumed;
+        if entry & REDUCE_RATE != 0 && !self.sample_rate.is_saturated() {
+            let old_divisor = self.sample_rate.divisor();
+            self.sample_rate.halve();
+            result = SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor: self.sample_rate.divisor(),
+            };
+        }
+        self.buf[(entry & !REDUCE_RATE) as usize] = value;
+        result
+    }
+
+    /// Return an iterator over the items in chronological order - *O(N log N)*.
+    ///
+    /// Walks the table back from the newest write until every stored slot is found,
+    /// which takes at most one loop of the table, as slot 0 is never in it.
+    pub fn ordered_iter(&self) -> LutOrderedIter<'_, T, N> {
+        let len = self.buf.len();
+        // Slot 0 holds the first value for good, so it's the oldest and is left at the front
+        let mut slots = [0; N];
+        let mut found = 1u64;
+        let mut remaining = len;
+        let mut loop_pos = self.loop_pos;
+        let mut position = self.samples_accepted;
+        while remaining > 1 {
+            position -= 1;
+            let slot = if position < N {
+                position as u8
+            } else {
+                loop_pos = loop_pos.checked_sub(1).unwrap_or(Self::LOOP_LEN - 1);
+                Self::TABLE[loop_pos as usize] & !REDUCE_RATE
+            };
+            // Only the newest write to a slot is still stored
+            if found & (1 << slot) == 0 {
+                found |= 1 << slot;
+                remaining -= 1;
+                slots[remaining] = slot;
+            }
+        }
+        LutOrderedIter {
+            buf: &self.buf,
+            slots,
+            pos: 0,
+            end: len,
+        }
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> Default for LutSamplingReservoir<T, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the items of a [LutSamplingReservoir] in chronological order,
+/// returned by [ordered_iter()](LutSamplingReservoir::ordered_iter).
+pub struct LutOrderedIter<'a, T, const N: usize> {
+    buf: &'a [T],
+    slots: [u8; N],
+    pos: usize,
+    end: usize,
+}
+
+// Not derived, as that would require T: Clone
+impl<T, const N: usize> Clone for LutOrderedIter<'_, T, N> {
+    fn clone(&self) -> Self {
+        Self {
+            buf: self.buf,
+            slots: self.slots,
+            pos: self.pos,
+            end: self.end,
+        }
+    }
+}
+
+impl<T, const N: usize> ExactSizeIterator for LutOrderedIter<'_, T, N> {}
+
+impl<T, const N: usize> FusedIterator for LutOrderedIter<'_, T, N> {}
+
+impl<'a, T, const N: usize> Iterator for LutOrderedIter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.end {
+            return None;
+        }
+        self.pos += 1;
+        Some(&self.buf[self.slots[self.pos - 1] as usize])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.end - self.pos, Some(self.end - self.pos))
+    }
+}
+
+impl<T, const N: usize> DoubleEndedIterator for LutOrderedIter<'_, T, N> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.pos == self.end {
+            return None;
+        }
+        self.end -= 1;
+        Some(&self.buf[self.slots[self.end] as usize])
+    }
+}
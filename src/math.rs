@@ -73,3 +73,341 @@
 //! * After 32 items observed: `0 2 4 6 8 10 12 14 16 18 20 22 24 26 28 30`
 //! * After 64 items observed: `0 4 8 12 16 20 24 28 32 36 40 44 48 52 56 60`
 //!
+//! ### Patterns, groups and loops
+//!
+//! [InfinitySamplerIndexer](crate::InfinitySamplerIndexer) reports where it is in the chart:
+//!
+//! * [pattern()](crate::InfinitySamplerIndexer::pattern) is the chart's pattern number. Pattern _p ≥ 1_
+//!   writes with a step of _2<sup>p</sup>_, so it is _log<sub>2</sub>_ of the indexer's step.
+//! * [group()](crate::InfinitySamplerIndexer::group) is the row within the pattern. Group _g_ of
+//!   pattern _p_ starts at index _2<sup>p-1</sup> + g_, i.e. it is the indexer's left offset
+//!   minus half the step. Pattern _0_ has a single group _0_.
+//! * [loop_iteration()](crate::InfinitySamplerIndexer::loop_iteration) counts how many times the
+//!   indexer went all the way from pattern _1_ to pattern _log<sub>2</sub>N_ (marked _repeats_ in the chart).
+//!   Each loop is _N/2 · log<sub>2</sub>N_ indexes long, which is _2N_ for _N=16_.
+//!
+//! All three describe the index that will be yielded next.
+//!
+//! ## Functions
+//!
+//! The functions below answer questions about the schedule analytically,
+//! without constructing a reservoir.
+
+use crate::buf::ReservoirOrderedIndexIter;
+
+const fn assert_capacity<const N: usize>() {
+    check_capacity(N);
+}
+
+const fn check_capacity(n: usize) {
+    assert!(n > 1);
+    assert!(
+        n.is_power_of_two(),
+        "Buffer capacity must be a power of two"
+    );
+}
+
+/// Returns the number of values stored by an `N`-slot reservoir
+/// after observing `samples_seen` values.
+pub(crate) const fn samples_accepted_for<const N: usize>(samples_seen: usize) -> usize {
+    stored_for(N, samples_seen)
+}
+
+/// Returns true if the sampling rate of an `n`-slot reservoir is halved when storing the
+/// value at the zero-based `position`, i.e. at positions N, N + N/2, N + N, ...
+///
+/// This is the one definition of the schedule: the reservoirs consult it on every stored
+/// value, and [rate_reductions_for()] counts it in closed form.
+#[cfg_attr(feature = "small-code", inline(never))]
+#[cfg_attr(not(feature = "small-code"), inline)]
+pub(crate) const fn reduces_rate_at(n: usize, position: usize) -> bool {
+    position >= n && (position - n) & (n / 2 - 1) == 0
+}
+
+/// Returns the number of times the sampling rate of an `N`-slot reservoir has been halved
+/// after storing `samples_accepted` values, i.e. the number of positions below
+/// `samples_accepted` for which [reduces_rate_at()] holds.
+pub(crate) const fn rate_reductions_for<const N: usize>(samples_accepted: usize) -> usize {
+    match samples_accepted {
+        stored if stored > N => (stored - N - 1) / (N / 2) + 1,
+        _ => 0,
+    }
+}
+
+/// Returns the smallest number of observations after which an `N`-slot reservoir
+/// has stored `samples_accepted` values, the inverse of [samples_accepted_for()].
+pub(crate) fn observations_for_stored<const N: usize>(samples_accepted: usize) -> usize {
+    if samples_accepted <= N {
+        return samples_accepted;
+    }
+    // Pattern q stores N/2 values 2^q apart, starting at observation 2^(q-1) * N
+    let k = samples_accepted - N - 1;
+    let q = k / (N / 2) + 1;
+    (N << (q - 1)) + ((k % (N / 2)) << q) + 1
+}
+
+/// Same as [samples_accepted_for()], but with the rate stopping at the terminal divisor
+/// of 2<sup>31</sup> like [SamplingRate](crate::SamplingRate) does, instead of halving forever.
+pub(crate) fn samples_accepted_saturating<const N: usize>(samples_seen: usize) -> usize {
+    // The 31st reduction takes the divisor to 2^31 at position N + 30 * N/2, which is
+    // stored at observation N * 2^30 + 1. From then on, every 2^31-th one is accepted.
+    let saturated_accepted = N + 30 * (N / 2) + 1;
+    match N.checked_mul(1 << 30) {
+        Some(saturated_seen) if samples_seen > saturated_seen => {
+            saturated_accepted + ((samples_seen - saturated_seen - 1) >> 31)
+        }
+        _ => samples_accepted_for::<N>(samples_seen),
+    }
+}
+
+/// Returns a stored count of at most one indexer loop past `N`, after which an `N`-slot
+/// reservoir holds its values in the same storage order as after `samples_accepted` values.
+///
+/// The insertion indexes repeat every loop, while the observation indexes of the stored
+/// values are multiplied by `N`, which is 1 modulo `N - 1` and keeps their storage indexes.
+pub(crate) fn order_equivalent_stored<const N: usize>(samples_accepted: usize) -> usize {
+    let loop_len = N.trailing_zeros() as usize * N / 2;
+    if samples_accepted <= N + loop_len {
+        return samples_accepted;
+    }
+    N + (samples_accepted - N - 1) % loop_len + 1
+}
+
+const fn stored_for(n: usize, samples_seen: usize) -> usize {
+    if samples_seen <= n {
+        return samples_seen;
+    }
+    // Pattern q is the last one whose first observation (2^(q-1) * N) is below samples_seen
+    let q = ((samples_seen - 1) / n).ilog2() as usize + 1;
+    let full_patterns = (q - 1) * (n / 2);
+    let in_last_pattern = (samples_seen - 1) / (1 << q) + 1 - n / 2;
+    let in_last_pattern = if in_last_pattern < n / 2 {
+        in_last_pattern
+    } else {
+        n / 2
+    };
+    n + full_patterns + in_last_pattern
+}
+
+/// Returns the smallest and the largest distance between consecutive samples held by an
+/// `n`-slot reservoir that has observed `samples_seen` values - *O(1)*.
+///
+/// These are the nearest powers of two around _M/N_ mentioned above. Both are equal
+/// when the buffer is perfectly even.
+///
+/// ```
+/// use infinity_sampler::math::stride_bounds;
+///
+/// // See "Buffer contents" above
+/// assert_eq!(stride_bounds(16, 16), (1, 1));
+/// assert_eq!(stride_bounds(16, 50), (2, 4));
+/// assert_eq!(stride_bounds(16, 64), (4, 4));
+/// ```
+pub const fn stride_bounds(n: usize, samples_seen: usize) -> (usize, usize) {
+    check_capacity(n);
+    if samples_seen <= n {
+        return (1, 1);
+    }
+    let step_lower = 1 << ((samples_seen - 1) / (n - 1)).ilog2();
+    if stored_for(n, samples_seen).is_multiple_of(n / 2) {
+        (step_lower, step_lower)
+    } else {
+        (step_lower, step_lower * 2)
+    }
+}
+
+/// Returns the smallest number of observations, not less than `samples_seen`, after which
+/// the samples held by an `n`-slot reservoir are exactly _M/N_ apart, i.e. perfectly evenly
+/// spread over all _M_ observations - *O(1)*.
+///
+/// These are the observation counts _N · 2<sup>k</sup>_ mentioned above.
+///
+/// ```
+/// use infinity_sampler::math::next_even_checkpoint;
+///
+/// assert_eq!(next_even_checkpoint(16, 0), 16);
+/// assert_eq!(next_even_checkpoint(16, 32), 32);
+/// assert_eq!(next_even_checkpoint(16, 33), 64);
+/// ```
+pub const fn next_even_checkpoint(n: usize, samples_seen: usize) -> usize {
+    check_capacity(n);
+    n * samples_seen.div_ceil(n).next_power_of_two()
+}
+
+/// Returns the smallest number of observations after which every pair of consecutive
+/// samples held by an `n`-slot reservoir is at least `coverage` observations apart,
+/// i.e. each sample stands for at least `coverage` observations - *O(1)*.
+///
+/// ```
+/// use infinity_sampler::math::{observations_for_coverage, stride_bounds};
+///
+/// let seen = observations_for_coverage(16, 4);
+/// assert_eq!(seen, 61);
+/// assert_eq!(stride_bounds(16, seen).0, 4);
+/// assert_eq!(stride_bounds(16, seen - 1).0, 2);
+/// ```
+pub const fn observations_for_coverage(n: usize, coverage: usize) -> usize {
+    check_capacity(n);
+    if coverage <= 1 {
+        return 0;
+    }
+    coverage.next_power_of_two() * (n - 1) + 1
+}
+
+/// Returns the observation index of the value stored at `position`,
+/// i.e. of the `position`-th accepted value.
+pub(crate) fn outer_index_for_position<const N: usize>(position: usize) -> usize {
+    if position < N {
+        return position;
+    }
+    let pattern = (position - N) / (N / 2) + 1;
+    let in_pattern = (position - N) % (N / 2);
+    (N / 2 + in_pattern) << pattern
+}
+
+/// Returns the position (the number of values stored before it) of the last write
+/// into `slot` among the first `stored` writes.
+fn last_write_position<const N: usize>(stored: usize, slot: usize) -> Option<usize> {
+    if slot >= stored.min(N) {
+        return None;
+    }
+    if stored <= N || slot == 0 {
+        return Some(slot);
+    }
+    let log_n = N.trailing_zeros() as usize;
+    let after_initial = stored - N;
+    let current = after_initial / (N / 2);
+    let written_in_current = after_initial % (N / 2);
+
+    // Every slot above 0 is written once per pattern p whose bit p-1 is set in the slot
+    // index, so it is found within one loop of patterns
+    let mut pattern_number = current;
+    for _ in 0..=log_n {
+        let pattern = pattern_number % log_n + 1;
+        let step = 1 << pattern;
+        let in_step = slot % step;
+        if in_step >= step / 2 {
+            let group = in_step - step / 2;
+            let offset = group * (N / step) + slot / step;
+            if pattern_number < current || offset < written_in_current {
+                return Some(N + pattern_number * (N / 2) + offset);
+            }
+        }
+        if pattern_number == 0 {
+            break;
+        }
+        pattern_number -= 1;
+    }
+    // Not overwritten since the initial pattern
+    Some(slot)
+}
+
+/// Returns the observation index of the value currently held in storage `slot`
+/// of an `N`-slot reservoir that has observed `samples_seen` values, or `None` if
+/// the slot hasn't been written yet - *O(log N)*.
+///
+/// ```
+/// use infinity_sampler::math::resident_outer_index;
+///
+/// // See "Buffer contents" above: after 32 observations, slot 1 was last written
+/// // with observation 16 in pattern 1
+/// assert_eq!(resident_outer_index::<16>(32, 1), Some(16));
+/// assert_eq!(resident_outer_index::<16>(32, 2), Some(2));
+/// assert_eq!(resident_outer_index::<16>(10, 12), None);
+/// ```
+pub fn resident_outer_index<const N: usize>(samples_seen: usize, slot: usize) -> Option<usize> {
+    assert_capacity::<N>();
+    let stored = samples_accepted_for::<N>(samples_seen);
+    last_write_position::<N>(stored, slot).map(outer_index_for_position::<N>)
+}
+
+/// Returns the observation indexes of the values an `N`-slot reservoir retains after
+/// observing `samples_seen` values, in chronological order.
+///
+/// ```
+/// use infinity_sampler::math::surviving_outer_indices;
+///
+/// let survivors: Vec<_> = surviving_outer_indices::<16>(64).collect();
+/// assert_eq!(
+///     survivors,
+///     [0, 4, 8, 12, 16, 20, 24, 28, 32, 36, 40, 44, 48, 52, 56, 60]
+/// );
+/// ```
+pub fn surviving_outer_indices<const N: usize>(
+    samples_seen: usize,
+) -> impl ExactSizeIterator<Item = usize> {
+    assert_capacity::<N>();
+    let indices =
+        ReservoirOrderedIndexIter::<N>::new(samples_seen, samples_accepted_for::<N>(samples_seen));
+    (0..indices.len()).map(move |pos| indices.outer_index_at(pos))
+}
+
+/// Returns how many times storage `slot` of an `N`-slot reservoir has been written to
+/// after observing `samples_seen` values, counting the initial write - *O(1)*.
+///
+/// Slot _0_ is only written once, in the initial pattern. Any other slot _s_ is written
+/// again in every pattern _p_ for which bit _p-1_ of _s_ is set, i.e. _popcount(s)_ times
+/// per loop. Since every loop multiplies the number of observations by _N_, the write count
+/// of slot _s_ grows as _popcount(s) · log<sub>N</sub>(M/N)_ for _M_ observations: the
+/// highest slot _N-1_ wears fastest, the average slot gets half of that, and the write
+/// count of slots at powers of two grows at the slowest nonzero rate.
+///
+/// ```
+/// use infinity_sampler::math::overwrite_count;
+///
+/// // See "Item insertion indices" above: slot 15 is written in every pattern
+/// assert_eq!(overwrite_count::<16>(256, 15), 5);
+/// assert_eq!(overwrite_count::<16>(256, 8), 2);
+/// assert_eq!(overwrite_count::<16>(256, 0), 1);
+/// assert_eq!(overwrite_count::<16>(10, 12), 0);
+/// ```
+pub fn overwrite_count<const N: usize>(samples_seen: usize, slot: usize) -> usize {
+    assert_capacity::<N>();
+    let stored = samples_accepted_for::<N>(samples_seen);
+    if slot >= stored.min(N) {
+        return 0;
+    }
+    if stored <= N || slot == 0 {
+        return 1;
+    }
+    let log_n = N.trailing_zeros() as usize;
+    let after_initial = stored - N;
+    let full_loops = after_initial / (N / 2 * log_n);
+    let in_loop = after_initial % (N / 2 * log_n);
+    let full_patterns = in_loop / (N / 2);
+    let written_in_current = in_loop % (N / 2);
+
+    let mut count = 1 + full_loops * slot.count_ones() as usize;
+    // Patterns 1..=full_patterns of the current loop
+    count += (slot & ((1 << full_patterns) - 1)).count_ones() as usize;
+    // The pattern currently being written
+    let step = 1 << (full_patterns + 1);
+    let in_step = slot % step;
+    if in_step >= step / 2 {
+        let group = in_step - step / 2;
+        let offset = group * (N / step) + slot / step;
+        if offset < written_in_current {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Returns the position at which the value held in storage `slot` appears in
+/// [ordered_iter()](crate::SamplingReservoir::ordered_iter) of an `N`-slot reservoir that has
+/// observed `samples_seen` values, or `None` if the slot hasn't been written yet - *O(log N)*.
+///
+/// ```
+/// use infinity_sampler::math::chronological_position;
+///
+/// // After 32 observations slot 1 holds observation 16, the 9th oldest value
+/// assert_eq!(chronological_position::<16>(32, 1), Some(8));
+/// assert_eq!(chronological_position::<16>(32, 2), Some(1));
+/// assert_eq!(chronological_position::<16>(10, 12), None);
+/// ```
+pub fn chronological_position<const N: usize>(samples_seen: usize, slot: usize) -> Option<usize> {
+    let outer_index = resident_outer_index::<N>(samples_seen, slot)?;
+    let indices =
+        ReservoirOrderedIndexIter::<N>::new(samples_seen, samples_accepted_for::<N>(samples_seen));
+    Some(indices.position_of_outer_index(outer_index))
+}
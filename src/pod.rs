@@ -0,0 +1,62 @@
+use bytemuck::Pod;
+use heapless::Vec;
+
+use crate::bytes::check_values_len;
+use crate::{DecodeError, ReservoirState, SamplingReservoir};
+
+impl<T: Pod, const N: usize> SamplingReservoir<T, N> {
+    /// Returns the occupied part of the buffer in storage order as raw bytes - *O(1)*,
+    /// for DMA and flash writes without per-value encoding.
+    ///
+    /// Unlike [to_bytes()](SamplingReservoir::to_bytes), the values are in the target's native
+    /// byte order, so they can only be reinterpreted on a target with the same endianness and
+    /// the same layout of `T`. The header written by [write_state_header()](Self::write_state_header)
+    /// is little-endian and records the value size and the capacity, which are checked on load.
+    ///
+    /// The slice is aligned for `T` only; DMA engines requiring a stricter alignment
+    /// need a copy or an over-aligned `T`.
+    ///
+    /// ```
+    /// use infinity_sampler::{ReservoirState, SamplingReservoir};
+    ///
+    /// let mut reservoir = SamplingReservoir::<u16, 8>::new();
+    /// for i in 0..100 {
+    ///     let _ = reservoir.sample(i);
+    /// }
+    /// let mut header = [0; ReservoirState::HEADER_LEN];
+    /// reservoir.write_state_header(&mut header);
+    /// let bytes = reservoir.as_unordered_bytes();
+    ///
+    /// let state = ReservoirState::read_header(&header).unwrap();
+    /// let restored = SamplingReservoir::<u16, 8>::from_pod_bytes(bytes, state).unwrap();
+    /// assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+    /// ```
+    pub fn as_unordered_bytes(&self) -> &[u8] {
+        bytemuck::cast_slice(self.as_unordered_slice())
+    }
+
+    /// Writes the [ReservoirState] header for [as_unordered_bytes()](Self::as_unordered_bytes)
+    /// into `out` and returns the number of bytes written, [ReservoirState::HEADER_LEN].
+    /// Panics if `out` is shorter.
+    pub fn write_state_header(&self, out: &mut [u8]) -> usize {
+        self.state(core::mem::size_of::<T>()).write_header(out);
+        ReservoirState::HEADER_LEN
+    }
+
+    /// Rebuilds a reservoir from the bytes returned by [as_unordered_bytes()](Self::as_unordered_bytes)
+    /// and the state read from its header.
+    ///
+    /// `bytes` doesn't need to be aligned. The state is validated the same way as by
+    /// [from_bytes()](Self::from_bytes), but the values themselves can't be checked,
+    /// see the endianness caveat above.
+    pub fn from_pod_bytes(bytes: &[u8], state: ReservoirState) -> Result<Self, DecodeError> {
+        let size = core::mem::size_of::<T>();
+        let len = state.stored_len::<N>(size)?;
+        check_values_len(bytes, len, size)?;
+
+        let buf: Vec<T, N> = (0..len)
+            .map(|i| bytemuck::pod_read_unaligned(&bytes[i * size..(i + 1) * size]))
+            .collect();
+        Self::from_state(state, buf)
+    }
+}
@@ -0,0 +1,110 @@
+use core::ops::DerefMut;
+
+use crate::{SamplingOutcome, SamplingReservoir};
+
+/// A fixed-size memory pool handing out owning handles to its blocks.
+///
+/// Dropping a handle must return its block to the pool, like `heapless::pool::boxed::Box` does.
+/// A `box_pool!` singleton only needs a forwarding impl:
+///
+/// ```ignore
+/// use heapless::box_pool;
+/// use heapless::pool::boxed::{Box, BoxPool};
+/// use infinity_sampler::ObjectPool;
+///
+/// box_pool!(Thumbnails: [u8; 2048]);
+///
+/// impl ObjectPool for Thumbnails {
+///     type Item = [u8; 2048];
+///     type Handle = Box<Thumbnails>;
+///
+///     fn alloc(&self, value: [u8; 2048]) -> Result<Box<Thumbnails>, [u8; 2048]> {
+///         <Thumbnails as BoxPool>::alloc(value)
+///     }
+/// }
+/// ```
+///
+/// `heapless::pool` itself is only available on targets with LL/SC or on x86,
+/// hence the trait instead of a direct dependency.
+pub trait ObjectPool {
+    /// The type of the pooled objects.
+    type Item;
+    /// An owning handle to a pool block, returning it to the pool on drop.
+    type Handle: DerefMut<Target = Self::Item>;
+
+    /// Moves `value` into a free block, or hands it back if the pool is exhausted.
+    fn alloc(&self, value: Self::Item) -> Result<Self::Handle, Self::Item>;
+}
+
+/// A [SamplingReservoir] that keeps its values in [ObjectPool] blocks instead of inline.
+///
+/// Discarded values never touch the pool, and once the reservoir is full an accepted value
+/// is written into the block of the one it evicts, so at most `N` blocks are taken at a time.
+/// All blocks go back to the pool when the reservoir, or its owning iterator, is dropped.
+pub struct PooledReservoir<P: ObjectPool, const N: usize> {
+    reservoir: SamplingReservoir<P::Handle, N>,
+    pool: P,
+}
+
+impl<P: ObjectPool, const N: usize> PooledReservoir<P, N> {
+    /// Creates an empty reservoir allocating from `pool`.
+    /// Panics if `N` is not a power of two.
+    pub const fn new(pool: P) -> Self {
+        Self {
+            reservoir: SamplingReservoir::new(),
+            pool,
+        }
+    }
+
+    /// Observe a value and possibly store it - *O(1)*.
+    ///
+    /// See [SamplingReservoir::sample()]. If the value is accepted into an empty slot and the
+    /// pool has no free block, it's handed back as `Err` and the observation isn't counted.
+    pub fn sample(&mut self, value: P::Item) -> Result<SamplingOutcome<P::Item>, P::Item> {
+        let accepted = self.reservoir.sampling_rate().samples_until_next() == 0;
+        if accepted && self.reservoir.samples_accepted() < N {
+            let handle = self.pool.alloc(value)?;
+            return Ok(match self.reservoir.sample(handle) {
+                SamplingOutcome::Consumed => SamplingOutcome::Consumed,
+                SamplingOutcome::ConsumedAndRateReduced {
+                    old_divisor,
+                    new_divisor,
+                } => SamplingOutcome::ConsumedAndRateReduced {
+                    old_divisor,
+                    new_divisor,
+                },
+                SamplingOutcome::Discarded(_) => unreachable!(),
+            });
+        }
+        Ok(match self.reservoir.observe() {
+            Some((insert_index, outcome)) => {
+                // Reuse the evicted value's block
+                **self.reservoir.slot_mut(insert_index) = value;
+                outcome
+            }
+            None => SamplingOutcome::Discarded(value),
+        })
+    }
+
+    /// Return an iterator over the stored values in chronological order - *O(1)*.
+    pub fn ordered_iter(&self) -> impl Iterator<Item = &P::Item> + '_ {
+        self.reservoir.ordered_iter().map(|handle| &**handle)
+    }
+
+    /// Consume self and return the handles in chronological order.
+    ///
+    /// Handles not taken from the iterator are returned to the pool when it's dropped.
+    pub fn into_ordered_iter(self) -> impl Iterator<Item = P::Handle> {
+        self.reservoir.into_ordered_iter()
+    }
+
+    /// Returns the underlying reservoir of handles.
+    pub fn reservoir(&self) -> &SamplingReservoir<P::Handle, N> {
+        &self.reservoir
+    }
+
+    /// Returns the pool.
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+}
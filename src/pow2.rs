@@ -0,0 +1,82 @@
+use crate::rate::ReservoirRate;
+
+/// A sampler whose divisor is always a power of two, stored as its log2.
+///
+/// Equivalent to a [SamplingRate](crate::SamplingRate) that is only ever divided by two,
+/// but keeps the divisor as a `u8` shift, and keeps halving up to a divisor of `2^63` rather
+/// than stopping at `2^31`.
+///
+/// ```
+/// use infinity_sampler::{PowerOfTwoRate, SamplingReservoir};
+///
+/// let mut sampler = PowerOfTwoRate::new(1);
+/// assert_eq!(sampler.divisor(), 2);
+/// assert_eq!(sampler.step(), false);
+/// assert_eq!(sampler.step(), true);
+///
+/// let mut reservoir = SamplingReservoir::<u32, 8, PowerOfTwoRate>::new();
+/// for i in 0..256 {
+///     let _ = reservoir.sample(i);
+/// }
+/// assert_eq!(reservoir.sampling_rate().divisor(), 32);
+/// ```
+#[derive(Copy, Clone)]
+pub struct PowerOfTwoRate {
+    shift: u8,
+    counter: u64,
+}
+
+impl PowerOfTwoRate {
+    /// The largest supported shift.
+    pub const MAX_SHIFT: u8 = 63;
+
+    /// Creates a rate with a divisor of `2^shift`.
+    /// Panics if `shift` exceeds [MAX_SHIFT](Self::MAX_SHIFT).
+    pub const fn new(shift: u8) -> Self {
+        assert!(shift <= Self::MAX_SHIFT);
+        Self { shift, counter: 0 }
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        self.counter = (self.counter + 1) & self.mask();
+        self.counter == 0
+    }
+
+    /// Halves the sampling rate. Saturates at [MAX_SHIFT](Self::MAX_SHIFT).
+    pub fn halve(&mut self) {
+        self.shift = (self.shift + 1).min(Self::MAX_SHIFT);
+    }
+
+    pub fn shift(&self) -> u8 {
+        self.shift
+    }
+
+    pub fn divisor(&self) -> u64 {
+        1 << self.shift
+    }
+
+    fn mask(&self) -> u64 {
+        self.divisor() - 1
+    }
+}
+
+impl ReservoirRate for PowerOfTwoRate {
+    const INITIAL: Self = Self::new(0);
+
+    fn step(&mut self) -> bool {
+        self.step()
+    }
+
+    fn halve(&mut self) {
+        self.halve();
+    }
+
+    fn divisor(&self) -> u64 {
+        self.divisor()
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.shift == Self::MAX_SHIFT
+    }
+}
@@ -0,0 +1,91 @@
+use core::fmt::{self, Display};
+use std::io::{self, Write};
+use std::vec::Vec;
+
+use crate::rate::ReservoirRate;
+use crate::SamplingReservoir;
+
+/// The quantiles reported by [SamplingReservoir::prometheus_text()].
+const QUANTILES: [f64; 5] = [0.0, 0.5, 0.9, 0.99, 1.0];
+
+impl<T: Copy + Into<f64>, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// Writes a summary of the stored values in the Prometheus text exposition format - *O(N log N)*.
+    ///
+    /// Emits a `summary` with the 0, 0.5, 0.9, 0.99 and 1 quantiles (nearest rank) and the
+    /// `_sum` and `_count` of the stored values, then the `_min`, `_max` and `_samples_seen`
+    /// gauges. Characters not allowed in metric names are replaced with `_`.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingReservoir;
+    ///
+    /// let mut reservoir = SamplingReservoir::<u16, 4>::new();
+    /// for i in 1..=8 {
+    ///     let _ = reservoir.sample(i * 10);
+    /// }
+    /// let mut out = Vec::new();
+    /// reservoir.prometheus_text("latency.ms", &mut out).unwrap();
+    /// let text = String::from_utf8(out).unwrap();
+    /// assert!(text.starts_with("# TYPE latency_ms summary\n"));
+    /// assert!(text.contains("latency_ms{quantile=\"0.5\"} 30\n"));
+    /// assert!(text.contains("latency_ms_count 4\n"));
+    /// ```
+    pub fn prometheus_text(&self, name: &str, w: &mut impl Write) -> io::Result<()> {
+        let name = MetricName(name);
+        let mut values: Vec<f64> = self.ordered_iter().map(|&value| value.into()).collect();
+        values.sort_unstable_by(f64::total_cmp);
+        let sum: f64 = values.iter().sum();
+
+        writeln!(w, "# TYPE {} summary", name)?;
+        for &quantile in &QUANTILES {
+            // Nearest rank
+            let rank = (quantile * values.len() as f64).ceil() as usize;
+            let value = values.get(rank.max(1) - 1).copied().unwrap_or(f64::NAN);
+            writeln!(w, "{}{{quantile=\"{}\"}} {}", name, quantile, Sample(value))?;
+        }
+        writeln!(w, "{}_sum {}", name, Sample(sum))?;
+        writeln!(w, "{}_count {}", name, values.len())?;
+
+        let min = values.first().copied().unwrap_or(f64::NAN);
+        let max = values.last().copied().unwrap_or(f64::NAN);
+        writeln!(w, "# TYPE {}_min gauge", name)?;
+        writeln!(w, "{}_min {}", name, Sample(min))?;
+        writeln!(w, "# TYPE {}_max gauge", name)?;
+        writeln!(w, "{}_max {}", name, Sample(max))?;
+        writeln!(w, "# TYPE {}_samples_seen gauge", name)?;
+        writeln!(w, "{}_samples_seen {}", name, self.samples_seen())
+    }
+}
+
+/// A metric name with the characters outside of `[a-zA-Z0-9_:]` replaced with `_`,
+/// prefixed with `_` if it would start with a digit.
+struct MetricName<'a>(&'a str);
+
+impl Display for MetricName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() || self.0.starts_with(|c: char| c.is_ascii_digit()) {
+            f.write_str("_")?;
+        }
+        for c in self.0.chars() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == ':' {
+                write!(f, "{}", c)?;
+            } else {
+                f.write_str("_")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A sample value, with the exposition format spelling of the special values.
+struct Sample(f64);
+
+impl Display for Sample {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            value if value.is_nan() => f.write_str("NaN"),
+            f64::INFINITY => f.write_str("+Inf"),
+            f64::NEG_INFINITY => f.write_str("-Inf"),
+            value => write!(f, "{}", value),
+        }
+    }
+}
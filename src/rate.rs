@@ -1,4 +1,29 @@
-use core::hint::unreachable_unchecked;
+use core::borrow::BorrowMut;
+use core::fmt;
+use core::iter::{Fuse, FusedIterator};
+
+/// A rate representation that can drive a [SamplingReservoir](crate::SamplingReservoir).
+///
+/// The reservoir's index math relies on the exact accept pattern of a
+/// [SamplingRate] starting at divisor 1 and halved by the reservoir, so implementors
+/// must accept exactly the same steps - only the representation may differ.
+pub trait ReservoirRate {
+    /// A rate accepting every step, with the phase counter at zero.
+    const INITIAL: Self;
+
+    /// Returns true if the sampler should sample.
+    fn step(&mut self) -> bool;
+
+    /// Halves the sampling rate, keeping the phase counter.
+    /// Does nothing once the rate [is saturated](Self::is_saturated).
+    fn halve(&mut self);
+
+    /// Returns the current divisor.
+    fn divisor(&self) -> u64;
+
+    /// Returns true once the divisor can't be doubled any further.
+    fn is_saturated(&self) -> bool;
+}
 
 /// A simple sampler.
 ///
@@ -20,11 +45,35 @@ use core::hint::unreachable_unchecked;
 /// assert_eq!(sampler.step(), false);
 /// assert_eq!(sampler.step(), false);
 /// assert_eq!(sampler.step(), true);
+/// ```
 ///
-#[derive(Copy, Clone)]
+/// The phase can be inspected through [counter()](Self::counter) and
+/// [samples_until_next()](Self::samples_until_next):
+///
+/// ```
+/// use infinity_sampler::SamplingRate;
+///
+/// let mut sampler = SamplingRate::new(3);
+/// assert_eq!(sampler.counter(), 0);
+/// assert_eq!(sampler.samples_until_next(), 2);
+///
+/// assert_eq!(sampler.step(), false);
+/// assert_eq!(sampler.counter(), 1);
+/// assert_eq!(sampler.samples_until_next(), 1);
+///
+/// assert_eq!(sampler.step(), false);
+/// assert_eq!(sampler.samples_until_next(), 0);
+/// assert_eq!(sampler.step(), true);
+/// assert_eq!(sampler.counter(), 0);
+/// assert_eq!(sampler, SamplingRate::new(3));
+/// ```
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct SamplingRate {
     divisor: u32,
-    counter: u32,
+    /// Steps left until the next accepted one, including it, in `1..=divisor`.
+    /// The phase [counter()](Self::counter) is `divisor - remaining`.
+    remaining: u32,
+    pending_ratio: u32,
 }
 
 impl SamplingRate {
@@ -32,27 +81,258 @@ impl SamplingRate {
         assert!(divisor > 0);
         Self {
             divisor,
-            counter: 0,
+            remaining: divisor,
+            pending_ratio: 1,
         }
     }
 
     /// Returns true if the sampler should sample.
+    #[must_use]
     pub fn step(&mut self) -> bool {
-        if self.divisor == 0 {
-            unsafe { unreachable_unchecked() };
+        self.remaining -= 1;
+        if self.remaining != 0 {
+            return false;
+        }
+        if self.pending_ratio != 1 {
+            self.divisor *= self.pending_ratio;
+            self.pending_ratio = 1;
+        }
+        self.remaining = self.divisor;
+        true
+    }
+
+    /// Advances the rate by `n` steps at once - *O(1)*.
+    ///
+    /// Returns how many of those steps would have been accepted and leaves the
+    /// counter exactly as if [step()](Self::step) had been called `n` times.
+    pub fn step_n(&mut self, n: u32) -> u32 {
+        if n < self.remaining {
+            self.remaining -= n;
+            return 0;
+        }
+        let rest = n - self.remaining;
+        if self.pending_ratio != 1 {
+            self.divisor *= self.pending_ratio;
+            self.pending_ratio = 1;
+        }
+        self.remaining = self.divisor - rest % self.divisor;
+        1 + rest / self.divisor
+    }
+
+    /// Returns how many steps will be discarded before the next accepted one.
+    pub fn samples_until_next(&self) -> u32 {
+        self.remaining - 1
+    }
+
+    /// Returns how many further [step()](Self::step) calls, including the next one,
+    /// it takes to reach the next accepted step. Always at least 1.
+    pub fn distance_to_next_sample(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Skips all steps that would be discarded before the next accepted one - *O(1)*,
+    /// so that the next [step()](Self::step) returns true.
+    ///
+    /// Returns the number of skipped steps.
+    pub fn advance_to_next_sample(&mut self) -> u32 {
+        let skipped = self.remaining - 1;
+        self.remaining = 1;
+        skipped
+    }
+
+    /// Consumes the rate and returns an iterator yielding only the elements of `iter`
+    /// whose step was accepted.
+    ///
+    /// ```
+    /// use infinity_sampler::SamplingRate;
+    ///
+    /// let frames: Vec<_> = SamplingRate::new(8).filter_iter(0..32).collect();
+    /// assert_eq!(frames, [7, 15, 23, 31]);
+    /// ```
+    pub fn filter_iter<I: IntoIterator>(self, iter: I) -> RateFiltered<I::IntoIter> {
+        RateFiltered {
+            iter: iter.into_iter().fuse(),
+            rate: self,
+        }
+    }
+
+    /// Same as [filter_iter()](Self::filter_iter), but borrows the rate so that it
+    /// remains usable (and keeps its phase) afterwards.
+    pub fn filter_iter_by_ref<I: IntoIterator>(
+        &mut self,
+        iter: I,
+    ) -> RateFiltered<I::IntoIter, &mut Self> {
+        RateFiltered {
+            iter: iter.into_iter().fuse(),
+            rate: self,
         }
-        self.counter += 1;
-        self.counter %= self.divisor;
-        self.counter == 0
     }
 
     /// Reduce the sampling rate by a ratio.
+    ///
+    /// Takes effect immediately, keeping the phase [counter()](Self::counter):
+    /// the current period is extended to the new divisor.
+    ///
+    /// Panics if the divisor, including a pending ratio, would overflow a `u32`.
     pub fn div(&mut self, ratio: u32) {
         assert!(ratio > 0);
-        self.divisor *= ratio;
+        let old_divisor = self.divisor;
+        self.divisor = checked_divisor(self.divisor, ratio, self.pending_ratio);
+        self.remaining += self.divisor - old_divisor;
+    }
+
+    /// Reduce the sampling rate by a ratio, starting from the next period.
+    ///
+    /// Unlike [div()](Self::div), the current period is still evaluated against
+    /// the old divisor and the change is applied right after the next accepted step,
+    /// so the accept pattern never has an irregular gap. Repeated calls accumulate.
+    ///
+    /// Calling [div()](Self::div) immediately after an accepted step has the same effect,
+    /// which is what [SamplingReservoir](crate::SamplingReservoir) does.
+    ///
+    /// Panics if the divisor, including the pending ratio, would overflow a `u32`.
+    pub fn div_deferred(&mut self, ratio: u32) {
+        assert!(ratio > 0);
+        checked_divisor(self.divisor, ratio, self.pending_ratio);
+        self.pending_ratio *= ratio;
+    }
+
+    /// Returns the ratio recorded by [div_deferred()](Self::div_deferred) that
+    /// hasn't been applied yet, if any.
+    pub fn pending_ratio(&self) -> Option<u32> {
+        match self.pending_ratio {
+            1 => None,
+            ratio => Some(ratio),
+        }
     }
 
     pub fn divisor(&self) -> u32 {
         self.divisor
     }
+
+    /// Returns the current phase counter, which is always in `0..divisor`.
+    ///
+    /// This is the number of steps taken since the last accepted one (or since
+    /// construction or [reset()](Self::reset)). It is incremented by every
+    /// [step()](Self::step) and wraps to zero on the accepted step, so a step is
+    /// accepted when the counter *before* it equals `divisor - 1`.
+    pub fn counter(&self) -> u32 {
+        self.divisor - self.remaining
+    }
+
+    /// Resets the phase counter to zero.
+    ///
+    /// After a reset, the first accepted step is the `divisor`-th call to
+    /// [step()](Self::step), exactly as for a freshly constructed rate.
+    pub fn reset(&mut self) {
+        self.remaining = self.divisor;
+    }
+
+    /// Sets the phase counter. The next accepted step will be the `divisor - phase`-th
+    /// call to [step()](Self::step).
+    ///
+    /// Panics if `phase` is not less than the divisor.
+    pub const fn set_phase(&mut self, phase: u32) {
+        assert!(phase < self.divisor, "Phase must be less than the divisor");
+        self.remaining = self.divisor - phase;
+    }
 }
+
+/// Returns `divisor * ratio`, checking that applying `pending_ratio` on top of it
+/// can't overflow either, so that the divisor never wraps to zero.
+fn checked_divisor(divisor: u32, ratio: u32, pending_ratio: u32) -> u32 {
+    match divisor.checked_mul(ratio) {
+        Some(divisor) if divisor.checked_mul(pending_ratio).is_some() => divisor,
+        _ => panic!("Divisor must fit in a u32"),
+    }
+}
+
+// Not derived, to show the phase counter rather than the countdown
+impl fmt::Debug for SamplingRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SamplingRate")
+            .field("divisor", &self.divisor)
+            .field("counter", &self.counter())
+            .field("pending_ratio", &self.pending_ratio)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for SamplingRate {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "SamplingRate {{ divisor: {=u32}, counter: {=u32}, pending_ratio: {=u32} }}",
+            self.divisor,
+            self.counter(),
+            self.pending_ratio
+        )
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for SamplingRate {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let divisor = u.int_in_range(1..=u32::MAX)?;
+        let counter = u.int_in_range(0..=divisor - 1)?;
+        let pending_ratio = u.int_in_range(1..=u32::MAX / divisor)?;
+        Ok(Self {
+            divisor,
+            remaining: divisor - counter,
+            pending_ratio,
+        })
+    }
+}
+
+impl ReservoirRate for SamplingRate {
+    const INITIAL: Self = Self::new(1);
+
+    fn step(&mut self) -> bool {
+        self.step()
+    }
+
+    fn halve(&mut self) {
+        if !self.is_saturated() {
+            self.div(2);
+        }
+    }
+
+    fn divisor(&self) -> u64 {
+        self.divisor as u64
+    }
+
+    fn is_saturated(&self) -> bool {
+        self.divisor > u32::MAX / 2
+    }
+}
+
+/// An iterator that only yields the elements accepted by a [SamplingRate].
+///
+/// Created by [SamplingRate::filter_iter()] and [SamplingRate::filter_iter_by_ref()].
+#[derive(Clone)]
+pub struct RateFiltered<I, R = SamplingRate> {
+    iter: Fuse<I>,
+    rate: R,
+}
+
+impl<I: Iterator, R: BorrowMut<SamplingRate>> Iterator for RateFiltered<I, R> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let rate = self.rate.borrow_mut();
+        self.iter.by_ref().find(|_| rate.step())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let rate = self.rate.borrow();
+        let (_, upper) = self.iter.size_hint();
+        let upper = upper.map(|upper| {
+            let accepted = (upper as u64 + rate.counter() as u64) / rate.divisor() as u64;
+            accepted as usize
+        });
+        (0, upper)
+    }
+}
+
+impl<I: Iterator, R: BorrowMut<SamplingRate>> FusedIterator for RateFiltered<I, R> {}
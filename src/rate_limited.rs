@@ -0,0 +1,90 @@
+use crate::SamplingRate;
+
+/// Lets through every `divisor`-th call, starting with the first one, and counts
+/// the suppressed ones in between.
+///
+/// Meant for hot log statements, see [sampled_log!](crate::sampled_log).
+///
+/// ```
+/// use infinity_sampler::RateLimitedLog;
+///
+/// let mut limiter = RateLimitedLog::new(3);
+/// let mut emitted = Vec::new();
+/// for i in 0..7 {
+///     limiter.maybe_with_suppressed(|suppressed| emitted.push((i, suppressed)));
+/// }
+/// assert_eq!(emitted, [(0, 0), (3, 2), (6, 2)]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct RateLimitedLog {
+    rate: SamplingRate,
+    suppressed: u32,
+}
+
+impl RateLimitedLog {
+    /// Creates a limiter letting through every `divisor`-th call.
+    /// Panics if `divisor` is zero.
+    pub const fn new(divisor: u32) -> Self {
+        let mut rate = SamplingRate::new(divisor);
+        // Let the first call through
+        rate.set_phase(divisor - 1);
+        Self {
+            rate,
+            suppressed: 0,
+        }
+    }
+
+    /// Calls `f` if this call is let through.
+    #[inline]
+    pub fn maybe<F: FnOnce()>(&mut self, f: F) {
+        self.maybe_with_suppressed(|_| f())
+    }
+
+    /// Calls `f` with the number of calls suppressed since the last one let through,
+    /// if this call is let through.
+    #[inline]
+    pub fn maybe_with_suppressed<F: FnOnce(u32)>(&mut self, f: F) {
+        if self.rate.divisor() == 1 {
+            return f(0);
+        }
+        if self.rate.step() {
+            f(core::mem::take(&mut self.suppressed));
+        } else {
+            self.suppressed = self.suppressed.saturating_add(1);
+        }
+    }
+
+    /// Returns the number of calls suppressed since the last one let through.
+    pub fn suppressed(&self) -> u32 {
+        self.suppressed
+    }
+}
+
+/// Logs through the `log` crate at the given level if the [RateLimitedLog] lets the call
+/// through, appending the number of suppressed messages if there were any.
+///
+/// ```
+/// use infinity_sampler::{sampled_log, RateLimitedLog};
+///
+/// let mut limiter = RateLimitedLog::new(100);
+/// for i in 0..1000 {
+///     // Logs "queue full: 0", then "queue full: 100 (and 99 suppressed)", ...
+///     sampled_log!(limiter, warn, "queue full: {}", i);
+/// }
+/// ```
+#[macro_export]
+macro_rules! sampled_log {
+    ($limiter:expr, $level:ident, $($arg:tt)+) => {
+        $limiter.maybe_with_suppressed(|suppressed| {
+            if suppressed == 0 {
+                $crate::__log::$level!($($arg)+);
+            } else {
+                $crate::__log::$level!(
+                    "{} (and {} suppressed)",
+                    ::core::format_args!($($arg)+),
+                    suppressed
+                );
+            }
+        })
+    };
+}
@@ -0,0 +1,61 @@
+/// A sampler accepting exactly `num` out of every `den` consecutive steps.
+///
+/// Uses Bresenham-style error accumulation, so the accepted steps are spread as
+/// evenly as possible: the gap between two accepted steps is always either
+/// `⌊den/num⌋` or `⌈den/num⌉`.
+///
+/// ```
+/// use infinity_sampler::RationalRate;
+///
+/// let mut sampler = RationalRate::new(2, 5);
+/// let accepted: Vec<_> = (0..10).map(|_| sampler.step()).collect();
+/// assert_eq!(
+///     accepted,
+///     [false, false, true, false, true, false, false, true, false, true]
+/// );
+/// ```
+#[derive(Copy, Clone)]
+pub struct RationalRate {
+    num: u32,
+    den: u32,
+    error: u32,
+}
+
+impl RationalRate {
+    /// Panics unless `0 < num <= den`.
+    pub const fn new(num: u32, den: u32) -> Self {
+        assert!(num > 0);
+        assert!(num <= den);
+        Self { num, den, error: 0 }
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        // Equivalent to `error += num; error >= den`, without overflowing
+        let headroom = self.den - self.num;
+        if self.error >= headroom {
+            self.error -= headroom;
+            true
+        } else {
+            self.error += self.num;
+            false
+        }
+    }
+
+    /// Reduce the sampling rate by a ratio, multiplying the denominator.
+    ///
+    /// The accumulated error is scaled as well, so the current phase is preserved.
+    pub fn div(&mut self, ratio: u32) {
+        assert!(ratio > 0);
+        self.den *= ratio;
+        self.error *= ratio;
+    }
+
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+
+    pub fn den(&self) -> u32 {
+        self.den
+    }
+}
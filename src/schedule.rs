@@ -0,0 +1,100 @@
+use core::fmt;
+use heapless::Vec;
+
+use crate::SamplingRate;
+
+/// A [SamplingRate] following a preprogrammed sequence of divisor changes.
+///
+/// Each breakpoint `(after_observations, divisor)` switches to `divisor` starting
+/// from the observation with that (zero-based) index, restarting the phase, so the
+/// first accepted observation after the switch is `after_observations + divisor - 1`.
+///
+/// ```
+/// use infinity_sampler::RateSchedule;
+///
+/// let mut schedule = RateSchedule::<2>::new(1, &[(4, 2), (8, 4)]).unwrap();
+/// let accepted: Vec<_> = (0..16).filter(|_| schedule.step()).collect();
+/// assert_eq!(accepted, [0, 1, 2, 3, 5, 7, 11, 15]);
+/// ```
+#[derive(Clone)]
+pub struct RateSchedule<const B: usize> {
+    breakpoints: Vec<(usize, u32), B>,
+    next_breakpoint: usize,
+    observed: usize,
+    rate: SamplingRate,
+}
+
+/// Returned by [RateSchedule::new()] for an invalid list of breakpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidSchedule {
+    /// Breakpoint observation counts are not strictly increasing.
+    NotIncreasing,
+    /// A divisor is zero.
+    ZeroDivisor,
+    /// There are more breakpoints than the schedule capacity.
+    TooManyBreakpoints,
+}
+
+impl fmt::Display for InvalidSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NotIncreasing => "breakpoints are not strictly increasing",
+            Self::ZeroDivisor => "divisor is zero",
+            Self::TooManyBreakpoints => "too many breakpoints",
+        })
+    }
+}
+
+impl<const B: usize> RateSchedule<B> {
+    /// Creates a schedule starting at `initial_divisor` and switching at the given breakpoints.
+    pub fn new(
+        initial_divisor: u32,
+        breakpoints: &[(usize, u32)],
+    ) -> Result<Self, InvalidSchedule> {
+        if initial_divisor == 0 || breakpoints.iter().any(|(_, divisor)| *divisor == 0) {
+            return Err(InvalidSchedule::ZeroDivisor);
+        }
+        if breakpoints.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err(InvalidSchedule::NotIncreasing);
+        }
+        Ok(Self {
+            breakpoints: Vec::from_slice(breakpoints)
+                .map_err(|_| InvalidSchedule::TooManyBreakpoints)?,
+            next_breakpoint: 0,
+            observed: 0,
+            rate: SamplingRate::new(initial_divisor),
+        })
+    }
+
+    /// Returns true if the sampler should sample.
+    pub fn step(&mut self) -> bool {
+        if let Some((after, divisor)) = self.breakpoints.get(self.next_breakpoint) {
+            if *after == self.observed {
+                self.rate = SamplingRate::new(*divisor);
+                self.next_breakpoint += 1;
+            }
+        }
+        self.observed += 1;
+        self.rate.step()
+    }
+
+    /// Reduce the current sampling rate by a ratio.
+    /// The next breakpoint overrides this.
+    pub fn div(&mut self, ratio: u32) {
+        self.rate.div(ratio);
+    }
+
+    pub fn divisor(&self) -> u32 {
+        self.rate.divisor()
+    }
+
+    /// Returns the number of steps taken so far.
+    pub fn observed(&self) -> usize {
+        self.observed
+    }
+
+    /// Returns a reference to the currently active rate.
+    pub fn current_rate(&self) -> &SamplingRate {
+        &self.rate
+    }
+}
@@ -0,0 +1,84 @@
+use core::cell::RefCell;
+
+use critical_section::Mutex;
+use heapless::Vec;
+
+use crate::{ReservoirRate, SamplingOutcome, SamplingRate, SamplingReservoir};
+
+/// A [SamplingReservoir] behind a critical-section mutex, to be kept in a `static`
+/// and sampled from interrupt handlers, other threads or other cores.
+///
+/// Every method runs inside [critical_section::with()], so a `critical-section`
+/// implementation must be linked in, e.g. the single-core one of `cortex-m`, which only
+/// toggles PRIMASK. On top of that, a method only costs the borrow flag check of a
+/// [RefCell]. The critical section is held for *O(1)* by [sample()](Self::sample),
+/// *O(N)* by [snapshot_ordered()](Self::snapshot_ordered) and for the whole closure
+/// passed to [with()](Self::with), which adds to the interrupt latency.
+///
+/// Calling a method of the same reservoir from within [with()](Self::with) panics.
+///
+/// For a single interrupt handler feeding a single reader,
+/// [SplitReservoir](crate::SplitReservoir) avoids the mutex altogether.
+///
+/// ```
+/// use infinity_sampler::SharedSamplingReservoir;
+///
+/// static RESERVOIR: SharedSamplingReservoir<u32, 4> = SharedSamplingReservoir::new();
+///
+/// // e.g. in an interrupt handler
+/// for i in 0..16 {
+///     let _ = RESERVOIR.sample(i);
+/// }
+///
+/// assert_eq!(RESERVOIR.snapshot_ordered(), [0, 4, 8, 12]);
+/// assert_eq!(RESERVOIR.with(|reservoir| reservoir.samples_seen()), 16);
+/// ```
+pub struct SharedSamplingReservoir<T, const N: usize, R = SamplingRate> {
+    inner: Mutex<RefCell<SamplingReservoir<T, N, R>>>,
+}
+
+impl<T, const N: usize, R: ReservoirRate> SharedSamplingReservoir<T, N, R> {
+    /// Creates an empty reservoir.
+    /// Fails to compile if `N` is not a power of two.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(SamplingReservoir::new())),
+        }
+    }
+
+    /// Observe a value and possibly store it - *O(1)*, within a critical section.
+    ///
+    /// See [SamplingReservoir::sample()]. A discarded value is returned and dropped outside
+    /// the critical section, an overwritten one is dropped inside it.
+    #[must_use]
+    pub fn sample(&self, value: T) -> SamplingOutcome<T> {
+        self.with(|reservoir| reservoir.sample(value))
+    }
+
+    /// Runs `f` on the reservoir within a critical section.
+    ///
+    /// Keep `f` short, interrupts are masked until it returns.
+    pub fn with<U>(&self, f: impl FnOnce(&mut SamplingReservoir<T, N, R>) -> U) -> U {
+        critical_section::with(|cs| f(&mut self.inner.borrow_ref_mut(cs)))
+    }
+
+    /// Returns a copy of the stored values in chronological order - *O(N)*,
+    /// within a critical section.
+    pub fn snapshot_ordered(&self) -> Vec<T, N>
+    where
+        T: Clone,
+    {
+        self.with(|reservoir| reservoir.ordered_iter().cloned().collect())
+    }
+
+    /// Consumes the wrapper and returns the reservoir.
+    pub fn into_inner(self) -> SamplingReservoir<T, N, R> {
+        self.inner.into_inner().into_inner()
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> Default for SharedSamplingReservoir<T, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
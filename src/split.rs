@@ -0,0 +1,153 @@
+use core::cell::{Cell, UnsafeCell};
+
+use heapless::Vec;
+
+use crate::{ReservoirRate, SamplingOutcome, SamplingRate, SamplingReservoir};
+
+/// A [SamplingReservoir] to be split into a [Producer] that samples values and a [Consumer]
+/// that reads them, e.g. to sample from an interrupt handler and read from the main loop.
+///
+/// Every call on either handle runs inside [critical_section::with()], so they can
+/// be moved to different execution contexts as long as a `critical-section`
+/// implementation is linked in, e.g. the single-core one of `cortex-m`. The critical
+/// section is held for *O(1)* by [Producer::sample()] and *O(N)* by
+/// [Consumer::snapshot_ordered()], which adds to the interrupt latency.
+///
+/// A handle used while the other one is in the middle of a call, e.g. from the `Clone`
+/// or `Drop` of a value, panics.
+///
+/// To share a reservoir with an interrupt handler, keep it in a `static` and split it
+/// once, the same way as [heapless::spsc::Queue::split()].
+///
+/// ```
+/// use infinity_sampler::SplitReservoir;
+///
+/// let mut reservoir = SplitReservoir::<u32, 4>::new();
+/// let (mut producer, consumer) = reservoir.split();
+/// std::thread::scope(|s| {
+///     s.spawn(move || {
+///         for i in 0..16 {
+///             let _ = producer.sample(i);
+///         }
+///     });
+/// });
+/// assert_eq!(consumer.samples_seen(), 16);
+/// assert_eq!(consumer.snapshot_ordered(), [0, 4, 8, 12]);
+/// ```
+pub struct SplitReservoir<T, const N: usize, R = SamplingRate> {
+    reservoir: UnsafeCell<SamplingReservoir<T, N, R>>,
+    /// Set while a handle accesses the reservoir.
+    busy: Cell<bool>,
+}
+
+impl<T, const N: usize, R: ReservoirRate> SplitReservoir<T, N, R> {
+    /// Creates an empty reservoir.
+    /// Fails to compile if `N` is not a power of two.
+    pub const fn new() -> Self {
+        Self::from_reservoir(SamplingReservoir::new())
+    }
+
+    /// Wraps an existing reservoir.
+    pub const fn from_reservoir(reservoir: SamplingReservoir<T, N, R>) -> Self {
+        Self {
+            reservoir: UnsafeCell::new(reservoir),
+            busy: Cell::new(false),
+        }
+    }
+
+    /// Splits the reservoir into its two handles.
+    pub fn split(&mut self) -> (Producer<'_, T, N, R>, Consumer<'_, T, N, R>) {
+        let shared = &*self;
+        (Producer { shared }, Consumer { shared })
+    }
+
+    /// Consumes the wrapper and returns the reservoir.
+    pub fn into_inner(self) -> SamplingReservoir<T, N, R> {
+        self.reservoir.into_inner()
+    }
+
+    /// Runs `f` on the reservoir within a critical section, unless a handle already does.
+    fn with<U>(&self, f: impl FnOnce(&mut SamplingReservoir<T, N, R>) -> U) -> U {
+        critical_section::with(|_| {
+            assert!(!self.busy.get(), "reservoir accessed reentrantly");
+            self.busy.set(true);
+            // Cleared on unwind too, so that a panicking value doesn't poison the handles
+            let _busy = ClearOnDrop(&self.busy);
+            // SAFETY: the critical section excludes the other contexts and the flag
+            // excludes a reentrant call from within `f`
+            f(unsafe { &mut *self.reservoir.get() })
+        })
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> Default for SplitReservoir<T, N, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct ClearOnDrop<'a>(&'a Cell<bool>);
+
+impl Drop for ClearOnDrop<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+/// The writing half of a [SplitReservoir].
+pub struct Producer<'a, T, const N: usize, R = SamplingRate> {
+    shared: &'a SplitReservoir<T, N, R>,
+}
+
+// SAFETY: the reservoir and the flag are only accessed within a critical section
+unsafe impl<T: Send, const N: usize, R: Send> Send for Producer<'_, T, N, R> {}
+
+impl<T, const N: usize, R: ReservoirRate> Producer<'_, T, N, R> {
+    /// Observe a value and possibly store it - *O(1)*, within a critical section.
+    ///
+    /// See [SamplingReservoir::sample()]. A discarded value is returned and dropped outside
+    /// the critical section, an overwritten one is dropped inside it.
+    #[must_use]
+    pub fn sample(&mut self, value: T) -> SamplingOutcome<T> {
+        self.shared.with(|reservoir| reservoir.sample(value))
+    }
+}
+
+/// The reading half of a [SplitReservoir].
+pub struct Consumer<'a, T, const N: usize, R = SamplingRate> {
+    shared: &'a SplitReservoir<T, N, R>,
+}
+
+// SAFETY: the reservoir and the flag are only accessed within a critical section
+unsafe impl<T: Send, const N: usize, R: Send> Send for Consumer<'_, T, N, R> {}
+
+impl<T, const N: usize, R: ReservoirRate> Consumer<'_, T, N, R> {
+    /// Get the number of currently stored items.
+    pub fn len(&self) -> usize {
+        self.shared.with(|reservoir| reservoir.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shared.with(|reservoir| reservoir.is_empty())
+    }
+
+    /// See [SamplingReservoir::samples_seen()].
+    pub fn samples_seen(&self) -> usize {
+        self.shared.with(|reservoir| reservoir.samples_seen())
+    }
+
+    /// See [SamplingReservoir::samples_accepted()].
+    pub fn samples_accepted(&self) -> usize {
+        self.shared.with(|reservoir| reservoir.samples_accepted())
+    }
+
+    /// Returns a copy of the stored values in chronological order - *O(N)*,
+    /// within a critical section.
+    pub fn snapshot_ordered(&self) -> Vec<T, N>
+    where
+        T: Clone,
+    {
+        self.shared
+            .with(|reservoir| reservoir.ordered_iter().cloned().collect())
+    }
+}
@@ -0,0 +1,93 @@
+use crate::rate::ReservoirRate;
+use crate::{SamplingOutcome, SamplingReservoir};
+
+/// A point in time usable with [Stamped] values.
+///
+/// Implemented for `u32` and `u64` tick counters and, with the `fugit` feature,
+/// for `fugit::Instant`. The counters are allowed to wrap around, as long as less than
+/// a full range of ticks passes between the compared instants.
+pub trait InstantLike: Copy + Ord {
+    /// The difference between two instants.
+    type Duration;
+
+    /// Returns the time elapsed from `earlier` to `self`.
+    fn duration_since(&self, earlier: Self) -> Self::Duration;
+}
+
+impl InstantLike for u32 {
+    type Duration = u32;
+
+    fn duration_since(&self, earlier: Self) -> u32 {
+        self.wrapping_sub(earlier)
+    }
+}
+
+impl InstantLike for u64 {
+    type Duration = u64;
+
+    fn duration_since(&self, earlier: Self) -> u64 {
+        self.wrapping_sub(earlier)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> InstantLike for fugit::Instant<u32, NOM, DENOM> {
+    type Duration = fugit::Duration<u32, NOM, DENOM>;
+
+    fn duration_since(&self, earlier: Self) -> Self::Duration {
+        fugit::Duration::<u32, NOM, DENOM>::from_ticks(self.ticks().wrapping_sub(earlier.ticks()))
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> InstantLike for fugit::Instant<u64, NOM, DENOM> {
+    type Duration = fugit::Duration<u64, NOM, DENOM>;
+
+    fn duration_since(&self, earlier: Self) -> Self::Duration {
+        fugit::Duration::<u64, NOM, DENOM>::from_ticks(self.ticks().wrapping_sub(earlier.ticks()))
+    }
+}
+
+/// A value together with the instant it was observed at.
+///
+/// ```
+/// use infinity_sampler::{SamplingReservoir, Stamped};
+///
+/// let mut reservoir = SamplingReservoir::<Stamped<f32, u64>, 8>::new();
+/// for ms in (1_000..2_000).step_by(10) {
+///     let _ = reservoir.sample_at(ms, 0.5);
+/// }
+/// assert_eq!(reservoir.duration_covered(), Some(960));
+/// assert_eq!(reservoir.newest_age(2_500), Some(540));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamped<T, I> {
+    /// The instant the value was observed at.
+    pub at: I,
+    /// The observed value.
+    pub value: T,
+}
+
+impl<T, I: InstantLike, const N: usize, R: ReservoirRate> SamplingReservoir<Stamped<T, I>, N, R> {
+    /// Observes a value stamped with `now`, see [sample()](Self::sample).
+    #[must_use]
+    pub fn sample_at(&mut self, now: I, value: T) -> SamplingOutcome<Stamped<T, I>> {
+        self.sample(Stamped { at: now, value })
+    }
+
+    /// Returns the time between the oldest and the newest stored value,
+    /// or `None` if the reservoir is empty.
+    pub fn duration_covered(&self) -> Option<I::Duration> {
+        let mut iter = self.ordered_iter();
+        let oldest = iter.next()?;
+        let newest = iter.next_back().unwrap_or(oldest);
+        Some(newest.at.duration_since(oldest.at))
+    }
+
+    /// Returns the time elapsed from the newest stored value to `now`,
+    /// or `None` if the reservoir is empty.
+    pub fn newest_age(&self, now: I) -> Option<I::Duration> {
+        let newest = self.ordered_iter().next_back()?;
+        Some(now.duration_since(newest.at))
+    }
+}
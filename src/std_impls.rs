@@ -0,0 +1,19 @@
+use std::vec::Vec;
+
+use crate::iter::{InvalidCapacity, InvalidState};
+use crate::{BufferTooSmall, DecodeError, InvalidSchedule, ReservoirRate, SamplingReservoir};
+
+impl std::error::Error for InvalidState {}
+impl std::error::Error for InvalidCapacity {}
+impl std::error::Error for InvalidSchedule {}
+impl std::error::Error for BufferTooSmall {}
+impl std::error::Error for DecodeError {}
+#[cfg(feature = "strict")]
+impl std::error::Error for crate::InvariantViolation {}
+
+/// Collects the items in chronological order.
+impl<T, const N: usize, R: ReservoirRate> From<SamplingReservoir<T, N, R>> for Vec<T> {
+    fn from(reservoir: SamplingReservoir<T, N, R>) -> Self {
+        reservoir.into_ordered_iter().collect()
+    }
+}
@@ -13,7 +13,7 @@ fn storage_idx_n_8() {
     let indices = (0..256)
         .into_iter()
         .filter(|x| SamplingReservoir::<u32, 8>::should_sample(*x))
-        .map(|x| SamplingReservoir::<u32, 8>::storage_index_for_outer_index(x))
+        .map(SamplingReservoir::<u32, 8>::storage_index_for_outer_index)
         .collect::<Vec<_>>();
 
     assert_eq!(
@@ -27,7 +27,7 @@ fn storage_idx_n_16() {
     let indices = (0..256)
         .into_iter()
         .filter(|x| SamplingReservoir::<u32, 16>::should_sample(*x))
-        .map(|x| SamplingReservoir::<u32, 16>::storage_index_for_outer_index(x))
+        .map(SamplingReservoir::<u32, 16>::storage_index_for_outer_index)
         .collect::<Vec<_>>();
 
     assert_eq!(
@@ -43,13 +43,9 @@ fn storage_idx_n_16() {
 fn insertion_sampling_sm() {
     let mut buf = SamplingReservoir::<u32, 8>::new();
     for i in 0..32 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
-    let mut inner = buf
-        .as_unordered_slice()
-        .into_iter()
-        .map(|x| *x)
-        .collect::<Vec<_>>();
+    let mut inner = buf.as_unordered_slice().to_vec();
     inner.sort();
     assert_eq!(&inner[..], &[0, 4, 8, 12, 16, 20, 24, 28]);
 }
@@ -58,13 +54,9 @@ fn insertion_sampling_sm() {
 fn insertion_sampling_lg() {
     let mut buf = SamplingReservoir::<u32, 16>::new();
     for i in 0..32 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
-    let mut inner = buf
-        .as_unordered_slice()
-        .into_iter()
-        .map(|x| *x)
-        .collect::<Vec<_>>();
+    let mut inner = buf.as_unordered_slice().to_vec();
     inner.sort();
     assert_eq!(
         &inner[..],
@@ -73,13 +65,9 @@ fn insertion_sampling_lg() {
 
     let mut buf = SamplingReservoir::<u32, 16>::new();
     for i in 0..64 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
-    let mut inner = buf
-        .as_unordered_slice()
-        .into_iter()
-        .map(|x| *x)
-        .collect::<Vec<_>>();
+    let mut inner = buf.as_unordered_slice().to_vec();
     inner.sort();
     assert_eq!(
         &inner[..],
@@ -88,13 +76,9 @@ fn insertion_sampling_lg() {
 
     let mut buf = SamplingReservoir::<u32, 16>::new();
     for i in 0..256 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
-    let mut inner = buf
-        .as_unordered_slice()
-        .into_iter()
-        .map(|x| *x)
-        .collect::<Vec<_>>();
+    let mut inner = buf.as_unordered_slice().to_vec();
     inner.sort();
     assert_eq!(
         &inner[..],
@@ -102,6 +86,28 @@ fn insertion_sampling_lg() {
     );
 }
 
+#[test]
+fn should_sample_matches_sample() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<(), N>::new();
+        for i in 0..observations {
+            let expected = SamplingReservoir::<(), N>::should_sample(i);
+            assert_eq!(buf.sample(()).is_consumed(), expected, "N={} i={}", N, i);
+            // Dropping the log2(N) most significant bits leaves only zeros
+            let significant_bits = usize::BITS - i.leading_zeros();
+            let counter_bits = significant_bits.saturating_sub(N.trailing_zeros());
+            assert_eq!(i & ((1 << counter_bits) - 1) == 0, expected);
+        }
+    }
+    let observations = if cfg!(miri) { 2_000 } else { 1 << 20 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+    check::<1024>(observations);
+}
+
 #[test]
 fn sample_rate() {
     let mut buf = SamplingReservoir::<usize, 16>::new();
@@ -113,11 +119,72 @@ fn sample_rate() {
     }
 }
 
+#[test]
+fn rate_reductions() {
+    fn check<const N: usize>() {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        let mut reductions = Vec::new();
+        for i in 0..N << 8 {
+            if let SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            } = buf.sample(i)
+            {
+                assert_eq!(new_divisor, buf.sampling_rate().divisor() as u64);
+                reductions.push((i, old_divisor, new_divisor));
+            }
+        }
+        // The rate halves when observation N * 2^k is stored
+        let expected = (0..8).map(|k| (N << k, 1 << k, 2 << k)).collect::<Vec<_>>();
+        assert_eq!(reductions, expected);
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+fn two_slot_schedule() {
+    // N/2 = 1, so the rate halves with every value stored into a full buffer
+    // and the accepted observations are 0, 1, 2, 4, 8, ...
+    let mut buf = SamplingReservoir::<usize, 2>::new();
+    let mut lut = LutSamplingReservoir::<usize, 2>::new();
+    for i in 0..600 {
+        let outcome = buf.sample(i);
+        assert_eq!(outcome.is_consumed(), i < 2 || i.is_power_of_two());
+        assert_eq!(
+            matches!(outcome, SamplingOutcome::ConsumedAndRateReduced { .. }),
+            i >= 2 && i.is_power_of_two()
+        );
+        let _ = lut.sample(i);
+
+        let newest = match i {
+            0 | 1 => i,
+            _ => 1 << i.ilog2(),
+        };
+        assert_eq!(buf.sampling_rate().divisor() as usize, newest.max(1));
+        let expected = match newest {
+            0 => vec![0],
+            // Evenly spaced at the current divisor
+            _ => vec![0, newest],
+        };
+        assert!(buf.ordered_iter().copied().eq(expected.iter().copied()));
+        assert!(lut.ordered_iter().copied().eq(expected.iter().copied()));
+        // The reported observation indexes are the values themselves
+        assert!(buf
+            .ordered_iter_enumerated()
+            .map(|(seen, &v)| (seen, v))
+            .eq(expected.iter().map(|&v| (v, v))));
+    }
+    buf.debug_validate().unwrap();
+}
+
 #[test]
 fn e2e_full() {
     let mut buf = SamplingReservoir::<u32, 8>::new();
     for i in 0..256 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
     let result = buf.into_ordered_iter().collect::<Vec<_>>();
     assert_eq!(&result[..], &[0, 32, 64, 96, 128, 160, 192, 224]);
@@ -127,7 +194,7 @@ fn e2e_full() {
 fn e2e_partial_fill() {
     let mut buf = SamplingReservoir::<u32, 8>::new();
     for i in 0..4 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
     let result = buf.into_ordered_iter().collect::<Vec<_>>();
     assert_eq!(&result[..], &[0, 1, 2, 3]);
@@ -137,7 +204,7 @@ fn e2e_partial_fill() {
 fn e2e_mid_loop() {
     let mut buf = SamplingReservoir::<u32, 16>::new();
     for i in 0..50 {
-        buf.sample(i);
+        let _ = buf.sample(i);
     }
     let result = buf.into_ordered_iter().collect::<Vec<_>>();
     assert_eq!(
@@ -151,10 +218,10 @@ fn e2e_fuzz() {
     for i in 1..100 {
         let mut buf = SamplingReservoir::<u32, 16>::new();
         for j in 0..i {
-            buf.sample(j);
+            let _ = buf.sample(j);
         }
         let result = buf.into_ordered_iter().collect::<Vec<_>>();
-        let mut sorted = result.iter().copied().collect::<Vec<_>>();
+        let mut sorted = result.clone();
         sorted.sort();
         assert_eq!(result, sorted);
     }
@@ -166,9 +233,4734 @@ fn leak_test() {
     for i in 1..100 {
         let mut buf = SamplingReservoir::<Vec<u8>, 16>::new();
         for _ in 0..i {
-            buf.sample(vec![0]);
+            let _ = buf.sample(vec![0]);
         }
 
         let _ = buf.clone().into_ordered_iter().collect::<Vec<_>>();
+
+        // Owning iterators dropped early, from both ends
+        drop(buf.clone().into_ordered_iter());
+        let mut iter = buf.clone().into_ordered_iter();
+        let _ = iter.next();
+        drop(iter);
+        let _ = buf.clone().into_ordered_iter().last();
+        let _ = buf.clone().into_ordered_iter().nth(i / 3);
+
+        // Clones outliving and outlived by the original
+        let clone = buf.clone();
+        let _ = buf.sample(vec![1]);
+        assert!(clone.ordered_iter().all(|value| value == &[0]));
+        let _ = buf.clone().into_inner();
+        drop(buf);
+        let mut clone_of_clone = clone.clone();
+        drop(clone);
+        let _ = clone_of_clone.sample(vec![2]);
+    }
+}
+
+#[test]
+fn rate_reset_first_accept() {
+    let mut rate = SamplingRate::new(4);
+    assert!(!rate.step());
+    assert!(!rate.step());
+    rate.reset();
+    assert_eq!(rate.counter(), 0);
+    let accepted = (0..8).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(
+        accepted,
+        [false, false, false, true, false, false, false, true]
+    );
+}
+
+#[test]
+fn rate_set_phase() {
+    let mut rate = SamplingRate::new(4);
+    rate.set_phase(3);
+    assert_eq!(rate.counter(), 3);
+    assert!(rate.step());
+    assert_eq!(rate.counter(), 0);
+
+    for divisor in 1..10 {
+        for phase in 0..divisor {
+            let mut a = SamplingRate::new(divisor);
+            let mut b = SamplingRate::new(divisor);
+            // Bring `a` into the phase by stepping and `b` by setting it directly
+            for _ in 0..phase {
+                assert!(!a.step());
+            }
+            b.set_phase(phase);
+            assert_eq!(a.counter(), b.counter());
+            for _ in 0..100 {
+                assert_eq!(a.step(), b.step());
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic(expected = "Phase must be less than the divisor")]
+fn rate_set_phase_out_of_range() {
+    SamplingRate::new(4).set_phase(4);
+}
+
+#[test]
+#[should_panic(expected = "Phase must be less than the divisor")]
+fn rate_set_phase_above_divisor() {
+    SamplingRate::new(4).set_phase(u32::MAX);
+}
+
+#[test]
+#[should_panic(expected = "Divisor must fit in a u32")]
+fn rate_div_overflow() {
+    let mut rate = SamplingRate::new(1 << 16);
+    rate.div(1 << 16);
+}
+
+#[test]
+#[should_panic(expected = "Divisor must fit in a u32")]
+fn rate_div_deferred_overflow() {
+    let mut rate = SamplingRate::new(1 << 16);
+    rate.div_deferred(1 << 8);
+    rate.div_deferred(1 << 8);
+}
+
+#[test]
+#[should_panic(expected = "Divisor must fit in a u32")]
+fn rate_div_overflow_with_pending() {
+    let mut rate = SamplingRate::new(1 << 16);
+    rate.div_deferred(1 << 8);
+    rate.div(1 << 8);
+}
+
+#[test]
+fn rate_div_deferred() {
+    let mut rate = SamplingRate::new(2);
+    assert!(!rate.step());
+    rate.div_deferred(2);
+    assert_eq!(rate.pending_ratio(), Some(2));
+    assert_eq!(rate.divisor(), 2);
+
+    // The current period completes with the old divisor
+    assert!(rate.step());
+    assert_eq!(rate.pending_ratio(), None);
+    assert_eq!(rate.divisor(), 4);
+
+    let accepted = (0..8).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(
+        accepted,
+        [false, false, false, true, false, false, false, true]
+    );
+}
+
+#[test]
+fn rate_div_deferred_no_gap() {
+    for divisor in 1..8 {
+        for phase in 0..divisor {
+            let mut rate = SamplingRate::new(divisor);
+            rate.set_phase(phase);
+            rate.div_deferred(2);
+            rate.div_deferred(3);
+
+            let accepted = (1..=200u32).filter(|_| rate.step()).collect::<Vec<_>>();
+            let first = divisor - phase;
+            assert_eq!(accepted[0], first);
+            for (i, pair) in accepted.windows(2).enumerate() {
+                assert_eq!(pair[1] - pair[0], divisor * 6, "gap #{}", i);
+            }
+        }
+    }
+}
+
+#[test]
+fn rate_step_n() {
+    for divisor in 1..12 {
+        for phase in 0..divisor {
+            for n in 0..40 {
+                for deferred in [1, 2, 3] {
+                    let mut naive = SamplingRate::new(divisor);
+                    naive.set_phase(phase);
+                    naive.div_deferred(deferred);
+                    let mut fast = naive;
+
+                    let expected = (0..n).filter(|_| naive.step()).count() as u32;
+                    assert_eq!(fast.step_n(n), expected);
+                    assert_eq!(fast.counter(), naive.counter());
+                    assert_eq!(fast.divisor(), naive.divisor());
+                    assert_eq!(fast.pending_ratio(), naive.pending_ratio());
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn rate_filter_iter() {
+    for divisor in 1..8 {
+        let mut rate = SamplingRate::new(divisor);
+        let expected = (0..100).filter(|_| rate.step()).collect::<Vec<_>>();
+        let filtered = SamplingRate::new(divisor)
+            .filter_iter(0..100)
+            .collect::<Vec<_>>();
+        assert_eq!(filtered, expected);
+    }
+}
+
+#[test]
+fn rate_filter_iter_by_ref() {
+    let mut rate = SamplingRate::new(3);
+    let first = rate.filter_iter_by_ref(0..5).collect::<Vec<_>>();
+    assert_eq!(first, [2]);
+    assert_eq!(rate.counter(), 2);
+
+    // The phase carries over into the next batch
+    let second = rate.filter_iter_by_ref(5..10).collect::<Vec<_>>();
+    assert_eq!(second, [5, 8]);
+}
+
+#[test]
+fn rate_filter_iter_size_hint() {
+    let filtered = SamplingRate::new(4).filter_iter(0..100);
+    assert_eq!(filtered.size_hint(), (0, Some(25)));
+
+    let mut rate = SamplingRate::new(4);
+    rate.set_phase(3);
+    let filtered = rate.filter_iter(0..5);
+    assert_eq!(filtered.size_hint(), (0, Some(2)));
+    assert_eq!(filtered.count(), 2);
+
+    let filtered = SamplingRate::new(4).filter_iter(0..);
+    assert_eq!(filtered.size_hint(), (0, None));
+}
+
+#[test]
+fn rate_filter_iter_fused() {
+    let mut filtered = SamplingRate::new(2).filter_iter(0..4);
+    assert_eq!(filtered.next(), Some(1));
+    assert_eq!(filtered.next(), Some(3));
+    for _ in 0..3 {
+        assert_eq!(filtered.next(), None);
+    }
+}
+
+#[test]
+fn timed_rate_irregular_clock() {
+    use core::cell::Cell;
+
+    let time = Cell::new(0u64);
+    let mut rate = TimedSamplingRate::new(|| time.get(), 100);
+
+    let mut accepted = Vec::new();
+    for t in [0, 10, 99, 100, 150, 199, 260, 261, 1000, 1050, 1099, 1100] {
+        time.set(t);
+        if rate.step() {
+            accepted.push(t);
+        }
+    }
+    assert_eq!(accepted, [0, 100, 260, 1000, 1100]);
+}
+
+#[test]
+fn timed_rate_clock_wrap() {
+    use core::cell::Cell;
+
+    let time = Cell::new(u64::MAX - 50);
+    let mut rate = TimedSamplingRate::new(|| time.get(), 100);
+    assert!(rate.step());
+
+    time.set(u64::MAX);
+    assert!(!rate.step());
+    time.set(48);
+    assert!(!rate.step());
+    time.set(49);
+    assert!(rate.step());
+    assert_eq!(rate.last_accepted(), Some(49));
+}
+
+#[test]
+fn timed_rate_div() {
+    use core::cell::Cell;
+
+    let time = Cell::new(0u64);
+    let mut rate = TimedSamplingRate::new(|| time.get(), 10);
+    assert!(rate.step());
+    rate.div(2);
+    assert_eq!(rate.interval(), 20);
+    time.set(10);
+    assert!(!rate.step());
+    time.set(20);
+    assert!(rate.step());
+    rate.div(u32::MAX);
+    rate.div(u32::MAX);
+    rate.div(u32::MAX);
+    assert_eq!(rate.interval(), u64::MAX);
+}
+
+#[test]
+fn sample_timed() {
+    use core::cell::Cell;
+
+    let time = Cell::new(0u64);
+    let mut rate = TimedSamplingRate::new(|| time.get(), 10);
+    let mut buf = SamplingReservoir::<u64, 4>::new();
+    for t in 0..100 {
+        time.set(t);
+        let _ = buf.sample_timed(t, &mut rate);
+    }
+    assert_eq!(buf.samples_seen(), 10);
+    let result = buf.into_ordered_iter().collect::<Vec<_>>();
+    assert_eq!(result, [0, 40, 60, 80]);
+}
+
+/// SplitMix64, a minimal seeded RNG for the `rand_core`-gated tests
+#[cfg(feature = "rand_core")]
+struct TestRng(u64);
+
+#[cfg(feature = "rand_core")]
+impl rand_core::RngCore for TestRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        rand_core::impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "rand_core")]
+#[test]
+fn bernoulli_rate_fraction() {
+    let steps = if cfg!(miri) { 2_000 } else { 200_000 };
+    let mut rate = BernoulliRate::new(TestRng(42), 1);
+    assert!((0..100).all(|_| rate.step()));
+
+    for divisor in [2, 4, 8] {
+        rate.div(2);
+        assert_eq!(rate.divisor(), divisor);
+        let accepted = (0..steps).filter(|_| rate.step()).count();
+        let expected = steps / divisor as usize;
+        assert!(
+            accepted.abs_diff(expected) < expected / 10,
+            "divisor {}: accepted {} of {}",
+            divisor,
+            accepted,
+            steps
+        );
+    }
+}
+
+#[cfg(feature = "rand_core")]
+#[test]
+fn bernoulli_rate_deterministic() {
+    let mut a = BernoulliRate::new(TestRng(7), 3);
+    let mut b = BernoulliRate::new(TestRng(7), 3);
+    for _ in 0..1000 {
+        assert_eq!(a.step(), b.step());
+    }
+
+    let mut c = BernoulliRate::new(TestRng(8), 3);
+    let a = (0..1000).map(|_| a.step()).collect::<Vec<_>>();
+    let c = (0..1000).map(|_| c.step()).collect::<Vec<_>>();
+    assert_ne!(a, c);
+}
+
+#[test]
+fn rational_rate_window_counts() {
+    for den in 1..40 {
+        for num in 1..=den {
+            let mut rate = RationalRate::new(num, den);
+            let accepted = (0..den * 4).map(|_| rate.step()).collect::<Vec<_>>();
+
+            // Exactly `num` accepted in every window of `den` consecutive steps
+            for window in accepted.windows(den as usize) {
+                assert_eq!(window.iter().filter(|x| **x).count(), num as usize);
+            }
+
+            // Gaps between accepted steps are as even as possible
+            let positions = (0..accepted.len())
+                .filter(|i| accepted[*i])
+                .collect::<Vec<_>>();
+            let min_gap = (den / num) as usize;
+            let max_gap = den.div_ceil(num) as usize;
+            for pair in positions.windows(2) {
+                let gap = pair[1] - pair[0];
+                assert!(gap >= min_gap && gap <= max_gap, "{}/{}: {}", num, den, gap);
+            }
+        }
+    }
+}
+
+#[test]
+fn rational_rate_44k1() {
+    // 44.1 kHz down to 600 Hz
+    let mut rate = RationalRate::new(600, 44100);
+    assert_eq!((0..44100).filter(|_| rate.step()).count(), 600);
+    rate.div(2);
+    assert_eq!(rate.den(), 88200);
+    assert_eq!((0..88200).filter(|_| rate.step()).count(), 600);
+}
+
+#[test]
+fn rate_distance_to_next_sample() {
+    let mut rate = SamplingRate::new(1);
+    for _ in 0..10 {
+        assert_eq!(rate.distance_to_next_sample(), 1);
+        assert_eq!(rate.advance_to_next_sample(), 0);
+        assert!(rate.step());
+    }
+
+    assert_eq!(SamplingRate::new(5).distance_to_next_sample(), 5);
+
+    for divisor in 1..10 {
+        for phase in 0..divisor {
+            for ratio in [1, 2, 3] {
+                let mut rate = SamplingRate::new(divisor);
+                rate.set_phase(phase);
+                rate.div(ratio);
+
+                let distance = rate.distance_to_next_sample();
+                let mut manual = rate;
+                let manual_distance = (1..).find(|_| manual.step()).unwrap();
+                assert_eq!(distance, manual_distance);
+
+                let mut skipping = rate;
+                assert_eq!(skipping.advance_to_next_sample(), distance - 1);
+                assert!(skipping.step());
+                assert_eq!(skipping.counter(), manual.counter());
+            }
+        }
+    }
+}
+
+#[test]
+fn rate_schedule_switch_points() {
+    let mut schedule = RateSchedule::<3>::new(1, &[(10, 2), (20, 5), (40, 10)]).unwrap();
+    let mut accepted = Vec::new();
+    for i in 0..100 {
+        if schedule.step() {
+            accepted.push(i);
+        }
+        let expected_divisor = match i {
+            0..=9 => 1,
+            10..=19 => 2,
+            20..=39 => 5,
+            _ => 10,
+        };
+        assert_eq!(schedule.divisor(), expected_divisor, "observation {}", i);
+    }
+    assert_eq!(schedule.observed(), 100);
+    assert_eq!(
+        accepted,
+        [
+            0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 11, 13, 15, 17, 19, 24, 29, 34, 39, 49, 59, 69, 79, 89,
+            99
+        ]
+    );
+}
+
+#[test]
+fn rate_schedule_validation() {
+    assert_eq!(
+        RateSchedule::<4>::new(1, &[(10, 2), (10, 4)]).err(),
+        Some(InvalidSchedule::NotIncreasing)
+    );
+    assert_eq!(
+        RateSchedule::<4>::new(1, &[(10, 2), (5, 4)]).err(),
+        Some(InvalidSchedule::NotIncreasing)
+    );
+    assert_eq!(
+        RateSchedule::<4>::new(1, &[(10, 0)]).err(),
+        Some(InvalidSchedule::ZeroDivisor)
+    );
+    assert_eq!(
+        RateSchedule::<4>::new(0, &[]).err(),
+        Some(InvalidSchedule::ZeroDivisor)
+    );
+    assert_eq!(
+        RateSchedule::<1>::new(1, &[(1, 2), (2, 4)]).err(),
+        Some(InvalidSchedule::TooManyBreakpoints)
+    );
+    assert!(RateSchedule::<0>::new(3, &[]).is_ok());
+}
+
+#[test]
+fn pow2_rate_matches_sampling_rate() {
+    let mut pow2 = PowerOfTwoRate::new(0);
+    let mut rate = SamplingRate::new(1);
+    for i in 0..20_000u32 {
+        assert_eq!(pow2.step(), rate.step());
+        if i % 1000 == 999 {
+            pow2.halve();
+            rate.div(2);
+            assert_eq!(pow2.divisor(), rate.divisor() as u64);
+        }
+    }
+}
+
+#[test]
+fn pow2_rate_saturates() {
+    let mut rate = PowerOfTwoRate::new(62);
+    rate.halve();
+    rate.halve();
+    assert_eq!(rate.shift(), PowerOfTwoRate::MAX_SHIFT);
+    assert_eq!(rate.divisor(), 1 << 63);
+    assert!(!rate.step());
+}
+
+fn pow2_reservoir_differential<const N: usize>(steps: usize) {
+    let mut reference = SamplingReservoir::<usize, N>::new();
+    let mut pow2 = SamplingReservoir::<usize, N, PowerOfTwoRate>::new();
+    for i in 0..steps {
+        let a = reference.sample(i);
+        let b = pow2.sample(i);
+        assert_eq!(
+            matches!(a, SamplingOutcome::Discarded(_)),
+            matches!(b, SamplingOutcome::Discarded(_))
+        );
+        assert_eq!(
+            reference.sampling_rate().divisor() as u64,
+            pow2.sampling_rate().divisor()
+        );
+    }
+    assert_eq!(reference.as_unordered_slice(), pow2.as_unordered_slice());
+    assert!(reference.ordered_iter().eq(pow2.ordered_iter()));
+}
+
+#[test]
+fn pow2_reservoir_equivalence() {
+    let steps = if cfg!(miri) { 5_000 } else { 1_000_000 };
+    pow2_reservoir_differential::<2>(steps);
+    pow2_reservoir_differential::<8>(steps);
+    pow2_reservoir_differential::<16>(steps);
+    pow2_reservoir_differential::<64>(steps);
+}
+
+#[test]
+fn burst_rate_pattern() {
+    let mut rate = BurstRate::new(3);
+    let before = (0..6).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(before, [false, false, true, false, false, true]);
+
+    // Burst in the middle of a period
+    assert!(!rate.step());
+    rate.grant_burst(4);
+    assert_eq!(rate.burst_remaining(), 4);
+    let during = (0..4).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(during, [true; 4]);
+    assert_eq!(rate.burst_remaining(), 0);
+
+    // Phase restarts at the end of the burst
+    let after = (0..6).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(after, [false, false, true, false, false, true]);
+}
+
+#[test]
+fn burst_rate_div_during_burst() {
+    let mut rate = BurstRate::new(2);
+    rate.grant_burst(2);
+    assert!(rate.step());
+    rate.div(2);
+    assert_eq!(rate.divisor(), 4);
+    assert!(rate.step());
+    let after = (0..8).map(|_| rate.step()).collect::<Vec<_>>();
+    assert_eq!(
+        after,
+        [false, false, false, true, false, false, false, true]
+    );
+}
+
+#[test]
+fn burst_rate_zero_burst() {
+    let mut rate = BurstRate::new(2);
+    rate.grant_burst(0);
+    assert!(!rate.step());
+    assert!(rate.step());
+}
+
+#[test]
+fn chained_rate_matches_nesting() {
+    for d1 in 1..6 {
+        for d2 in 1..6 {
+            for phase in 0..d1 {
+                let mut first = SamplingRate::new(d1);
+                first.set_phase(phase);
+                let mut second = SamplingRate::new(d2);
+                let mut chained = first.chain(second);
+
+                for i in 0..500 {
+                    if i == 100 {
+                        first.div(2);
+                        chained.first_mut().div(2);
+                    }
+                    if i == 300 {
+                        second.div(3);
+                        chained.second_mut().div(3);
+                    }
+                    if i == 400 {
+                        second.div(2);
+                        chained.div(2);
+                    }
+                    let nested = first.step() && second.step();
+                    assert_eq!(chained.step(), nested);
+                }
+                assert_eq!(
+                    chained.effective_divisor(),
+                    first.divisor() as u64 * second.divisor() as u64
+                );
+                assert_eq!(chained.first().counter(), first.counter());
+                assert_eq!(chained.second().counter(), second.counter());
+            }
+        }
+    }
+}
+
+#[test]
+fn chained_rate_effective_divisor() {
+    let chained = SamplingRate::new(u32::MAX).chain(SamplingRate::new(u32::MAX));
+    assert_eq!(
+        chained.effective_divisor(),
+        u32::MAX as u64 * u32::MAX as u64
+    );
+}
+
+#[test]
+fn budgeted_rate_cap_fixed_windows() {
+    for budget in 1..8 {
+        for window_len in [1, 5, 16, 100] {
+            let mut rate = BudgetedRate::new(budget, window_len);
+            for _ in 0..20 {
+                let accepted = (0..window_len).filter(|_| rate.step()).count();
+                assert!(accepted as u32 <= budget);
+            }
+            // In steady state, the budget is used up as much as the divisor allows
+            assert_eq!(rate.divisor(), window_len.div_ceil(budget));
+        }
+    }
+}
+
+#[test]
+fn budgeted_rate_cap_bursty() {
+    // Windows closed by an external tick, with wildly varying input rates
+    let arrivals = [3, 500, 20, 0, 1000, 7, 7, 300, 1, 64];
+    let mut rate = BudgetedRate::new(10, u32::MAX);
+    let mut previous = 0;
+    for arrived in arrivals {
+        let accepted = (0..arrived).filter(|_| rate.step()).count();
+        assert!(accepted <= 10, "{} accepted of {}", accepted, arrived);
+        assert_eq!(rate.accepted_in_window() as usize, accepted);
+        assert_eq!(rate.observed_in_window(), arrived);
+        if previous == arrived {
+            // Same input rate as the previous window uses the budget fully
+            assert_eq!(accepted as u32, arrived.min(10));
+        }
+        rate.end_window();
+        assert_eq!(rate.divisor(), arrived.div_ceil(10).max(1));
+        previous = arrived;
+    }
+}
+
+#[test]
+fn rate_eq_debug() {
+    let mut a = SamplingRate::new(4);
+    let b = SamplingRate::new(4);
+    assert_eq!(a, b);
+    assert!(!a.step());
+    assert_ne!(a, b);
+    assert_eq!(
+        format!("{:?}", a),
+        "SamplingRate { divisor: 4, counter: 1, pending_ratio: 1 }"
+    );
+}
+
+#[test]
+fn rate_samples_until_next() {
+    for divisor in 1..10 {
+        let mut rate = SamplingRate::new(divisor);
+        for _ in 0..30 {
+            let until = rate.samples_until_next();
+            let mut probe = rate;
+            assert_eq!((0..).take_while(|_| !probe.step()).count() as u32, until);
+            let _ = rate.step();
+        }
+    }
+}
+
+/// Closed-form storage index of the value stored at `position`, going through
+/// an observation index congruent to the real one modulo `N - 1`,
+/// since the real one overflows after a few dozen patterns.
+fn storage_index_for_position<const N: usize>(position: usize) -> usize {
+    if position < N {
+        return SamplingReservoir::<u32, N>::storage_index_for_outer_index(position);
+    }
+    // The k-th value of pattern p is observed at 2^p * (N/2 + k), and 2^log2(N) ≡ 1 mod N-1
+    let pattern = (position - N) / (N / 2) + 1;
+    let k = (position - N) % (N / 2);
+    let log_n = N.trailing_zeros() as usize;
+    let outer_mod = ((1 << (pattern % log_n)) * (N / 2 + k)) % (N - 1);
+    let outer = if outer_mod == 0 { N - 1 } else { outer_mod };
+    SamplingReservoir::<u32, N>::storage_index_for_outer_index(outer)
+}
+
+fn indexer_differential<const N: usize>(positions: usize) {
+    let mut indexer = InfinitySamplerIndexer::<N>::new();
+    for position in 0..positions {
+        assert_eq!(indexer.position(), position);
+        assert_eq!(
+            indexer.next_index(),
+            storage_index_for_position::<N>(position),
+            "N={} position={}",
+            N,
+            position
+        );
+    }
+}
+
+#[test]
+fn indexer_matches_closed_form() {
+    let positions = if cfg!(miri) { 2_000 } else { 1_000_000 };
+    indexer_differential::<2>(positions);
+    indexer_differential::<4>(positions);
+    indexer_differential::<8>(positions);
+    indexer_differential::<16>(positions);
+    indexer_differential::<256>(positions);
+    indexer_differential::<4096>(positions);
+}
+
+#[test]
+fn indexer_matches_sampled_outer_indices() {
+    fn check<const N: usize>() {
+        let expected = (0..1 << 14)
+            .filter(|x| SamplingReservoir::<u32, N>::should_sample(*x))
+            .map(SamplingReservoir::<u32, N>::storage_index_for_outer_index)
+            .collect::<Vec<_>>();
+        let indexes = InfinitySamplerIndexer::<N>::new()
+            .take(expected.len())
+            .collect::<Vec<_>>();
+        assert_eq!(indexes, expected);
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+    check::<32>();
+}
+
+#[test]
+fn reverse_indexer() {
+    fn check<const N: usize>() {
+        let forward = InfinitySamplerIndexer::<N>::new()
+            .take(N * 20 + 5)
+            .collect::<Vec<_>>();
+        for len in 0..N * 20 {
+            let mut indexer = InfinitySamplerIndexer::<N>::new();
+            indexer.by_ref().take(len).for_each(drop);
+            let mut reverse = indexer.reverse();
+            assert_eq!(reverse.position(), len);
+
+            // Go back halfway, then forward again
+            let back = reverse.by_ref().take(len / 2).collect::<Vec<_>>();
+            let expected = forward[len - len / 2..len]
+                .iter()
+                .rev()
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(back, expected);
+
+            let mut indexer = reverse.reverse();
+            assert_eq!(indexer.position(), len - len / 2);
+            let again = indexer.by_ref().take(len / 2 + 5).collect::<Vec<_>>();
+            assert_eq!(again, forward[len - len / 2..len + 5]);
+
+            let reverse = indexer.reverse().collect::<Vec<_>>();
+            let expected = forward[..len + 5].iter().rev().copied().collect::<Vec<_>>();
+            assert_eq!(reverse, expected);
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+fn indexer_skip_to() {
+    fn check<const N: usize>() {
+        let mut reference = InfinitySamplerIndexer::<N>::new();
+        let loops = if cfg!(miri) { 4 } else { 40 };
+        for position in 0..N * loops {
+            let mut skipped = InfinitySamplerIndexer::<N>::new();
+            skipped.skip_to(position);
+            assert_eq!(skipped.position(), position);
+
+            // Backwards as well
+            let mut rewound = InfinitySamplerIndexer::<N>::new();
+            rewound.skip_to(N * 50);
+            rewound.skip_to(position);
+
+            let mut probe = reference.clone();
+            let expected = probe.by_ref().take(N + 3).collect::<Vec<_>>();
+            assert_eq!(skipped.by_ref().take(N + 3).collect::<Vec<_>>(), expected);
+            assert_eq!(rewound.take(N + 3).collect::<Vec<_>>(), expected);
+            assert_eq!(
+                skipped.reverse().collect::<Vec<_>>(),
+                probe.reverse().collect::<Vec<_>>()
+            );
+
+            reference.next_index();
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+    check::<64>();
+}
+
+#[test]
+fn indexer_nth() {
+    let mut indexer = InfinitySamplerIndexer::<16>::new();
+    let reference = InfinitySamplerIndexer::<16>::new()
+        .take(1000)
+        .collect::<Vec<_>>();
+    let mut position = 0;
+    for n in [0, 1, 5, 15, 0, 31, 100, 7, 300] {
+        assert_eq!(indexer.nth(n), Some(reference[position + n]));
+        position += n + 1;
+        assert_eq!(indexer.position(), position);
+    }
+
+    // Far positions must not overflow the pattern arithmetic
+    let mut indexer = InfinitySamplerIndexer::<8>::new();
+    indexer.skip_to(10_000_000);
+    assert_eq!(
+        indexer.next(),
+        Some(storage_index_for_position::<8>(10_000_000))
+    );
+}
+
+#[test]
+fn dyn_indexer_matches_const() {
+    use crate::iter::{DynInfinitySamplerIndexer, InvalidCapacity};
+
+    fn check<const N: usize>() {
+        let len = N / 2 * N.trailing_zeros() as usize * 4 + N;
+        let mut dynamic = DynInfinitySamplerIndexer::new(N).unwrap();
+        assert_eq!(dynamic.capacity(), N);
+        let mut fixed = InfinitySamplerIndexer::<N>::new();
+        for _ in 0..len {
+            assert_eq!(dynamic.next(), fixed.next());
+        }
+        assert_eq!(dynamic.position(), fixed.position());
+
+        let mut dynamic_skipped = DynInfinitySamplerIndexer::new(N).unwrap();
+        dynamic_skipped.skip_to(len / 3);
+        let mut fixed_skipped = InfinitySamplerIndexer::<N>::new();
+        fixed_skipped.skip_to(len / 3);
+        assert_eq!(dynamic_skipped.nth(7), fixed_skipped.nth(7));
+
+        let reversed = dynamic.reverse().collect::<Vec<_>>();
+        assert_eq!(reversed.len(), len);
+        assert_eq!(reversed, fixed.reverse().collect::<Vec<_>>());
+    }
+    check::<4>();
+    check::<8>();
+    check::<64>();
+    check::<1024>();
+
+    for n in [0, 1, 3, 6, 12, 1000] {
+        assert_eq!(
+            DynInfinitySamplerIndexer::new(n).err(),
+            Some(InvalidCapacity)
+        );
+    }
+}
+
+#[test]
+fn indexer_size_hints() {
+    let indexer = InfinitySamplerIndexer::<8>::new();
+    assert_eq!(indexer.size_hint(), (usize::MAX, None));
+
+    let mut indexer = InfinitySamplerIndexer::<8>::new();
+    indexer.by_ref().take(10).for_each(drop);
+    let mut reverse = indexer.reverse();
+    assert_eq!(reverse.len(), 10);
+    assert_eq!(reverse.size_hint(), (10, Some(10)));
+    reverse.by_ref().take(4).for_each(drop);
+    assert_eq!(reverse.len(), 6);
+    assert_eq!(reverse.len(), reverse.position());
+    assert_eq!(reverse.by_ref().count(), 6);
+    assert_eq!(reverse.len(), 0);
+}
+
+#[test]
+fn reverse_indexer_fused() {
+    let mut indexer = InfinitySamplerIndexer::<4>::new();
+    indexer.by_ref().take(3).for_each(drop);
+    let mut reverse = indexer.reverse();
+    assert_eq!(reverse.by_ref().collect::<Vec<_>>(), [2, 1, 0]);
+    for _ in 0..5 {
+        assert_eq!(reverse.next(), None);
+    }
+    assert_eq!(reverse.position(), 0);
+
+    // Still usable in the forward direction afterwards
+    let mut indexer = reverse.reverse();
+    assert_eq!(indexer.next(), Some(0));
+}
+
+#[test]
+fn indexer_from_position() {
+    fn check<const N: usize>() {
+        let loop_len = N / 2 * N.trailing_zeros() as usize;
+        let mut stepped = InfinitySamplerIndexer::<N>::new();
+        for position in 0..N + loop_len * 3 {
+            let mut forward = InfinitySamplerIndexer::<N>::from_position(position);
+            assert_eq!(forward.position(), position);
+            assert_eq!(forward.next_index(), stepped.clone().next_index());
+
+            let reverse = ReverseInfinitySamplerIndexer::<N>::from_position(position);
+            assert_eq!(reverse.len(), position);
+            assert!(reverse.eq(stepped.clone().reverse()));
+
+            stepped.next_index();
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+    check::<32>();
+}
+
+#[test]
+fn reverse_indexer_new() {
+    let mut reverse = ReverseInfinitySamplerIndexer::<8>::new();
+    assert_eq!(reverse.position(), 0);
+    assert_eq!(reverse.next(), None);
+    let mut reverse = ReverseInfinitySamplerIndexer::<8>::default();
+    assert_eq!(reverse.next(), None);
+    assert_eq!(reverse.reverse().next(), Some(0));
+}
+
+#[test]
+fn indexer_prev_interleaved() {
+    fn check<const N: usize>(seed: u64) {
+        let forward = InfinitySamplerIndexer::<N>::new()
+            .take(N * 64)
+            .collect::<Vec<_>>();
+        let mut indexer = InfinitySamplerIndexer::<N>::new();
+        let mut position = 0;
+        let mut state = seed;
+        for _ in 0..5000 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            // Biased towards moving forward so the walk crosses several loops
+            if (state >> 33) % 5 < 3 {
+                if position + 1 >= forward.len() {
+                    continue;
+                }
+                assert_eq!(indexer.next(), Some(forward[position]));
+                position += 1;
+            } else if position == 0 {
+                assert_eq!(indexer.prev(), None);
+            } else {
+                position -= 1;
+                assert_eq!(indexer.prev(), Some(forward[position]));
+            }
+            assert_eq!(indexer.position(), position);
+        }
+    }
+    for seed in 0..4 {
+        check::<2>(seed);
+        check::<4>(seed);
+        check::<8>(seed);
+        check::<16>(seed);
+    }
+}
+
+#[test]
+fn indexer_raw_round_trip() {
+    use crate::iter::{InvalidState, RawIndexerState};
+
+    fn check<const N: usize>() {
+        let mut state = 12345u64;
+        for _ in 0..200 {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let checkpoint_at = (state >> 33) as usize % (N * 40);
+
+            let mut uninterrupted = InfinitySamplerIndexer::<N>::new();
+            uninterrupted.by_ref().take(checkpoint_at).for_each(drop);
+
+            let raw = uninterrupted.to_raw();
+            assert_eq!(raw.position, checkpoint_at);
+            let restored = InfinitySamplerIndexer::<N>::from_raw(raw).unwrap();
+            assert!(restored.take(N * 3).eq(uninterrupted.take(N * 3)));
+        }
+
+        let valid = InfinitySamplerIndexer::<N>::from_position(N + 3).to_raw();
+        let invalid = [
+            RawIndexerState { step: 3, ..valid },
+            RawIndexerState {
+                step: N * 2,
+                ..valid
+            },
+            RawIndexerState { step: 0, ..valid },
+            RawIndexerState {
+                left_offset: N,
+                ..valid
+            },
+            RawIndexerState { idx: N, ..valid },
+            RawIndexerState {
+                idx: valid.idx + 1,
+                ..valid
+            },
+            RawIndexerState {
+                position: valid.position + 1,
+                ..valid
+            },
+        ];
+        for raw in invalid {
+            assert_eq!(
+                InfinitySamplerIndexer::<N>::from_raw(raw).err(),
+                Some(InvalidState),
+                "{:?}",
+                raw
+            );
+        }
+    }
+    check::<4>();
+    check::<8>();
+    check::<16>();
+    check::<64>();
+}
+
+#[test]
+fn observations_for_stored_inverts_schedule() {
+    fn check<const N: usize>() {
+        for seen in 0..20_000 {
+            let stored = math::samples_accepted_for::<N>(seen);
+            let first_seen = math::observations_for_stored::<N>(stored);
+            assert!(first_seen <= seen);
+            assert_eq!(math::samples_accepted_for::<N>(first_seen), stored);
+            // The chronological order doesn't depend on where in the gap the count is
+            assert!(buf::ReservoirOrderedIndexIter::<N>::new(seen, stored)
+                .eq(buf::ReservoirOrderedIndexIter::<N>::new(first_seen, stored)));
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+#[cfg(target_pointer_width = "64")]
+fn rate_saturates_at_terminal_divisor() {
+    // Just before the 31st reduction, which takes the divisor to 2^31
+    let seen = math::observations_for_stored::<4>(64);
+    let mut reservoir = SamplingReservoir::<usize, 4>::from_ordered(seen, [0, 1, 2, 3]).unwrap();
+    assert_eq!(reservoir.sampling_rate().divisor(), 1 << 30);
+    assert!(!reservoir.is_rate_saturated());
+
+    let mut reductions = Vec::new();
+    let mut last = 0;
+    for _ in 0..20 {
+        reservoir.skip_discarded();
+        last = reservoir.samples_seen();
+        match reservoir.sample(last) {
+            SamplingOutcome::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            } => reductions.push((old_divisor, new_divisor)),
+            outcome => assert!(outcome.is_consumed()),
+        }
+        reservoir.debug_validate().unwrap();
+        let ordered: Vec<_> = reservoir.ordered_iter().copied().collect();
+        assert_eq!(ordered.len(), 4);
+        assert!(ordered.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(ordered.last(), Some(&last));
+    }
+    assert_eq!(reductions, [(1 << 30, 1 << 31)]);
+    assert!(reservoir.is_rate_saturated());
+    assert_eq!(reservoir.sampling_rate().divisor(), 1 << 31);
+    assert!(last > seen + (18 << 31));
+}
+
+/// Returns a reservoir that has accepted `accepted` values past the terminal rate
+/// reduction, then discarded a few, so that the phase counter isn't zero.
+fn rate_saturated_reservoir(accepted: usize) -> SamplingReservoir<u32, 4> {
+    let seen = math::observations_for_stored::<4>(64);
+    let mut reservoir = SamplingReservoir::<u32, 4>::from_ordered(seen, [0, 1, 2, 3]).unwrap();
+    for i in 0..accepted + 1 {
+        reservoir.skip_discarded();
+        assert!(reservoir.sample(i as u32 + 4).is_consumed());
+        assert_eq!(
+            reservoir.samples_accepted(),
+            math::samples_accepted_saturating::<4>(reservoir.samples_seen())
+        );
+    }
+    for _ in 0..3 {
+        assert!(!reservoir.sample(0).is_consumed());
+    }
+    assert!(reservoir.is_rate_saturated());
+    assert_eq!(reservoir.sampling_rate().counter(), 3);
+    reservoir
+}
+
+#[test]
+fn from_ordered_rate_saturated() {
+    for accepted in 0..10 {
+        let reservoir = rate_saturated_reservoir(accepted);
+        assert_eq!(
+            reservoir.samples_accepted(),
+            math::samples_accepted_saturating::<4>(reservoir.samples_seen())
+        );
+        let mut restored = SamplingReservoir::<u32, 4>::from_ordered(
+            reservoir.samples_seen(),
+            reservoir.ordered_iter().copied(),
+        )
+        .unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+        assert_eq!(restored.samples_accepted(), reservoir.samples_accepted());
+        restored.debug_validate().unwrap();
+
+        let mut original = reservoir;
+        for i in 0..4 {
+            original.skip_discarded();
+            restored.skip_discarded();
+            assert_eq!(original.sample(i), restored.sample(i));
+            assert!(original.ordered_iter().eq(restored.ordered_iter()));
+        }
+    }
+}
+
+#[test]
+fn order_repeats_every_indexer_loop() {
+    fn check<const N: usize>() {
+        let order = |stored| {
+            let seen = math::observations_for_stored::<N>(stored);
+            buf::ReservoirOrderedIndexIter::<N>::new(seen, stored)
+        };
+        for stored in 0..N + N / 2 * 50 {
+            let equivalent = math::order_equivalent_stored::<N>(stored);
+            assert!(equivalent <= N + N.trailing_zeros() as usize * N / 2);
+            assert!(order(stored).eq(order(equivalent)));
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+fn saturated_count_keeps_order() {
+    fn check<const N: usize>() {
+        let steps = if cfg!(miri) { 100 } else { 3000 };
+        let mut reference = SamplingReservoir::<usize, N>::new();
+        for i in 0..5000 {
+            let _ = reference.sample(i);
+        }
+        // The observed count runs ahead of the stored one until it stops
+        let max = SamplingReservoir::<usize, N>::MAX_SAMPLES_SEEN;
+        let mut reservoir = SamplingReservoir::<usize, N>::from_parts(
+            reference.as_unordered_slice().iter().copied().collect(),
+            *reference.sampling_rate(),
+            reference.samples_accepted(),
+            max - 3,
+        );
+        for i in 5000..5000 + steps {
+            let _ = reference.sample(i);
+            let _ = reservoir.sample(i);
+            assert_eq!(reservoir.is_saturated(), i >= 5002);
+            assert!(reservoir.ordered_indices().all(|idx| idx < reservoir.len()));
+            if reservoir.is_saturated() {
+                assert!(reservoir.ordered_iter().eq(reference.ordered_iter()));
+                reservoir.debug_validate().unwrap();
+            }
+        }
+        assert_eq!(reservoir.samples_seen(), max);
+        assert_eq!(reservoir.skip_discarded(), reference.skip_discarded());
+        assert_eq!(reservoir.samples_seen(), max);
+    }
+    check::<2>();
+    check::<8>();
+    check::<32>();
+}
+
+#[test]
+fn samples_accepted_for_seen() {
+    fn check<const N: usize>() {
+        let mut buf = SamplingReservoir::<(), N>::new();
+        for seen in 0..20_000 {
+            assert_eq!(buf.samples_seen(), seen);
+            assert_eq!(
+                math::samples_accepted_for::<N>(seen),
+                buf.samples_accepted()
+            );
+            let _ = buf.sample(());
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+#[allow(deprecated)]
+fn accepted_and_stored_counters() {
+    fn check<const N: usize>(steps: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        let mut lut = LutSamplingReservoir::<usize, N>::new();
+        let mut indexed = IndexedSamplingReservoir::<usize, N>::new();
+        let mut accepted = 0;
+        let mut reductions = 0;
+        for seen in 1..=steps {
+            let outcome = buf.sample(seen);
+            if !outcome.is_discarded() {
+                accepted += 1;
+            }
+            if let SamplingOutcome::ConsumedAndRateReduced { .. } = outcome {
+                reductions += 1;
+            }
+            let _ = lut.sample(seen);
+            let _ = indexed.sample(seen);
+
+            assert_eq!(buf.samples_seen(), seen);
+            assert_eq!(buf.samples_accepted(), accepted);
+            assert_eq!(buf.samples_stored(), accepted);
+            assert_eq!(buf.len(), accepted.min(N));
+            assert_eq!(buf.sampling_rate().divisor(), 1 << reductions);
+
+            assert_eq!(lut.samples_seen(), seen);
+            assert_eq!(lut.samples_accepted(), accepted);
+            assert_eq!(lut.len(), accepted.min(N));
+            assert_eq!(indexed.samples_seen(), seen);
+            assert_eq!(indexed.samples_accepted(), accepted);
+            assert_eq!(indexed.len(), accepted.min(N));
+        }
+        assert!(reductions > 2);
+        assert!(buf.samples_accepted() > 2 * buf.len());
+    }
+    check::<2>(1000);
+    check::<8>(10_000);
+    check::<64>(100_000);
+}
+
+#[test]
+fn resident_outer_index_brute_force() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            assert_eq!(buf.samples_seen(), seen);
+            for slot in 0..N + 2 {
+                assert_eq!(
+                    math::resident_outer_index::<N>(seen, slot),
+                    buf.as_unordered_slice().get(slot).copied(),
+                    "N={} seen={} slot={}",
+                    N,
+                    seen,
+                    slot
+                );
+            }
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 500 } else { 50_000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+}
+
+#[test]
+fn ordered_iter_enumerated_matches_simulator() {
+    fn check<const N: usize>(loops: usize) {
+        // Runs the explainer's algorithm directly, recording the outer index held by each slot
+        let mut slots = [None; N];
+        let mut expected = Vec::new();
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        let loop_len = N.trailing_zeros() as usize * N / 2;
+        let observations = math::observations_for_stored::<N>(N + loops * loop_len) + 1;
+        for seen in 0..observations {
+            assert!(
+                buf.ordered_iter_enumerated()
+                    .map(|(outer_index, value)| (outer_index, *value))
+                    .eq(expected
+                        .iter()
+                        .map(|&outer_index| (outer_index, outer_index))),
+                "N={} seen={}",
+                N,
+                seen
+            );
+            let _ = buf.sample(seen);
+
+            let significant_bits = usize::BITS - seen.leading_zeros();
+            let dropped_bits = significant_bits.saturating_sub(N.trailing_zeros());
+            if seen & ((1 << dropped_bits) - 1) == 0 {
+                let slot = if seen == 0 {
+                    0
+                } else {
+                    (seen - 1) % (N - 1) + 1
+                };
+                slots[slot] = Some(seen);
+                expected = slots.iter().flatten().copied().collect();
+                expected.sort_unstable();
+            }
+        }
+    }
+    let loops = if cfg!(miri) { 1 } else { 3 };
+    check::<4>(loops);
+    check::<8>(loops);
+    check::<16>(loops);
+    if !cfg!(miri) {
+        check::<32>(loops);
+    }
+}
+
+#[test]
+fn surviving_outer_indices_differential() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let survivors = math::surviving_outer_indices::<N>(seen).collect::<Vec<_>>();
+            let enumerated = buf.ordered_iter_enumerated().collect::<Vec<_>>();
+            assert_eq!(survivors.len(), enumerated.len());
+            for (survivor, (outer_index, value)) in survivors.iter().zip(enumerated) {
+                assert_eq!(*survivor, outer_index, "N={} seen={}", N, seen);
+                assert_eq!(outer_index, *value);
+            }
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 300 } else { 20_000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<32>(observations);
+
+    // Same as e2e_mid_loop
+    assert!(math::surviving_outer_indices::<16>(50)
+        .eq([0, 4, 8, 12, 16, 20, 22, 24, 26, 28, 30, 32, 36, 40, 44, 48]));
+}
+
+#[test]
+fn indexer_peek() {
+    fn check<const N: usize>() {
+        let len = N / 2 * N.trailing_zeros() as usize * 3 + N;
+        let mut indexer = InfinitySamplerIndexer::<N>::new();
+        for _ in 0..len {
+            let peeked = indexer.peek();
+            assert_eq!(indexer.peek(), peeked);
+            assert_eq!(indexer.next(), Some(peeked));
+        }
+
+        let mut reverse = indexer.reverse();
+        while let Some(peeked) = reverse.peek() {
+            assert_eq!(reverse.peek(), Some(peeked));
+            assert_eq!(reverse.next(), Some(peeked));
+        }
+        assert_eq!(reverse.peek(), None);
+        assert_eq!(reverse.position(), 0);
+
+        // Mixed directions, including across loop boundaries
+        let mut indexer = InfinitySamplerIndexer::<N>::from_position(N);
+        for step in 0..len {
+            if step % 3 == 2 {
+                let mut reverse = indexer.reverse();
+                let peeked = reverse.peek();
+                assert_eq!(reverse.next(), peeked);
+                indexer = reverse.reverse();
+            } else {
+                let peeked = indexer.peek();
+                assert_eq!(indexer.next_index(), peeked);
+            }
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+    check::<256>();
+}
+
+#[test]
+fn indexer_pattern_and_group() {
+    let mut expected = vec![(0, 0); 16];
+    // From the chart in the math module
+    for (pattern, groups, group_len) in [(1, 1, 8), (2, 2, 4), (3, 4, 2), (4, 8, 1)] {
+        for group in 0..groups {
+            expected.extend(core::iter::repeat_n((pattern, group), group_len));
+        }
+    }
+    assert_eq!(expected.len(), 16 + 32);
+
+    let mut indexer = InfinitySamplerIndexer::<16>::new();
+    for loop_iteration in 0..5 {
+        let expected = if loop_iteration == 0 {
+            &expected[..]
+        } else {
+            &expected[16..]
+        };
+        for (position, &pattern_group) in expected.iter().enumerate() {
+            assert_eq!(
+                (indexer.pattern(), indexer.group()),
+                pattern_group,
+                "loop {} position {}",
+                loop_iteration,
+                position
+            );
+            assert_eq!(indexer.loop_iteration(), loop_iteration);
+            indexer.next_index();
+        }
+    }
+    assert_eq!(indexer.loop_iteration(), 5);
+
+    // Non-N=16 loops are N/2 * log2(N) long
+    let indexer = InfinitySamplerIndexer::<8>::from_position(8 + 12 * 3 - 1);
+    assert_eq!(indexer.loop_iteration(), 2);
+    assert_eq!((indexer.pattern(), indexer.group()), (3, 3));
+}
+
+#[test]
+fn overwrite_count_matches_simulation() {
+    fn check<const N: usize>() {
+        let loop_len = N / 2 * N.trailing_zeros() as usize;
+        let mut writes = [0; N];
+        let mut indexer = InfinitySamplerIndexer::<N>::new();
+        for position in 0..N + loop_len * 10 {
+            // Just before this write, and just after it
+            let next_outer_index = math::outer_index_for_position::<N>(position);
+            for (slot, &count) in writes.iter().enumerate() {
+                assert_eq!(
+                    math::overwrite_count::<N>(next_outer_index, slot),
+                    count,
+                    "N={} seen={} slot={}",
+                    N,
+                    next_outer_index,
+                    slot
+                );
+            }
+            writes[indexer.next_index()] += 1;
+            for (slot, &count) in writes.iter().enumerate() {
+                assert_eq!(
+                    math::overwrite_count::<N>(next_outer_index + 1, slot),
+                    count
+                );
+            }
+        }
+        // 10 loops past the initial pattern
+        for (slot, &count) in writes.iter().enumerate() {
+            let expected = if slot == 0 {
+                1
+            } else {
+                1 + 10 * slot.count_ones() as usize
+            };
+            assert_eq!(count, expected);
+        }
+    }
+    check::<8>();
+    check::<16>();
+    check::<32>();
+
+    // Against an actual reservoir, at every observation count
+    let mut writes = [0; 16];
+    let mut buf = SamplingReservoir::<usize, 16>::new();
+    for seen in 0..3000 {
+        for (slot, &count) in writes.iter().enumerate() {
+            assert_eq!(math::overwrite_count::<16>(seen, slot), count);
+        }
+        let before = buf.as_unordered_slice().to_vec();
+        let stored = buf.samples_accepted();
+        let _ = buf.sample(seen);
+        if buf.samples_accepted() > stored {
+            let slot = buf
+                .as_unordered_slice()
+                .iter()
+                .position(|&v| v == seen)
+                .unwrap();
+            assert!(before.len() <= slot || before[slot] != seen);
+            writes[slot] += 1;
+        }
+    }
+}
+
+#[test]
+fn chronological_position_inverts_ordered_indices() {
+    use crate::buf::ReservoirOrderedIndexIter;
+
+    fn check<const N: usize>(observations: usize) {
+        for seen in 0..observations {
+            let stored = math::samples_accepted_for::<N>(seen);
+            let mut initialized = [false; N];
+            for (rank, slot) in ReservoirOrderedIndexIter::<N>::new(seen, stored).enumerate() {
+                assert_eq!(
+                    math::chronological_position::<N>(seen, slot),
+                    Some(rank),
+                    "N={} seen={} slot={}",
+                    N,
+                    seen,
+                    slot
+                );
+                initialized[slot] = true;
+            }
+            for (slot, _) in initialized.iter().enumerate().filter(|(_, &init)| !init) {
+                assert_eq!(math::chronological_position::<N>(seen, slot), None);
+            }
+        }
+    }
+    let observations = if cfg!(miri) { 200 } else { 10_000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn ordered_index_iter_matches_reference() {
+    use crate::buf::ReservoirOrderedIndexIter;
+
+    // The previous implementation, recomputing the spacing for every position
+    fn reference_outer_index<const N: usize>(seen: usize, stored: usize, pos: usize) -> usize {
+        if seen < N {
+            return pos;
+        }
+        let log = usize::BITS - ((seen - 1) / (N - 1)).leading_zeros() - 1;
+        let step_lower = 1 << log;
+        let step_upper = step_lower << 1;
+        let n_upper_steps = stored % (N / 2);
+        if pos < n_upper_steps {
+            pos * step_upper
+        } else if pos < N - n_upper_steps {
+            n_upper_steps * step_upper + (pos - n_upper_steps) * step_lower
+        } else {
+            n_upper_steps * step_upper
+                + (N - n_upper_steps * 2) * step_lower
+                + (pos - (N - n_upper_steps)) * step_upper
+        }
+    }
+
+    fn check<const N: usize>(rng: &mut u64, rounds: usize) {
+        for _ in 0..rounds {
+            // SplitMix64
+            *rng = rng.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = *rng;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^= z >> 31;
+            // Spread the sample counts over all magnitudes
+            let seen = (z >> 8) as usize >> (z as u32 % 40);
+            let stored = math::samples_accepted_for::<N>(seen);
+
+            let indices = ReservoirOrderedIndexIter::<N>::new(seen, stored);
+            let expected: Vec<_> = (0..stored.min(N))
+                .map(|pos| reference_outer_index::<N>(seen, stored, pos))
+                .collect();
+            for (pos, &outer_index) in expected.iter().enumerate() {
+                assert_eq!(
+                    indices.outer_index_at(pos),
+                    outer_index,
+                    "N={} seen={}",
+                    N,
+                    seen
+                );
+                assert_eq!(indices.position_of_outer_index(outer_index), pos);
+            }
+            let slots: Vec<_> = expected
+                .iter()
+                .map(|&outer_index| {
+                    SamplingReservoir::<(), N>::storage_index_for_outer_index(outer_index)
+                })
+                .collect();
+            assert_eq!(
+                indices.clone().collect::<Vec<_>>(),
+                slots,
+                "N={} seen={}",
+                N,
+                seen
+            );
+            assert_eq!(
+                indices.rev().collect::<Vec<_>>(),
+                slots.into_iter().rev().collect::<Vec<_>>()
+            );
+        }
+    }
+    let rounds = if cfg!(miri) { 20 } else { 2_000 };
+    let mut rng = 7;
+    check::<2>(&mut rng, rounds);
+    check::<4>(&mut rng, rounds);
+    check::<16>(&mut rng, rounds);
+    check::<256>(&mut rng, rounds);
+    check::<4096>(&mut rng, rounds / 10);
+}
+
+#[test]
+fn const_index_table() {
+    use crate::iter::index_table;
+
+    const TABLE: [u16; 4096] = index_table::<256, 4096>();
+    assert!(TABLE
+        .iter()
+        .map(|&i| i as usize)
+        .eq(InfinitySamplerIndexer::<256>::new().take(4096)));
+
+    const SMALL: [u16; 100] = index_table::<4, 100>();
+    assert!(SMALL
+        .iter()
+        .map(|&i| i as usize)
+        .eq(InfinitySamplerIndexer::<4>::new().take(100)));
+
+    // Matches the reservoir's own placement of sampled values
+    const POSITIONS: usize = 300;
+    const STORAGE: [u16; POSITIONS] = index_table::<16, POSITIONS>();
+    let stored = (0..POSITIONS)
+        .map(math::outer_index_for_position::<16>)
+        .map(SamplingReservoir::<(), 16>::storage_index_for_outer_index);
+    assert!(STORAGE.iter().map(|&i| i as usize).eq(stored));
+}
+
+#[test]
+fn math_strides_match_reservoir() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let outer_indices = buf
+                .ordered_iter_enumerated()
+                .map(|(outer_index, _)| outer_index)
+                .collect::<Vec<_>>();
+            let strides = outer_indices.windows(2).map(|w| w[1] - w[0]);
+            let (lower, upper) = math::stride_bounds(N, seen);
+            if seen > 1 {
+                assert_eq!(strides.clone().min(), Some(lower), "N={} seen={}", N, seen);
+                assert_eq!(strides.max(), Some(upper), "N={} seen={}", N, seen);
+            }
+
+            let checkpoint = math::next_even_checkpoint(N, seen);
+            assert!(checkpoint >= seen);
+            if checkpoint == seen && seen >= N {
+                // Exact multiples of the stride, all the way through
+                assert_eq!(lower, upper);
+                assert!(outer_indices
+                    .iter()
+                    .enumerate()
+                    .all(|(i, &outer_index)| outer_index == i * lower));
+            }
+            if N > 2 && seen > N && lower == upper {
+                // Already even, only the observations after the last sample are left over.
+                // With N=2 there is only ever one stride.
+                assert!(checkpoint - seen < lower, "N={} seen={}", N, seen);
+            }
+
+            let _ = buf.sample(seen);
+        }
+    }
+    check::<2>(2000);
+    check::<4>(2000);
+    check::<16>(5000);
+    check::<64>(5000);
+
+    for coverage in 0..100 {
+        let seen = math::observations_for_coverage(16, coverage);
+        assert!(math::stride_bounds(16, seen).0 >= coverage);
+        if seen > 0 {
+            assert!(math::stride_bounds(16, seen - 1).0 < coverage);
+        }
+    }
+}
+
+#[test]
+fn indexed_reservoir_matches_reservoir() {
+    fn check<const N: usize>(observations: usize) {
+        let mut plain = SamplingReservoir::<usize, N>::new();
+        let mut indexed = IndexedSamplingReservoir::<usize, N>::new();
+        for i in 0..observations {
+            assert!(plain
+                .ordered_iter_enumerated()
+                .eq(indexed.ordered_iter_enumerated()));
+            assert_eq!(indexed.len(), plain.len());
+            assert_eq!(indexed.samples_accepted(), plain.samples_accepted());
+            assert_eq!(indexed.samples_seen(), plain.samples_seen());
+            assert_eq!(plain.sample(i), indexed.sample(i));
+        }
+        assert_eq!(indexed.sampling_rate(), plain.sampling_rate());
+        assert!(plain.ordered_iter().eq(indexed.ordered_iter()));
+    }
+    let observations = if cfg!(miri) { 200 } else { 5000 };
+    check::<2>(observations);
+    check::<8>(observations);
+    check::<32>(observations);
+}
+
+#[test]
+fn indexed_reservoir_saturated_count() {
+    let max = SamplingReservoir::<usize, 8>::MAX_SAMPLES_SEEN;
+    let mut plain = SamplingReservoir::<usize, 8>::new();
+    let mut indexed = IndexedSamplingReservoir::<usize, 8>::new();
+    for i in 0..200 {
+        assert_eq!(plain.sample(i), indexed.sample(i));
+    }
+    indexed.set_samples_seen(max - 3);
+    for i in 200..2000 {
+        assert_eq!(plain.sample(i), indexed.sample(i));
+        assert_eq!(indexed.samples_seen(), (max - 3 + i - 199).min(max));
+    }
+
+    // The values observed at the ceiling share its index
+    let entries: Vec<_> = indexed.ordered_iter_enumerated().collect();
+    assert!(entries.iter().all(|&(idx, _)| idx < max));
+    assert!(entries.iter().filter(|&&(idx, _)| idx == max - 1).count() > 1);
+    let mut values: Vec<_> = entries.iter().map(|&(_, &value)| value).collect();
+    values.sort_unstable();
+    assert!(values.iter().eq(plain.ordered_iter()));
+}
+
+#[test]
+fn indexed_reservoir_drops_and_clones() {
+    use std::rc::Rc;
+
+    let token = Rc::new(());
+    let mut reservoir = IndexedSamplingReservoir::<Rc<()>, 8>::new();
+    for _ in 0..5 {
+        let _ = reservoir.sample(token.clone());
+    }
+    assert_eq!(Rc::strong_count(&token), 6);
+    for _ in 0..100 {
+        // Discarded and overwritten values are dropped right away
+        let _ = reservoir.sample(token.clone());
+    }
+    assert_eq!(Rc::strong_count(&token), 9);
+
+    let clone = reservoir.clone();
+    assert_eq!(Rc::strong_count(&token), 17);
+    assert!(clone
+        .ordered_iter_enumerated()
+        .map(|(i, _)| i)
+        .eq(reservoir.ordered_iter_enumerated().map(|(i, _)| i)));
+    drop(reservoir);
+    assert_eq!(Rc::strong_count(&token), 9);
+    drop(clone);
+    assert_eq!(Rc::strong_count(&token), 1);
+
+    // Partially filled
+    let mut reservoir = IndexedSamplingReservoir::<Rc<()>, 8>::new();
+    let _ = reservoir.sample(token.clone());
+    let clone = reservoir.clone();
+    assert_eq!(clone.len(), 1);
+    assert_eq!(Rc::strong_count(&token), 3);
+    drop((reservoir, clone));
+    assert_eq!(Rc::strong_count(&token), 1);
+
+    let mut item = Item::new();
+    let index = core::num::NonZeroUsize::new(3).unwrap();
+    assert!(item.write(index, token.clone()).is_none());
+    assert!(item.write(index, token.clone()).is_some());
+    assert_eq!(Rc::strong_count(&token), 2);
+    let copy = item.clone();
+    assert_eq!(Rc::strong_count(&token), 3);
+    assert!(copy.into_inner().is_some());
+    assert!(item.take().is_some());
+    assert!(item.clone().into_inner().is_none());
+    assert_eq!(Rc::strong_count(&token), 1);
+}
+
+#[test]
+fn initialized_item_access() {
+    use core::num::NonZeroUsize;
+    use std::string::String;
+
+    let mut item = Item::<String>::new();
+    assert!(item.as_init().is_none());
+    assert!(item.as_init_mut().is_none());
+
+    let index = NonZeroUsize::new(7).unwrap();
+    item.write(index, String::from("hello"));
+    assert_eq!(item.as_init().unwrap().as_str(), "hello");
+    assert_eq!(item.as_init().unwrap().insertion_index(), index);
+
+    let init = item.as_init_mut().unwrap();
+    init.push_str(", world");
+    assert_eq!(init.insertion_index(), index);
+    **init = String::from("replaced");
+    assert_eq!(unsafe { item.assume_init() }.as_str(), "replaced");
+
+    let copy = item.clone();
+    assert_eq!(copy.as_init().unwrap().as_str(), "replaced");
+    assert_eq!(item.take().as_deref(), Some("replaced"));
+    assert!(item.as_init().is_none());
+    assert_eq!(copy.into_inner().as_deref(), Some("replaced"));
+}
+
+#[test]
+fn item_map_and_replace() {
+    use core::num::NonZeroUsize;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    let first = NonZeroUsize::new(1).unwrap();
+    let second = NonZeroUsize::new(2).unwrap();
+
+    let mut item = Item::<u32>::new();
+    item.map_in_place(|_| unreachable!());
+    assert!(item.as_init().is_none());
+    item.write(first, 21);
+    item.map_in_place(|v| v * 2);
+    assert_eq!(**item.as_init().unwrap(), 42);
+    assert_eq!(item.as_init().unwrap().insertion_index(), first);
+
+    item.replace_with(second, |old| {
+        assert_eq!(old, Some(42));
+        1
+    });
+    assert_eq!(**item.as_init().unwrap(), 1);
+    assert_eq!(item.as_init().unwrap().insertion_index(), second);
+
+    let mut item = Item::new();
+    item.replace_with(first, |old| {
+        assert!(old.is_none());
+        5
+    });
+    assert_eq!(item.into_inner(), Some(5));
+
+    // Panicking closures leave the slot empty and drop the value exactly once
+    let token = Rc::new(());
+    let mut item = Item::new();
+    item.write(first, token.clone());
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        item.map_in_place(|_| panic!("map"));
+    }));
+    assert!(result.is_err());
+    assert!(item.as_init().is_none());
+    assert_eq!(Rc::strong_count(&token), 1);
+
+    item.write(first, token.clone());
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        item.replace_with(second, |_| panic!("replace"));
+    }));
+    assert!(result.is_err());
+    assert!(item.as_init().is_none());
+    assert_eq!(Rc::strong_count(&token), 1);
+
+    item.write(first, token.clone());
+    item.map_in_place(|v| v);
+    item.replace_with(second, |old| old.unwrap());
+    assert_eq!(Rc::strong_count(&token), 2);
+    drop(item);
+    assert_eq!(Rc::strong_count(&token), 1);
+}
+
+#[test]
+fn item_accessors() {
+    use core::num::NonZeroUsize;
+    use std::format;
+    use std::string::String;
+
+    let mut empty = Item::<String>::default();
+    assert!(!empty.is_initialized());
+    assert_eq!(empty.insertion_index(), None);
+    assert_eq!(empty.get(), None);
+    assert_eq!(empty.get_mut(), None);
+    assert_eq!(format!("{:?}", empty), "Item { insertion_index: None }");
+    assert_eq!(empty, Item::new());
+
+    let index = NonZeroUsize::new(4).unwrap();
+    let mut item = Item::new();
+    item.write(index, String::from("a"));
+    assert!(item.is_initialized());
+    assert_eq!(item.insertion_index(), Some(index));
+    assert_eq!(item.get().map(String::as_str), Some("a"));
+    item.get_mut().unwrap().push('b');
+    assert_eq!(item.get().map(String::as_str), Some("ab"));
+    assert_eq!(
+        format!("{:?}", item),
+        "Item { insertion_index: Some(4), value: \"ab\" }"
+    );
+
+    assert_ne!(item, empty);
+    assert_eq!(item, item.clone());
+    let mut other = Item::new();
+    other.write(NonZeroUsize::new(5).unwrap(), String::from("ab"));
+    assert_ne!(item, other);
+    other.write(index, String::from("ab"));
+    assert_eq!(item, other);
+    other.write(index, String::from("b"));
+    assert_ne!(item, other);
+
+    item.take();
+    assert!(!item.is_initialized());
+    assert_eq!(item, empty);
+}
+
+#[test]
+fn item_slice_sort() {
+    use core::num::NonZeroUsize;
+    use std::rc::Rc;
+
+    let token = Rc::new(());
+    let mut seed = 7u64;
+    for len in [0, 1, 2, 5, 16, 33] {
+        let mut items = (0..len).map(|_| Item::new()).collect::<Vec<_>>();
+        let mut indexes = Vec::new();
+        for item in items.iter_mut() {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            if seed >> 62 != 0 {
+                let index = (seed >> 33) as usize + 1;
+                item.write(NonZeroUsize::new(index).unwrap(), (index, token.clone()));
+                indexes.push(index);
+            }
+        }
+        indexes.sort_unstable();
+        assert_eq!(Rc::strong_count(&token), indexes.len() + 1);
+
+        items.sort_by_insertion_index();
+        assert_eq!(Rc::strong_count(&token), indexes.len() + 1);
+        let prefix = items.initialized_prefix();
+        assert_eq!(prefix.len(), indexes.len());
+        for (item, &index) in prefix.iter().zip(&indexes) {
+            assert_eq!(item.insertion_index().get(), index);
+            assert_eq!(item.0, index);
+        }
+        assert!(items[indexes.len()..].iter().all(|i| !i.is_initialized()));
+
+        drop(items);
+        assert_eq!(Rc::strong_count(&token), 1);
+    }
+
+    // Stops at the first gap
+    let mut items = [Item::new(), Item::new(), Item::new()];
+    items[0].write(NonZeroUsize::new(1).unwrap(), 1);
+    items[2].write(NonZeroUsize::new(2).unwrap(), 2);
+    assert_eq!(items.initialized_prefix().len(), 1);
+    items.sort_by_insertion_index();
+    assert_eq!(items.initialized_prefix().len(), 2);
+}
+
+#[cfg(feature = "stats")]
+#[test]
+fn item_generations_match_overwrite_count() {
+    use core::num::NonZeroUsize;
+
+    let observations = if cfg!(miri) { 500 } else { 5000 };
+    let mut reservoir = IndexedSamplingReservoir::<usize, 8>::new();
+    for seen in 0..observations {
+        for (slot, item) in reservoir.as_unordered_items().iter().enumerate() {
+            assert_eq!(
+                item.generation() as usize,
+                math::overwrite_count::<8>(seen, slot),
+                "seen={} slot={}",
+                seen,
+                slot
+            );
+        }
+        let _ = reservoir.sample(seen);
+    }
+    let max = reservoir.as_unordered_items()[7].generation();
+    assert_eq!(reservoir.max_generation(), max);
+    assert_eq!(
+        IndexedSamplingReservoir::<usize, 8>::new().max_generation(),
+        0
+    );
+
+    // Clones keep generations, take and map_in_place don't count as writes
+    let index = NonZeroUsize::new(1).unwrap();
+    let mut item = Item::new();
+    item.write(index, 1);
+    item.write(index, 2);
+    item.replace_with(index, |v| v.unwrap() + 1);
+    assert_eq!(item.generation(), 3);
+    item.map_in_place(|v| v + 1);
+    assert_eq!(item.generation(), 3);
+    assert_eq!(item.as_init().unwrap().generation(), 3);
+    assert_eq!(item.clone().generation(), 3);
+    assert_eq!(item.take(), Some(4));
+    assert_eq!(item.generation(), 3);
+    assert_eq!(item.clone().generation(), 3);
+}
+
+#[test]
+fn sampling_outcome_helpers() {
+    use std::format;
+
+    let consumed = SamplingOutcome::<u8>::Consumed;
+    let reduced = SamplingOutcome::<u8>::ConsumedAndRateReduced {
+        old_divisor: 1,
+        new_divisor: 2,
+    };
+    let discarded = SamplingOutcome::Discarded(7u8);
+
+    assert!(consumed.is_consumed() && !consumed.is_discarded());
+    assert!(reduced.is_consumed() && !reduced.is_discarded());
+    assert!(!discarded.is_consumed() && discarded.is_discarded());
+    assert_eq!(consumed.clone().into_discarded(), None);
+    assert_eq!(reduced.clone().into_discarded(), None);
+    assert_eq!(discarded.clone().into_discarded(), Some(7));
+
+    assert_ne!(consumed, reduced);
+    assert_eq!(discarded, SamplingOutcome::Discarded(7));
+    assert_ne!(discarded, SamplingOutcome::Discarded(8));
+    assert_eq!(format!("{:?}", discarded), "Discarded(7)");
+    assert_eq!(
+        format!("{:?}", reduced),
+        "ConsumedAndRateReduced { old_divisor: 1, new_divisor: 2 }"
+    );
+}
+
+#[test]
+fn sampling_outcome_combinators() {
+    let reduced = SamplingOutcome::<u8>::ConsumedAndRateReduced {
+        old_divisor: 4,
+        new_divisor: 8,
+    };
+    assert_eq!(
+        reduced.as_ref(),
+        SamplingOutcome::ConsumedAndRateReduced {
+            old_divisor: 4,
+            new_divisor: 8,
+        }
+    );
+    assert_eq!(
+        reduced.clone().map(|_| unreachable!()),
+        SamplingOutcome::<()>::ConsumedAndRateReduced {
+            old_divisor: 4,
+            new_divisor: 8,
+        }
+    );
+    assert_eq!(reduced.value(), None);
+
+    assert_eq!(
+        SamplingOutcome::<u8>::Consumed.map(u32::from),
+        SamplingOutcome::Consumed
+    );
+    assert_eq!(
+        SamplingOutcome::<u8>::Consumed.as_ref(),
+        SamplingOutcome::Consumed
+    );
+
+    let discarded = SamplingOutcome::Discarded(Vec::from([1, 2, 3]));
+    assert_eq!(
+        discarded.as_ref().map(Vec::len),
+        SamplingOutcome::Discarded(3)
+    );
+    assert_eq!(discarded.as_ref().value(), Some(&Vec::from([1, 2, 3])));
+    assert_eq!(discarded.value(), Some(Vec::from([1, 2, 3])));
+}
+
+#[test]
+fn ordered_iter_double_ended() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let reference = buf.ordered_iter().copied().collect::<Vec<_>>();
+            assert!(buf.ordered_iter().rev().eq(reference.iter().rev()));
+
+            // Alternate between the ends until they meet
+            for front_first in [true, false] {
+                let mut iter = buf.ordered_iter();
+                let (mut front, mut back) = (0, reference.len());
+                let mut from_front = front_first;
+                while front < back {
+                    assert_eq!(iter.len(), back - front);
+                    if from_front {
+                        assert_eq!(iter.next(), Some(&reference[front]));
+                        front += 1;
+                    } else {
+                        back -= 1;
+                        assert_eq!(iter.next_back(), Some(&reference[back]));
+                    }
+                    from_front = !from_front;
+                }
+                assert_eq!(iter.next(), None);
+                assert_eq!(iter.next_back(), None);
+            }
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 100 } else { 3000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn ordered_iter_rev() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        let check_rev = |buf: &SamplingReservoir<usize, N>| {
+            let mut forward = buf.ordered_iter().collect::<Vec<_>>();
+            forward.reverse();
+            let rev = buf.ordered_iter_rev();
+            assert_eq!(rev.len(), forward.len());
+            assert_eq!(rev.collect::<Vec<_>>(), forward);
+        };
+        for seen in 0..observations {
+            check_rev(&buf);
+            let _ = buf.sample(seen);
+        }
+        // Deep steady state
+        for seen in observations..observations * 50 {
+            let _ = buf.sample(seen);
+        }
+        check_rev(&buf);
+    }
+    check::<2>(100);
+    check::<8>(1000);
+    check::<16>(1000);
+
+    // Exactly N
+    let mut buf = SamplingReservoir::<usize, 16>::new();
+    for i in 0..16 {
+        let _ = buf.sample(i);
+    }
+    assert!(buf.ordered_iter_rev().copied().eq((0..16).rev()));
+}
+
+#[test]
+fn ordered_iter_nth_last_count() {
+    fn filled<const N: usize>(observations: usize) -> SamplingReservoir<Vec<u8>, N> {
+        let mut buf = SamplingReservoir::<Vec<u8>, N>::new();
+        for i in 0..observations {
+            let _ = buf.sample(Vec::from([i as u8; 3]));
+        }
+        buf
+    }
+    fn check<const N: usize>(observations: usize) {
+        let buf = filled::<N>(observations);
+        let reference = buf.ordered_iter().cloned().collect::<Vec<_>>();
+        assert_eq!(buf.ordered_iter().count(), reference.len());
+        assert_eq!(buf.ordered_iter().last(), reference.last());
+        for step in 0..reference.len() + 2 {
+            let mut iter = buf.ordered_iter();
+            let mut expected = reference.iter();
+            loop {
+                let item = iter.nth(step);
+                assert_eq!(item, expected.nth(step));
+                assert_eq!(iter.len(), expected.len());
+                if item.is_none() {
+                    break;
+                }
+            }
+
+            // Owning, skipped values must be dropped exactly once
+            let mut iter = buf.clone().into_ordered_iter();
+            let mut expected = reference.iter();
+            loop {
+                let item = iter.nth(step);
+                assert_eq!(item.as_ref(), expected.nth(step));
+                if item.is_none() {
+                    break;
+                }
+            }
+            let mut iter = buf.clone().into_ordered_iter();
+            assert_eq!(iter.nth(step).as_ref(), reference.get(step));
+            // Dropped with leftovers
+        }
+        assert_eq!(buf.clone().into_ordered_iter().count(), reference.len());
+        assert_eq!(
+            buf.clone().into_ordered_iter().last().as_ref(),
+            reference.last()
+        );
+        let mut iter = buf.into_ordered_iter();
+        assert_eq!(iter.next().as_ref(), reference.first());
+        let expected_last = reference.last().filter(|_| reference.len() > 1);
+        assert_eq!(iter.last().as_ref(), expected_last);
+    }
+    let deep = if cfg!(miri) { 100 } else { 5000 };
+    check::<4>(0);
+    check::<4>(3);
+    check::<4>(deep);
+    check::<16>(16);
+    check::<16>(deep);
+
+    // Non-dropping values take the shortcut
+    let mut buf = SamplingReservoir::<u32, 8>::new();
+    for i in 0..100 {
+        let _ = buf.sample(i);
+    }
+    let mut iter = buf.into_ordered_iter();
+    assert_eq!(iter.nth(2), Some(32));
+    assert_eq!(iter.nth(3), Some(80));
+    assert_eq!(iter.nth(5), None);
+}
+
+#[test]
+fn ordered_iters_fused() {
+    use crate::buf::ReservoirOrderedIndexIter;
+
+    let mut buf = SamplingReservoir::<Vec<u8>, 8>::new();
+    for i in 0..50 {
+        let _ = buf.sample(Vec::from([i]));
+    }
+
+    let mut indices =
+        ReservoirOrderedIndexIter::<8>::new(buf.samples_seen(), buf.samples_accepted());
+    let mut iter = buf.ordered_iter();
+    let mut enumerated = buf.ordered_iter_enumerated();
+    assert_eq!(indices.by_ref().count(), 8);
+    assert_eq!(iter.by_ref().count(), 8);
+    assert_eq!(enumerated.by_ref().count(), 8);
+    for _ in 0..5 {
+        assert_eq!(indices.next(), None);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(enumerated.next(), None);
+    }
+
+    drop(enumerated);
+
+    let mut owning = buf.into_ordered_iter();
+    assert_eq!(owning.by_ref().count(), 8);
+    for _ in 0..5 {
+        assert_eq!(owning.next(), None);
+        assert_eq!(owning.nth(2), None);
+    }
+}
+
+#[test]
+fn ordered_iter_clone() {
+    struct NotClone(usize);
+
+    let mut buf = SamplingReservoir::<NotClone, 16>::new();
+    for i in 0..100 {
+        let _ = buf.sample(NotClone(i));
+    }
+    let reference = buf.ordered_iter().map(|v| v.0).collect::<Vec<_>>();
+    for split in 0..=16 {
+        let mut iter: crate::iter::ReservoirOrderedIter<'_, NotClone, 16> = buf.ordered_iter();
+        iter.by_ref().take(split).for_each(drop);
+        let clone = iter.clone();
+        assert_eq!(clone.len(), iter.len());
+        let rest = iter.map(|v| v.0).collect::<Vec<_>>();
+        assert_eq!(rest, reference[split..]);
+        assert_eq!(
+            clone.rev().map(|v| v.0).collect::<Vec<_>>(),
+            rest.iter().rev().copied().collect::<Vec<_>>()
+        );
+    }
+}
+
+#[test]
+fn ordered_chunks() {
+    fn check<const N: usize, const M: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let chunks = buf.ordered_chunks::<M>();
+            assert_eq!(chunks.len(), buf.len().div_ceil(M));
+            let chunks = chunks.collect::<Vec<_>>();
+            assert_eq!(chunks.len(), buf.len().div_ceil(M));
+            if let Some((last, full)) = chunks.split_last() {
+                assert!(full.iter().all(|chunk| chunk.len() == M));
+                assert_eq!(last.len(), buf.len() - full.len() * M);
+            }
+            assert!(chunks.into_iter().flatten().eq(buf.ordered_iter()));
+            let _ = buf.sample(seen);
+        }
+    }
+    check::<8, 1>(100);
+    check::<8, 3>(100);
+    check::<16, 4>(300);
+    check::<16, 5>(300);
+    check::<64, 32>(1000);
+    check::<4, 100>(50);
+
+    let buf = SamplingReservoir::<u8, 4>::new();
+    assert_eq!(buf.ordered_chunks::<2>().next(), None);
+}
+
+#[test]
+fn ordered_pairs() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let enumerated = buf.ordered_iter_enumerated().collect::<Vec<_>>();
+            let pairs = buf.ordered_pairs();
+            assert_eq!(pairs.len(), buf.len().saturating_sub(1));
+            let expected = enumerated
+                .windows(2)
+                .map(|w| (w[0].1, w[1].1, w[1].0 - w[0].0));
+            assert!(pairs.eq(expected), "N={} seen={}", N, seen);
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 200 } else { 5000 };
+    check::<2>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn into_ordered_array() {
+    use std::string::{String, ToString};
+
+    let mut buf = SamplingReservoir::<String, 8>::new();
+    for i in 0..8 {
+        buf = buf.into_ordered_array().unwrap_err();
+        assert_eq!(buf.len(), i);
+        assert!(buf
+            .ordered_iter()
+            .map(String::as_str)
+            .eq((0..i).map(|i| i.to_string())));
+        let _ = buf.sample(i.to_string());
+    }
+    for i in 8..100 {
+        let _ = buf.sample(i.to_string());
+    }
+    let expected = buf.ordered_iter().cloned().collect::<Vec<_>>();
+    let array = buf.into_ordered_array().ok().unwrap();
+    assert_eq!(array[..], expected[..]);
+}
+
+#[test]
+fn ordered_iter_timed() {
+    let mut buf = SamplingReservoir::<usize, 16>::new();
+    for seen in 0..if cfg!(miri) { 300 } else { 5000 } {
+        for period in [1, 10, 1_000_000_007] {
+            let timed = buf.ordered_iter_timed(period).collect::<Vec<_>>();
+            assert_eq!(timed.len(), buf.len());
+            assert!(timed.windows(2).all(|w| w[0].0 < w[1].0));
+            assert!(timed.iter().zip(buf.ordered_iter_enumerated()).all(
+                |(&(time, a), (outer_index, b))| time == outer_index as u64 * period
+                    && core::ptr::eq(a, b)
+            ));
+
+            let offset = buf.ordered_iter_timed_from(1234, period);
+            assert!(offset
+                .map(|(t, _)| t - 1234)
+                .eq(timed.iter().map(|(t, _)| *t)));
+        }
+        let _ = buf.sample(seen);
+    }
+
+    // Long runs don't overflow
+    let mut buf = SamplingReservoir::<u32, 4>::new();
+    for i in 0..=1 << 14 {
+        let _ = buf.sample(i);
+    }
+    let (time, &value) = buf.ordered_iter_timed(1 << 40).last().unwrap();
+    assert_eq!(value, 1 << 14);
+    assert_eq!(time, 1 << 54);
+}
+
+#[test]
+fn reservoir_sample_ext() {
+    let empty = core::iter::empty::<u8>().sample_reservoir::<8>();
+    assert!(empty.is_empty());
+    assert_eq!(empty.samples_seen(), 0);
+
+    let short = (0..5).sample_reservoir::<8>();
+    assert!(short.ordered_iter().copied().eq(0..5));
+
+    for len in [0, 3, 8, 9, 100, 4321] {
+        let mut manual = SamplingReservoir::<usize, 8>::new();
+        for i in 0..len {
+            let _ = manual.sample(i);
+        }
+        let sampled = (0..len).sample_reservoir::<8>();
+        assert!(sampled.ordered_iter().eq(manual.ordered_iter()));
+        assert_eq!(sampled.samples_seen(), len);
+
+        // Continuing into an existing reservoir
+        let mut reservoir = (0..len / 2).sample_reservoir::<8>();
+        let stats = (len / 2..len).sample_into(&mut reservoir);
+        assert!(reservoir.ordered_iter().eq(manual.ordered_iter()));
+        assert_eq!(stats.seen, len - len / 2);
+        let stored_before = (0..len / 2).sample_reservoir::<8>().samples_accepted();
+        assert_eq!(stats.stored, manual.samples_accepted() - stored_before);
+    }
+
+    let mut reservoir = SamplingReservoir::<u32, 8, PowerOfTwoRate>::new();
+    let stats = (0..8 << 5).sample_into(&mut reservoir);
+    assert_eq!(
+        stats,
+        SampleStats {
+            seen: 8 << 5,
+            stored: 8 + 4 * 5,
+            rate_reductions: 5,
+        }
+    );
+}
+
+#[test]
+fn ordered_iter_weighted() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let weighted = buf.ordered_iter_weighted();
+            assert_eq!(weighted.len(), buf.len());
+            let weighted = weighted.collect::<Vec<_>>();
+            assert_eq!(weighted.iter().map(|(_, w)| w).sum::<usize>(), seen);
+            assert!(weighted.iter().all(|&(_, w)| w > 0));
+            // Each value is its own outer index
+            for pair in weighted.windows(2) {
+                assert_eq!(pair[0].0 + pair[0].1, *pair[1].0);
+            }
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 200 } else { 5000 };
+    check::<2>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn ordered_iter_fold_matches_next() {
+    use crate::buf::ReservoirOrderedIndexIter;
+
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..observations {
+            let stored = buf.samples_accepted();
+            for (front, back) in [(0, 0), (1, 0), (0, 1), (3, 2), (N / 2, 1)] {
+                let mut indices = ReservoirOrderedIndexIter::<N>::new(seen, stored);
+                indices.by_ref().take(front).for_each(drop);
+                indices.by_ref().rev().take(back).for_each(drop);
+                // `for` steps with next()
+                let mut stepped = Vec::new();
+                for idx in indices.clone() {
+                    stepped.push(idx);
+                }
+                let folded = indices.fold(Vec::new(), |mut acc, idx| {
+                    acc.push(idx);
+                    acc
+                });
+                assert_eq!(folded, stepped, "N={} seen={}", N, seen);
+            }
+
+            let mut stepped = Vec::new();
+            for item in buf.ordered_iter_enumerated() {
+                stepped.push(item);
+            }
+            let mut folded = Vec::new();
+            buf.ordered_iter_enumerated()
+                .for_each(|item| folded.push(item));
+            assert_eq!(folded, stepped);
+            assert_eq!(
+                buf.ordered_iter().sum::<usize>(),
+                stepped.iter().map(|(_, v)| **v).sum::<usize>()
+            );
+            let _ = buf.sample(seen);
+        }
+    }
+    let observations = if cfg!(miri) { 100 } else { 5000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn ordered_peekable_merge() {
+    // Two reservoirs fed from the same stream, merged back by observation index
+    let mut even = SamplingReservoir::<usize, 8>::new();
+    let mut all = SamplingReservoir::<usize, 16>::new();
+    for seen in 0..3000 {
+        let mut a = even.ordered_peekable();
+        let mut b = all.ordered_peekable();
+        let mut merged = Vec::new();
+        loop {
+            let next = match (a.peek(), b.peek()) {
+                (Some((i, _)), Some((j, _))) if i <= j => a.next(),
+                (_, Some(_)) => b.next(),
+                (Some(_), None) => a.next(),
+                (None, None) => break,
+            };
+            merged.push(next.unwrap());
+        }
+        assert_eq!(a.next(), None);
+        assert_eq!(b.peek(), None);
+
+        let mut expected = even
+            .ordered_iter_enumerated()
+            .chain(all.ordered_iter_enumerated())
+            .collect::<Vec<_>>();
+        expected.sort_by_key(|(i, _)| *i);
+        assert_eq!(merged, expected);
+        assert!(merged.iter().all(|(i, v)| *i == **v));
+
+        // next_if() consumes only matching items
+        let mut iter = all.ordered_peekable();
+        let limit = seen / 2;
+        let mut early = 0;
+        while iter.next_if(|i, _| i < limit).is_some() {
+            early += 1;
+        }
+        assert_eq!(
+            early,
+            all.ordered_iter_enumerated()
+                .filter(|(i, _)| *i < limit)
+                .count()
+        );
+        assert_eq!(iter.len(), all.len() - early);
+        assert_eq!(iter.peek(), iter.next());
+
+        let _ = even.sample(seen);
+        let _ = all.sample(seen);
+    }
+}
+
+#[test]
+fn from_ordered_roundtrip() {
+    fn check<const N: usize>() {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for seen in 0..if cfg!(miri) { 300 } else { 5000 } {
+            let mut restored =
+                SamplingReservoir::<usize, N>::from_ordered(seen, buf.ordered_iter().copied())
+                    .unwrap();
+            assert_eq!(restored.as_unordered_slice(), buf.as_unordered_slice());
+            assert_eq!(restored.sampling_rate(), buf.sampling_rate());
+            assert_eq!(restored.samples_accepted(), buf.samples_accepted());
+            assert_eq!(restored.samples_seen(), seen);
+
+            assert!(
+                SamplingReservoir::<usize, N>::from_ordered(
+                    seen,
+                    buf.ordered_iter().copied().skip(1)
+                )
+                .is_err()
+                    || buf.is_empty()
+            );
+            assert!(SamplingReservoir::<usize, N>::from_ordered(
+                seen,
+                buf.ordered_iter().copied().chain([0])
+            )
+            .is_err());
+
+            // Both continue identically
+            let mut original = buf.clone();
+            for i in seen..seen + 2 * N {
+                assert_eq!(restored.sample(i), original.sample(i));
+            }
+            assert_eq!(restored.as_unordered_slice(), original.as_unordered_slice());
+            let _ = buf.sample(seen);
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<16>();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn ordered_export_roundtrip() {
+    use std::string::String;
+
+    let mut buf = SamplingReservoir::<u32, 16>::new();
+    for seen in 0..if cfg!(miri) { 100 } else { 2000 } {
+        let expected =
+            SamplingReservoir::<u32, 16>::from_ordered(seen as usize, buf.ordered_iter().copied())
+                .unwrap();
+
+        let json = serde_json::to_string(&buf.ordered_export()).unwrap();
+        let import: OrderedImport<u32, 16> = serde_json::from_str(&json).unwrap();
+        assert_eq!(import.samples_seen, seen as usize);
+        assert_eq!(
+            &import.values[..],
+            &buf.ordered_iter().copied().collect::<Vec<_>>()[..]
+        );
+        let from_json = import.into_reservoir().unwrap();
+        assert_eq!(
+            from_json.as_unordered_slice(),
+            expected.as_unordered_slice()
+        );
+        assert_eq!(from_json.sampling_rate(), expected.sampling_rate());
+        assert_eq!(from_json.samples_accepted(), expected.samples_accepted());
+
+        let bytes = postcard::to_allocvec(&buf.ordered_export()).unwrap();
+        let import: OrderedImport<u32, 16> = postcard::from_bytes(&bytes).unwrap();
+        let from_postcard = import.into_reservoir().unwrap();
+        assert_eq!(
+            from_postcard.as_unordered_slice(),
+            expected.as_unordered_slice()
+        );
+        assert_eq!(from_postcard.sampling_rate(), expected.sampling_rate());
+
+        let mut direct = serde_json::Serializer::new(std::vec::Vec::new());
+        buf.serialize_ordered(&mut direct).unwrap();
+        assert_eq!(String::from_utf8(direct.into_inner()).unwrap(), json);
+
+        let _ = buf.sample(seen);
+    }
+
+    // Too many values for the capacity
+    let import =
+        serde_json::from_str::<OrderedImport<u32, 2>>(r#"{"samples_seen":3,"values":[0,1,2]}"#);
+    assert!(import.is_err());
+}
+
+#[cfg(feature = "defmt")]
+#[test]
+fn defmt_format_impls() {
+    fn assert_format<T: defmt::Format>() {}
+
+    assert_format::<SamplingRate>();
+    assert_format::<SampleStats>();
+    assert_format::<SamplingOutcome<u32>>();
+    assert_format::<SamplingReservoir<u32, 16>>();
+    assert_format::<SamplingReservoir<u32, 16, PowerOfTwoRate>>();
+}
+
+#[cfg(feature = "ufmt")]
+#[test]
+fn ufmt_rendering() {
+    use heapless::String;
+    use ufmt::uwrite;
+
+    let mut buf = SamplingReservoir::<u32, 4>::new();
+    let mut out = String::<128>::new();
+    uwrite!(out, "[{}]", buf.ordered_display(" ")).unwrap();
+    assert_eq!(out, "[seen 0, accepted 0: ]");
+
+    let mut outcomes = String::<128>::new();
+    for i in 0..5 {
+        uwrite!(outcomes, "{};", buf.sample(i)).unwrap();
+    }
+    assert_eq!(
+        outcomes,
+        "consumed;consumed;consumed;consumed;consumed, rate reduced;"
+    );
+    for i in 5..20 {
+        let _ = buf.sample(i);
+    }
+
+    out.clear();
+    uwrite!(out, "{} {:?}", buf.sampling_rate(), buf.sampling_rate()).unwrap();
+    assert_eq!(out, "1/8 SamplingRate { divisor: 8, counter: 3 }");
+
+    out.clear();
+    uwrite!(out, "{}", buf.ordered_display("|")).unwrap();
+    assert_eq!(out, "seen 20, accepted 9: 0|8|12|16");
+
+    out.clear();
+    for i in 20..33 {
+        let outcome = buf.sample(i);
+        if i % 4 == 0 {
+            uwrite!(out, "{:?};", outcome).unwrap();
+        }
+    }
+    assert_eq!(
+        out,
+        "Discarded(20);Consumed;Discarded(28);\
+         ConsumedAndRateReduced { old_divisor: 8, new_divisor: 16 };"
+    );
+}
+
+#[test]
+fn bytes_roundtrip() {
+    let mut buf = SamplingReservoir::<u32, 8>::new();
+    let mut bytes = [0u8; 128];
+    for seen in 0..if cfg!(miri) { 100 } else { 2000 } {
+        assert_eq!(
+            buf.to_bytes(&mut bytes[..buf.encoded_len() - 1]),
+            Err(BufferTooSmall)
+        );
+        let len = buf.to_bytes(&mut bytes).unwrap();
+        assert_eq!(len, buf.encoded_len());
+        let encoded = &bytes[..len];
+
+        let restored = SamplingReservoir::<u32, 8>::from_bytes(encoded).unwrap();
+        assert_eq!(restored.as_unordered_slice(), buf.as_unordered_slice());
+        assert_eq!(restored.sampling_rate(), buf.sampling_rate());
+        assert_eq!(restored.samples_accepted(), buf.samples_accepted());
+        assert_eq!(restored.samples_seen(), buf.samples_seen());
+
+        for cut in 0..len {
+            assert_eq!(
+                SamplingReservoir::<u32, 8>::from_bytes(&encoded[..cut]).err(),
+                Some(DecodeError::Truncated)
+            );
+        }
+        assert_eq!(
+            SamplingReservoir::<u32, 8>::from_bytes(&bytes[..len + 1]).err(),
+            Some(DecodeError::TrailingBytes)
+        );
+
+        // Any corrupted header byte is rejected
+        let header_len = len - buf.len() * 4;
+        for i in 0..header_len {
+            let mut corrupted = [0u8; 128];
+            corrupted[..len].copy_from_slice(encoded);
+            corrupted[i] ^= 0xff;
+            assert!(SamplingReservoir::<u32, 8>::from_bytes(&corrupted[..len]).is_err());
+        }
+
+        let _ = buf.sample(seen);
+    }
+
+    let len = buf.to_bytes(&mut bytes).unwrap();
+    assert_eq!(
+        SamplingReservoir::<u32, 16>::from_bytes(&bytes[..len]).err(),
+        Some(DecodeError::CapacityMismatch)
+    );
+    assert_eq!(
+        SamplingReservoir::<u16, 8>::from_bytes(&bytes[..len]).err(),
+        Some(DecodeError::ValueSizeMismatch)
+    );
+    bytes[0] = b'X';
+    assert_eq!(
+        SamplingReservoir::<u32, 8>::from_bytes(&bytes[..len]).err(),
+        Some(DecodeError::BadMagic)
+    );
+    bytes[0] = b'I';
+    bytes[4] = 2;
+    assert_eq!(
+        SamplingReservoir::<u32, 8>::from_bytes(&bytes[..len]).err(),
+        Some(DecodeError::UnsupportedVersion(2))
+    );
+}
+
+#[test]
+fn from_iterator() {
+    let collected: SamplingReservoir<u32, 8> = (0..100).collect();
+    let sampled = (0..100).sample_reservoir::<8>();
+    assert!(collected.ordered_iter().eq(sampled.ordered_iter()));
+    assert_eq!(collected.samples_seen(), 100);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn std_conversions() {
+    use crate::iter::{InvalidCapacity, InvalidState};
+    use std::boxed::Box;
+    use std::error::Error;
+    use std::string::ToString;
+
+    let buf: SamplingReservoir<u32, 8> = (0..100).collect();
+    let expected: Vec<u32> = buf.ordered_iter().copied().collect();
+    assert_eq!(Vec::from(buf), expected);
+
+    let errors: [Box<dyn Error>; 6] = [
+        Box::new(InvalidState),
+        Box::new(InvalidCapacity),
+        Box::new(InvalidSchedule::ZeroDivisor),
+        Box::new(BufferTooSmall),
+        Box::new(DecodeError::Truncated),
+        Box::new(DecodeError::UnsupportedVersion(7)),
+    ];
+    for error in &errors {
+        assert!(!error.to_string().is_empty());
+    }
+    assert_eq!(errors[5].to_string(), "unsupported encoding version 7");
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn rate_limited_log() {
+    use std::string::{String, ToString};
+    use std::sync::Mutex;
+
+    static MESSAGES: Mutex<Vec<(log::Level, String)>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let message = (record.level(), record.args().to_string());
+            MESSAGES.lock().unwrap().push(message);
+        }
+
+        fn flush(&self) {}
+    }
+
+    log::set_logger(&Capture).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut limiter = RateLimitedLog::new(4);
+    for i in 0..10 {
+        sampled_log!(limiter, warn, "hot path {}", i);
+    }
+    assert_eq!(limiter.suppressed(), 1);
+    let mut every = RateLimitedLog::new(1);
+    for i in 0..2 {
+        sampled_log!(every, info, "every {}", i);
+    }
+
+    let messages = MESSAGES.lock().unwrap();
+    let expected = [
+        (log::Level::Warn, "hot path 0"),
+        (log::Level::Warn, "hot path 4 (and 3 suppressed)"),
+        (log::Level::Warn, "hot path 8 (and 3 suppressed)"),
+        (log::Level::Info, "every 0"),
+        (log::Level::Info, "every 1"),
+    ];
+    assert_eq!(messages.len(), expected.len());
+    for ((level, message), (expected_level, expected_message)) in messages.iter().zip(expected) {
+        assert_eq!(level, &expected_level);
+        assert_eq!(message, expected_message);
+    }
+
+    // Suppression counts without a logger
+    let mut limiter = RateLimitedLog::new(5);
+    let mut counts = Vec::new();
+    for _ in 0..23 {
+        limiter.maybe_with_suppressed(|suppressed| counts.push(suppressed));
+    }
+    assert_eq!(counts, [0, 4, 4, 4, 4]);
+    assert_eq!(limiter.suppressed(), 2);
+
+    let mut calls = 0;
+    for _ in 0..23 {
+        limiter.maybe(|| calls += 1);
+    }
+    assert_eq!(calls, 5);
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_reservoir_layer() {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let layer = ReservoirLayer::<32>::new()
+        .with_max_level(tracing::Level::INFO)
+        .with_max_event_len(24);
+    let subscriber = tracing_subscriber::registry().with(layer.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        for i in 0..5000 {
+            tracing::info!(i, "event");
+            tracing::debug!(i, "ignored");
+            if i % 1000 == 0 {
+                tracing::warn!("long message that does not fit: {}", "x".repeat(100));
+            }
+        }
+    });
+
+    let events = layer.drain_ordered();
+    assert_eq!(events.len(), 32);
+    assert!(events.iter().all(|event| event.len() <= 24));
+    assert!(events.iter().all(|event| !event.contains("ignored")));
+    assert_eq!(events[0], "INFO event i=0");
+
+    // Chronological, and spread over the whole stream
+    let indices: Vec<usize> = events
+        .iter()
+        .filter_map(|event| event.strip_prefix("INFO event i=")?.parse().ok())
+        .collect();
+    assert!(indices.len() > 24);
+    assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(*indices.last().unwrap() > 4000);
+
+    assert!(layer.drain_ordered().is_empty());
+
+    // Truncated on a char boundary
+    let layer = ReservoirLayer::<4>::new().with_max_event_len(24);
+    let subscriber = tracing_subscriber::registry().with(layer.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::warn!("long message that does not fit");
+        tracing::error!(code = "ééééééééé");
+    });
+    assert_eq!(
+        layer.drain_ordered(),
+        ["WARN long message that d", "ERROR code=éééééé"]
+    );
+}
+
+#[test]
+fn stamped_wrapping_ticks() {
+    let mut buf = SamplingReservoir::<Stamped<u32, u32>, 16>::new();
+    assert_eq!(buf.duration_covered(), None);
+    assert_eq!(buf.newest_age(0), None);
+
+    let start = u32::MAX - 500;
+    let _ = buf.sample_at(start, 0);
+    assert_eq!(buf.duration_covered(), Some(0));
+    assert_eq!(buf.newest_age(start.wrapping_add(7)), Some(7));
+
+    for i in 1..1000 {
+        let _ = buf.sample_at(start.wrapping_add(i), i);
+    }
+    let newest = buf.ordered_iter().next_back().unwrap();
+    assert_eq!(buf.duration_covered(), Some(newest.value));
+    assert_eq!(
+        buf.newest_age(start.wrapping_add(1000)),
+        Some(1000 - newest.value)
+    );
+}
+
+#[cfg(feature = "fugit")]
+#[test]
+fn stamped_fugit_instants() {
+    type Instant = fugit::Instant<u64, 1, 1000>;
+    type Duration = fugit::Duration<u64, 1, 1000>;
+
+    // Starts right below the end of the u64 range and wraps around
+    let start = u64::MAX - 10_000;
+    let mut buf = SamplingReservoir::<Stamped<u32, Instant>, 32>::new();
+    for i in 0..50_000u32 {
+        let now = Instant::from_ticks(start.wrapping_add(u64::from(i) * 3));
+        let _ = buf.sample_at(now, i);
+    }
+
+    let oldest = buf.ordered_iter().next().unwrap();
+    let newest = buf.ordered_iter().next_back().unwrap();
+    assert_eq!(oldest.value, 0);
+    assert!(buf
+        .ordered_iter()
+        .zip(buf.ordered_iter().skip(1))
+        .all(|(a, b)| a.at < b.at));
+    assert_eq!(
+        buf.duration_covered(),
+        Some(Duration::from_ticks(u64::from(newest.value) * 3))
+    );
+
+    let now = Instant::from_ticks(start.wrapping_add(150_000));
+    assert_eq!(
+        buf.newest_age(now),
+        Some(Duration::from_ticks(150_000 - u64::from(newest.value) * 3))
+    );
+    assert_eq!(
+        buf.newest_age(newest.at + Duration::from_ticks(u64::MAX - 1)),
+        Some(Duration::from_ticks(u64::MAX - 1))
+    );
+}
+
+#[cfg(feature = "rand_core")]
+#[test]
+fn jittered_reservoir_periods() {
+    type Plain = SamplingReservoir<usize, 16>;
+
+    let observations = if cfg!(miri) { 2_000 } else { 100_000 };
+    let mut buf = JitteredReservoir::<usize, 16, _>::new(TestRng(3));
+    let mut accepted = Vec::new();
+    for i in 0..observations {
+        if !buf.sample(i).is_discarded() {
+            accepted.push(i);
+        }
+        // Counted up to the end of the current period at most
+        let reservoir = buf.reservoir();
+        let divisor = reservoir.sampling_rate().divisor() as usize;
+        assert!(i < reservoir.samples_seen() && reservoir.samples_seen() <= i + divisor);
+        reservoir.debug_validate().unwrap();
+    }
+
+    // Exactly one accepted observation per period of the plain schedule,
+    // including the current period, which may end past the last observation
+    let nominal = (0..observations * 2)
+        .filter(|i| Plain::should_sample(*i))
+        .collect::<Vec<_>>();
+    let ended = nominal.iter().filter(|i| **i < observations).count();
+    assert!(accepted.len() == ended || accepted.len() == ended + 1);
+    assert_eq!(accepted[0], 0);
+    for (k, i) in accepted.iter().enumerate().skip(1) {
+        assert!(nominal[k - 1] < *i && *i <= nominal[k]);
+    }
+    assert!(accepted.iter().zip(&nominal).any(|(a, n)| a != n));
+
+    // The stored values are ordered and within one period of their nominal indexes
+    let reservoir = buf.reservoir();
+    let stored = reservoir.ordered_iter_enumerated().collect::<Vec<_>>();
+    assert_eq!(stored.len(), 16);
+    for pair in stored.windows(2) {
+        let ((prev_nominal, prev), (nominal, value)) = (pair[0], pair[1]);
+        assert!(prev < value);
+        assert!(prev_nominal < *value && *value <= nominal);
+    }
+
+    // Same seed, same selection
+    let mut other = JitteredReservoir::<usize, 16, _>::new(TestRng(3));
+    for i in 0..observations {
+        let _ = other.sample(i);
+    }
+    assert_eq!(
+        other.reservoir().as_unordered_slice(),
+        reservoir.as_unordered_slice()
+    );
+}
+
+#[cfg(feature = "rand_core")]
+#[test]
+fn jittered_reservoir_stays_ordered() {
+    let observations = if cfg!(miri) { 300 } else { 5_000 };
+    let mut bytes = [0u8; 128];
+    for seed in 0..if cfg!(miri) { 2 } else { 20 } {
+        let mut buf = JitteredReservoir::<u32, 8, _>::new(TestRng(seed));
+        for i in 0..observations {
+            let _ = buf.sample(i);
+            let reservoir = buf.reservoir();
+            reservoir.debug_validate().unwrap();
+
+            // Ordered, and each value within one period of its nominal index
+            let stored = reservoir.ordered_iter_enumerated().collect::<Vec<_>>();
+            let mut prev_nominal = None;
+            for &(nominal, &value) in &stored {
+                assert!(value as usize <= nominal);
+                assert!(prev_nominal.is_none_or(|prev| prev < value as usize));
+                prev_nominal = Some(nominal);
+            }
+
+            let len = reservoir.to_bytes(&mut bytes).unwrap();
+            let restored = SamplingReservoir::<u32, 8>::from_bytes(&bytes[..len]).unwrap();
+            assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+            assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+        }
+    }
+}
+
+#[test]
+fn debug_validate_live() {
+    let mut buf = SamplingReservoir::<u8, 8>::new();
+    let mut pow2 = SamplingReservoir::<u8, 8, PowerOfTwoRate>::new();
+    for i in 0..if cfg!(miri) { 500 } else { 20_000 } {
+        assert_eq!(buf.debug_validate(), Ok(()));
+        assert_eq!(pow2.debug_validate(), Ok(()));
+        let _ = buf.sample(i as u8);
+        let _ = pow2.sample(i as u8);
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+#[test]
+fn arbitrary_reservoirs_are_consistent() {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    let mut state = 0x2545f4914f6cdd1du64;
+    let mut bytes = [0u8; 256];
+    for _ in 0..if cfg!(miri) { 50 } else { 2000 } {
+        for byte in bytes.iter_mut() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            *byte = state as u8;
+        }
+        let mut u = Unstructured::new(&bytes);
+
+        let rate = SamplingRate::arbitrary(&mut u).unwrap();
+        assert!(rate.counter() < rate.divisor());
+
+        let Ok(mut buf) = SamplingReservoir::<u16, 16>::arbitrary(&mut u) else {
+            continue;
+        };
+        assert_eq!(buf.debug_validate(), Ok(()));
+        let indices = buf
+            .ordered_iter_enumerated()
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(indices.last().is_none_or(|last| *last < buf.samples_seen()));
+
+        // Keeps sampling consistently
+        for i in 0..64 {
+            let _ = buf.sample(i);
+            assert_eq!(buf.debug_validate(), Ok(()));
+        }
+    }
+}
+
+#[test]
+fn csv_snapshot() {
+    use heapless::String;
+
+    let mut buf = SamplingReservoir::<i32, 8>::new();
+    let mut out = String::<512>::new();
+    buf.write_csv(&mut out, CsvOptions::default()).unwrap();
+    assert_eq!(out, "chronological_pos,outer_index,value\n");
+
+    for i in 0..40 {
+        let _ = buf.sample(i * i - 100);
+    }
+    out.clear();
+    buf.write_csv(&mut out, CsvOptions::default()).unwrap();
+    assert_eq!(
+        out,
+        "chronological_pos,outer_index,value\n\
+         0,0,-100\n\
+         1,8,-36\n\
+         2,12,44\n\
+         3,16,156\n\
+         4,20,300\n\
+         5,24,476\n\
+         6,28,684\n\
+         7,32,924\n"
+    );
+
+    out.clear();
+    let options = CsvOptions {
+        separator: ';',
+        header: false,
+        ..Default::default()
+    };
+    buf.write_csv(&mut out, options).unwrap();
+    assert!(out.starts_with("0;0;-100\n1;8;-36\n"));
+    assert_eq!(out.lines().count(), 8);
+
+    // Stops at the first error of the writer
+    let mut small = String::<16>::new();
+    assert!(buf.write_csv(&mut small, CsvOptions::default()).is_err());
+}
+
+/// A heap-backed pool that counts its free blocks
+#[cfg(feature = "pool")]
+struct CountingPool {
+    free: std::rc::Rc<core::cell::Cell<usize>>,
+}
+
+#[cfg(feature = "pool")]
+struct CountingHandle {
+    value: std::boxed::Box<[u8; 64]>,
+    free: std::rc::Rc<core::cell::Cell<usize>>,
+}
+
+#[cfg(feature = "pool")]
+impl core::ops::Deref for CountingHandle {
+    type Target = [u8; 64];
+
+    fn deref(&self) -> &[u8; 64] {
+        &self.value
+    }
+}
+
+#[cfg(feature = "pool")]
+impl core::ops::DerefMut for CountingHandle {
+    fn deref_mut(&mut self) -> &mut [u8; 64] {
+        &mut self.value
+    }
+}
+
+#[cfg(feature = "pool")]
+impl Drop for CountingHandle {
+    fn drop(&mut self) {
+        self.free.set(self.free.get() + 1);
+    }
+}
+
+#[cfg(feature = "pool")]
+impl crate::ObjectPool for CountingPool {
+    type Item = [u8; 64];
+    type Handle = CountingHandle;
+
+    fn alloc(&self, value: [u8; 64]) -> Result<CountingHandle, [u8; 64]> {
+        if self.free.get() == 0 {
+            return Err(value);
+        }
+        self.free.set(self.free.get() - 1);
+        Ok(CountingHandle {
+            value: std::boxed::Box::new(value),
+            free: self.free.clone(),
+        })
+    }
+}
+
+#[test]
+#[cfg(feature = "pool")]
+fn pooled_reservoir_never_leaks() {
+    use crate::PooledReservoir;
+    use std::rc::Rc;
+
+    let free = Rc::new(core::cell::Cell::new(8));
+    let new_pooled = || PooledReservoir::<_, 8>::new(CountingPool { free: free.clone() });
+
+    let mut pooled = new_pooled();
+    let mut plain = SamplingReservoir::<u8, 8>::new();
+    for i in 0..1000u32 {
+        let value = i as u8;
+        let outcome = pooled.sample([value; 64]).unwrap();
+        assert_eq!(outcome.is_discarded(), plain.sample(value).is_discarded());
+        // Only the stored values hold blocks
+        assert_eq!(free.get(), 8 - pooled.reservoir().len());
+    }
+    assert!(pooled
+        .ordered_iter()
+        .map(|block| block[0])
+        .eq(plain.ordered_iter().copied()));
+    drop(pooled);
+    assert_eq!(free.get(), 8);
+
+    // Dropping the owning iterator early returns the remaining blocks
+    let mut pooled = new_pooled();
+    for i in 0..100u8 {
+        let _ = pooled.sample([i; 64]).unwrap();
+    }
+    let mut iter = pooled.into_ordered_iter();
+    let first = iter.next().unwrap();
+    assert_eq!(first[0], 0);
+    drop(iter);
+    assert_eq!(free.get(), 7);
+    drop(first);
+    assert_eq!(free.get(), 8);
+
+    // An exhausted pool hands the value back without counting it
+    free.set(1);
+    let mut pooled = new_pooled();
+    assert!(pooled.sample([1; 64]).is_ok());
+    assert_eq!(pooled.sample([2; 64]).err(), Some([2; 64]));
+    assert_eq!(pooled.reservoir().samples_seen(), 1);
+    drop(pooled);
+    assert_eq!(free.get(), 1);
+}
+
+#[test]
+fn bytes_roundtrip_rate_saturated() {
+    let mut bytes = [0u8; 64];
+    for accepted in 0..10 {
+        let reservoir = rate_saturated_reservoir(accepted);
+        let len = reservoir.to_bytes(&mut bytes).unwrap();
+        let restored = SamplingReservoir::<u32, 4>::from_bytes(&bytes[..len]).unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+        assert_eq!(restored.samples_accepted(), reservoir.samples_accepted());
+        assert_eq!(restored.samples_seen(), reservoir.samples_seen());
+    }
+}
+
+#[test]
+fn bytes_roundtrip_at_seen_ceiling() {
+    let max = SamplingReservoir::<u32, 4>::MAX_SAMPLES_SEEN;
+    let least = math::samples_accepted_saturating::<4>(max);
+    let at_ceiling = |samples_accepted, phase| {
+        let reductions = math::rate_reductions_for::<4>(samples_accepted).min(31);
+        let mut rate = SamplingRate::new(1 << reductions);
+        rate.set_phase(phase);
+        SamplingReservoir::<u32, 4>::from_parts((0..4).collect(), rate, samples_accepted, max)
+    };
+
+    let mut bytes = [0u8; 64];
+    for extra in 0..10 {
+        let reservoir = at_ceiling(least + extra, extra as u32 * 3);
+        reservoir.debug_validate().unwrap();
+        let len = reservoir.to_bytes(&mut bytes).unwrap();
+        let mut restored = SamplingReservoir::<u32, 4>::from_bytes(&bytes[..len]).unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+        assert_eq!(restored.samples_accepted(), reservoir.samples_accepted());
+        assert_eq!(restored.samples_seen(), max);
+
+        let mut original = reservoir;
+        for i in 0..4 {
+            assert_eq!(original.skip_discarded(), restored.skip_discarded());
+            assert_eq!(original.sample(i), restored.sample(i));
+            assert!(original.ordered_iter().eq(restored.ordered_iter()));
+        }
+    }
+
+    // Fewer accepted values than that many observations store
+    let len = at_ceiling(least - 1, 0).to_bytes(&mut bytes).unwrap();
+    assert_eq!(
+        SamplingReservoir::<u32, 4>::from_bytes(&bytes[..len]).err(),
+        Some(DecodeError::InvalidState)
+    );
+}
+
+#[test]
+#[cfg(feature = "bytemuck")]
+fn pod_bytes_roundtrip() {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(C)]
+    struct Reading {
+        channel: u16,
+        flags: u16,
+        value: f32,
+    }
+    // SAFETY: repr(C) without padding, and any bit pattern is valid
+    unsafe impl bytemuck::Zeroable for Reading {}
+    unsafe impl bytemuck::Pod for Reading {}
+
+    let mut header = [0; ReservoirState::HEADER_LEN];
+
+    let mut buf = SamplingReservoir::<u16, 8>::new();
+    let state = ReservoirState::read_header(&header);
+    assert_eq!(state, Err(DecodeError::BadMagic));
+    for i in 0..1000 {
+        buf.write_state_header(&mut header);
+        let state = ReservoirState::read_header(&header).unwrap();
+        let bytes = buf.as_unordered_bytes();
+        assert_eq!(bytes.len(), buf.len() * 2);
+        let restored = SamplingReservoir::<u16, 8>::from_pod_bytes(bytes, state).unwrap();
+        assert!(restored.ordered_iter().eq(buf.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), buf.sampling_rate());
+        let _ = buf.sample(i);
+    }
+
+    let mut buf = SamplingReservoir::<Reading, 16>::new();
+    for i in 0..300u16 {
+        let _ = buf.sample(Reading {
+            channel: i % 4,
+            flags: i,
+            value: f32::from(i) / 2.0,
+        });
+    }
+    assert_eq!(buf.write_state_header(&mut header), header.len());
+    let state = ReservoirState::read_header(&header).unwrap();
+    assert_eq!(state.value_size, 8);
+    assert_eq!(state.samples_seen, 300);
+
+    // Unaligned input, like a byte offset into a flash page
+    let mut storage = [0u8; 1 + 16 * 8];
+    let bytes = buf.as_unordered_bytes();
+    storage[1..=bytes.len()].copy_from_slice(bytes);
+    let restored =
+        SamplingReservoir::<Reading, 16>::from_pod_bytes(&storage[1..=bytes.len()], state).unwrap();
+    assert!(restored.ordered_iter().eq(buf.ordered_iter()));
+
+    assert_eq!(
+        SamplingReservoir::<Reading, 16>::from_pod_bytes(&bytes[1..], state).err(),
+        Some(DecodeError::Truncated)
+    );
+    assert_eq!(
+        SamplingReservoir::<Reading, 8>::from_pod_bytes(bytes, state).err(),
+        Some(DecodeError::CapacityMismatch)
+    );
+    assert_eq!(
+        SamplingReservoir::<u32, 16>::from_pod_bytes(bytes, state).err(),
+        Some(DecodeError::ValueSizeMismatch)
+    );
+    let stale = ReservoirState {
+        samples_seen: 301,
+        ..state
+    };
+    assert_eq!(
+        SamplingReservoir::<Reading, 16>::from_pod_bytes(bytes, stale).err(),
+        Some(DecodeError::InvalidState)
+    );
+}
+
+#[test]
+#[cfg(feature = "bytemuck")]
+fn pod_bytes_roundtrip_rate_saturated() {
+    let mut header = [0; ReservoirState::HEADER_LEN];
+    for accepted in 0..10 {
+        let reservoir = rate_saturated_reservoir(accepted);
+        reservoir.write_state_header(&mut header);
+        let state = ReservoirState::read_header(&header).unwrap();
+        let restored =
+            SamplingReservoir::<u32, 4>::from_pod_bytes(reservoir.as_unordered_bytes(), state)
+                .unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+    }
+}
+
+#[test]
+#[cfg(feature = "minicbor")]
+fn minicbor_roundtrip() {
+    let mut bytes = [0u8; 256];
+    let mut buf = SamplingReservoir::<u32, 16>::new();
+    for i in 0..2000u32 {
+        if i % 97 == 0 {
+            bytes.fill(0);
+            minicbor::encode(&buf, &mut bytes[..]).unwrap();
+            let restored: SamplingReservoir<u32, 16> = minicbor::decode(&bytes).unwrap();
+            assert!(restored.ordered_iter().eq(buf.ordered_iter()));
+            assert_eq!(restored.sampling_rate(), buf.sampling_rate());
+            assert_eq!(restored.samples_seen(), buf.samples_seen());
+
+            bytes.fill(0);
+            minicbor::encode(buf.ordered_export(), &mut bytes[..]).unwrap();
+            let import: OrderedImport<u32, 16> = minicbor::decode(&bytes).unwrap();
+            assert_eq!(import.samples_seen, buf.samples_seen());
+            let restored = import.into_reservoir().unwrap();
+            assert!(restored.ordered_iter().eq(buf.ordered_iter()));
+        }
+        let _ = buf.sample(i);
+    }
+
+    // A payload from a different N fails cleanly
+    bytes.fill(0);
+    minicbor::encode(&buf, &mut bytes[..]).unwrap();
+    assert!(minicbor::decode::<SamplingReservoir<u32, 8>>(&bytes).is_err());
+    assert!(minicbor::decode::<SamplingReservoir<u32, 32>>(&bytes).is_err());
+    bytes.fill(0);
+    minicbor::encode(buf.ordered_export(), &mut bytes[..]).unwrap();
+    assert!(minicbor::decode::<OrderedImport<u32, 8>>(&bytes).is_err());
+    assert!(minicbor::decode::<OrderedImport<u32, 32>>(&bytes).is_err());
+
+    // Inconsistent counters
+    let mut encoder = minicbor::Encoder::new(&mut bytes[..]);
+    encoder
+        .array(6)
+        .unwrap()
+        .u32(16)
+        .unwrap()
+        .u64(100)
+        .unwrap()
+        .u64(17)
+        .unwrap()
+        .u32(1)
+        .unwrap()
+        .u32(0)
+        .unwrap();
+    assert!(minicbor::decode::<SamplingReservoir<u32, 16>>(&bytes).is_err());
+
+    // An import that decodes can be rebuilt, including past the rate saturation
+    let mut encoder = minicbor::Encoder::new(&mut bytes[..]);
+    encoder.array(2).unwrap().u64(1 << 40).unwrap();
+    encoder.array(2).unwrap().u32(0).unwrap().u32(1).unwrap();
+    let import: OrderedImport<u32, 2> = minicbor::decode(&bytes).unwrap();
+    assert!(import.into_reservoir().unwrap().ordered_iter().eq(&[0, 1]));
+    let max = SamplingReservoir::<u32, 2>::MAX_SAMPLES_SEEN as u64;
+    let mut encoder = minicbor::Encoder::new(&mut bytes[..]);
+    encoder.array(2).unwrap().u64(max + 1).unwrap();
+    encoder.array(2).unwrap().u32(0).unwrap().u32(1).unwrap();
+    assert!(minicbor::decode::<OrderedImport<u32, 2>>(&bytes).is_err());
+
+    // The output buffer is too small
+    assert!(minicbor::encode(&buf, &mut bytes[..20]).is_err());
+}
+
+#[test]
+#[cfg(feature = "minicbor")]
+fn minicbor_roundtrip_rate_saturated() {
+    let mut bytes = [0u8; 64];
+    for accepted in 0..10 {
+        let reservoir = rate_saturated_reservoir(accepted);
+        bytes.fill(0);
+        minicbor::encode(&reservoir, &mut bytes[..]).unwrap();
+        let restored: SamplingReservoir<u32, 4> = minicbor::decode(&bytes).unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+
+        bytes.fill(0);
+        minicbor::encode(reservoir.ordered_export(), &mut bytes[..]).unwrap();
+        let import: OrderedImport<u32, 4> = minicbor::decode(&bytes).unwrap();
+        let restored = import.into_reservoir().unwrap();
+        assert!(restored.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(restored.sampling_rate(), reservoir.sampling_rate());
+    }
+}
+
+/// In-memory NOR flash that can only clear bits on write, and loses power
+/// after a given number of written bytes
+#[cfg(feature = "embedded-storage")]
+struct MockFlash {
+    data: [u8; 2048],
+    write_budget: usize,
+}
+
+#[cfg(feature = "embedded-storage")]
+impl embedded_storage::nor_flash::ErrorType for MockFlash {
+    type Error = embedded_storage::nor_flash::NorFlashErrorKind;
+}
+
+#[cfg(feature = "embedded-storage")]
+impl embedded_storage::nor_flash::ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 4;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_read(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+#[cfg(feature = "embedded-storage")]
+impl embedded_storage::nor_flash::NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 8;
+    const ERASE_SIZE: usize = 512;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_erase(self, from, to)?;
+        for sector in self.data[from as usize..to as usize].chunks_mut(Self::ERASE_SIZE) {
+            if self.write_budget == 0 {
+                return Err(embedded_storage::nor_flash::NorFlashErrorKind::Other);
+            }
+            self.write_budget -= 1;
+            sector.fill(0xff);
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        embedded_storage::nor_flash::check_write(self, offset, bytes.len())?;
+        let offset = offset as usize;
+        for (cell, byte) in self.data[offset..].iter_mut().zip(bytes) {
+            if self.write_budget == 0 {
+                return Err(embedded_storage::nor_flash::NorFlashErrorKind::Other);
+            }
+            self.write_budget -= 1;
+            *cell &= byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+#[cfg(feature = "embedded-storage")]
+fn flash_save_load() {
+    let mut flash = MockFlash {
+        data: [0xff; 2048],
+        write_budget: usize::MAX,
+    };
+    assert!(SamplingReservoir::<u32, 64>::load(&mut flash, 512)
+        .unwrap()
+        .is_none());
+
+    let mut old = SamplingReservoir::<u32, 64>::new();
+    for i in 0..1000 {
+        let _ = old.sample(i);
+    }
+    let mut new = old.clone();
+    for i in 1000..1100 {
+        let _ = new.sample(i * 3);
+    }
+
+    old.save(&mut flash, 512).unwrap();
+    let loaded = SamplingReservoir::<u32, 64>::load(&mut flash, 512)
+        .unwrap()
+        .unwrap();
+    assert!(loaded.ordered_iter().eq(old.ordered_iter()));
+    assert_eq!(loaded.sampling_rate(), old.sampling_rate());
+
+    // Other types and capacities don't load
+    assert!(SamplingReservoir::<u32, 32>::load(&mut flash, 512)
+        .unwrap()
+        .is_none());
+    assert!(SamplingReservoir::<u16, 64>::load(&mut flash, 512)
+        .unwrap()
+        .is_none());
+    assert!(SamplingReservoir::<u32, 64>::load(&mut flash, 1024)
+        .unwrap()
+        .is_none());
+
+    // A power cut at any point of a save leaves either the old record or none at all
+    let snapshot = flash.data;
+    let mut outcomes = [0; 3];
+    for budget in 0..400 {
+        flash.data = snapshot;
+        flash.write_budget = budget;
+        let saved = new.save(&mut flash, 512).is_ok();
+        flash.write_budget = usize::MAX;
+        let loaded = SamplingReservoir::<u32, 64>::load(&mut flash, 512).unwrap();
+        match loaded {
+            None => {
+                assert!(!saved);
+                outcomes[0] += 1;
+            }
+            Some(loaded) if loaded.samples_seen() == old.samples_seen() => {
+                assert!(!saved);
+                assert!(loaded.ordered_iter().eq(old.ordered_iter()));
+                outcomes[1] += 1;
+            }
+            Some(loaded) => {
+                assert!(saved);
+                assert!(loaded.ordered_iter().eq(new.ordered_iter()));
+                outcomes[2] += 1;
+            }
+        }
+    }
+    // Only a cut before the first erase keeps the old record
+    assert_eq!(outcomes[1], 1);
+    assert!(outcomes[0] > 100);
+    assert!(outcomes[2] > 0);
+
+    // A corrupted payload is detected by the CRC
+    flash.data[512 + 16 + 40] ^= 1;
+    assert!(SamplingReservoir::<u32, 64>::load(&mut flash, 512)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+#[cfg(feature = "embedded-storage")]
+fn flash_save_load_rate_saturated() {
+    let mut flash = MockFlash {
+        data: [0xff; 2048],
+        write_budget: usize::MAX,
+    };
+    for accepted in 0..10 {
+        let reservoir = rate_saturated_reservoir(accepted);
+        reservoir.save(&mut flash, 512).unwrap();
+        let loaded = SamplingReservoir::<u32, 4>::load(&mut flash, 512)
+            .unwrap()
+            .unwrap();
+        assert!(loaded.ordered_iter().eq(reservoir.ordered_iter()));
+        assert_eq!(loaded.sampling_rate(), reservoir.sampling_rate());
+        assert_eq!(loaded.samples_seen(), reservoir.samples_seen());
+    }
+}
+
+#[test]
+#[cfg(feature = "embassy")]
+fn embassy_channel_drain() {
+    use core::cell::{Cell, RefCell};
+    use embassy_futures::select::select;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+    use embassy_sync::blocking_mutex::Mutex;
+    use embassy_sync::channel::Channel;
+
+    let channel = Channel::<NoopRawMutex, u32, 16>::new();
+    let reservoir = Mutex::new(RefCell::new(SamplingReservoir::<u32, 32>::new()));
+    let snapshots = Cell::new(0);
+
+    embassy_futures::block_on(select(
+        drain_channel_into(channel.receiver(), &reservoir),
+        embassy_futures::join::join(
+            async {
+                for i in 0..10_000 {
+                    channel.send(i).await;
+                }
+                while !channel.is_empty() {
+                    embassy_futures::yield_now().await;
+                }
+            },
+            // Another task reading consistent snapshots while values keep coming
+            async {
+                for _ in 0..100 {
+                    let snapshot = snapshot(&reservoir);
+                    assert!(snapshot
+                        .ordered_iter()
+                        .zip(snapshot.ordered_iter().skip(1))
+                        .all(|(a, b)| a < b));
+                    snapshots.set(snapshots.get() + 1);
+                    embassy_futures::yield_now().await;
+                }
+            },
+        ),
+    ));
+    assert_eq!(snapshots.get(), 100);
+
+    let expected = (0..10_000).sample_reservoir::<32>();
+    let drained = snapshot(&reservoir);
+    assert_eq!(drained.samples_seen(), 10_000);
+    assert!(drained.ordered_iter().eq(expected.ordered_iter()));
+}
+
+#[test]
+#[cfg(feature = "cs")]
+fn split_producer_consumer_threads() {
+    let count = if cfg!(miri) { 2_000 } else { 1_000_000 };
+    let mut reservoir = SplitReservoir::<u32, 32>::new();
+    let (mut producer, consumer) = reservoir.split();
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        let done = &done;
+        s.spawn(move || {
+            for i in 0..count {
+                let _ = producer.sample(i);
+            }
+            done.store(true, std::sync::atomic::Ordering::Release);
+        });
+        // Reads racing the producer always see a consistent state
+        let mut reads = 0;
+        while !done.load(std::sync::atomic::Ordering::Acquire) || reads < 10 {
+            let values = consumer.snapshot_ordered();
+            assert!(values.iter().zip(values.iter().skip(1)).all(|(a, b)| a < b));
+            let accepted = consumer.samples_accepted();
+            assert!(consumer.len() >= accepted.min(32));
+            assert!(consumer.samples_seen() >= accepted);
+            reads += 1;
+        }
+    });
+
+    let expected = (0..count).sample_reservoir::<32>();
+    assert_eq!(consumer.samples_seen(), count as usize);
+    assert_eq!(consumer.samples_accepted(), expected.samples_accepted());
+    assert!(consumer
+        .snapshot_ordered()
+        .iter()
+        .eq(expected.ordered_iter()));
+    let reservoir = reservoir.into_inner();
+    assert_eq!(reservoir.debug_validate(), Ok(()));
+}
+
+#[test]
+#[cfg(feature = "cs")]
+fn split_reentrant_access_panics() {
+    use std::cell::RefCell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    // Samples into the reservoir from within a snapshot of it
+    #[derive(Debug)]
+    struct Reentrant;
+
+    std::thread_local! {
+        static PRODUCER: RefCell<Option<Producer<'static, Reentrant, 2>>> = const { RefCell::new(None) };
+    }
+
+    impl Clone for Reentrant {
+        fn clone(&self) -> Self {
+            PRODUCER.with(|producer| {
+                let _ = producer.borrow_mut().as_mut().unwrap().sample(Reentrant);
+            });
+            Reentrant
+        }
+    }
+
+    // Handed out for 'static so that a thread local can hold the producer,
+    // and freed at the end
+    let reservoir =
+        std::boxed::Box::into_raw(std::boxed::Box::new(SplitReservoir::<Reentrant, 2>::new()));
+    let (mut producer, consumer) = unsafe { &mut *reservoir }.split();
+    let _ = producer.sample(Reentrant);
+    PRODUCER.with(|cell| *cell.borrow_mut() = Some(producer));
+
+    let panic = catch_unwind(AssertUnwindSafe(|| consumer.snapshot_ordered())).unwrap_err();
+    assert_eq!(
+        panic.downcast_ref::<&str>(),
+        Some(&"reservoir accessed reentrantly")
+    );
+    // The handles are usable again afterwards
+    assert_eq!(consumer.len(), 1);
+    PRODUCER.with(|cell| {
+        let _ = cell.borrow_mut().as_mut().unwrap().sample(Reentrant);
+    });
+    assert_eq!(consumer.samples_seen(), 2);
+
+    PRODUCER.with(|cell| cell.borrow_mut().take());
+    // SAFETY: the producer is gone and the consumer isn't used past here
+    drop(unsafe { std::boxed::Box::from_raw(reservoir) });
+}
+
+#[test]
+#[cfg(feature = "cs")]
+fn shared_reservoir_threads() {
+    static RESERVOIR: SharedSamplingReservoir<u32, 32> = SharedSamplingReservoir::new();
+    const THREADS: u32 = 4;
+    let per_thread = if cfg!(miri) { 500 } else { 250_000 };
+    let done = std::sync::atomic::AtomicU32::new(0);
+
+    std::thread::scope(|s| {
+        for t in 0..THREADS {
+            let done = &done;
+            s.spawn(move || {
+                for i in 0..per_thread {
+                    let _ = RESERVOIR.sample(t * per_thread + i);
+                }
+                done.fetch_add(1, std::sync::atomic::Ordering::Release);
+            });
+        }
+        // Reads racing the producers always see a consistent state
+        while done.load(std::sync::atomic::Ordering::Acquire) < THREADS {
+            let len = RESERVOIR.snapshot_ordered().len();
+            RESERVOIR.with(|reservoir| {
+                assert_eq!(reservoir.debug_validate(), Ok(()));
+                assert!(reservoir.len() >= len);
+            });
+        }
+    });
+
+    let total = (THREADS * per_thread) as usize;
+    RESERVOIR.with(|reservoir| {
+        assert_eq!(reservoir.samples_seen(), total);
+        assert_eq!(
+            reservoir.samples_accepted(),
+            math::samples_accepted_for::<32>(total)
+        );
+        assert_eq!(reservoir.debug_validate(), Ok(()));
+    });
+
+    // A single producer keeps the chronological order
+    let count = if cfg!(miri) { 1_000 } else { 10_000 };
+    let shared = SharedSamplingReservoir::<u32, 8>::default();
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for i in 0..count {
+                let _ = shared.sample(i);
+            }
+        });
+        for _ in 0..100 {
+            let values = shared.snapshot_ordered();
+            assert!(values.iter().zip(values.iter().skip(1)).all(|(a, b)| a < b));
+        }
+    });
+    let expected = (0..count).sample_reservoir::<8>();
+    assert!(shared.snapshot_ordered().iter().eq(expected.ordered_iter()));
+    assert!(shared
+        .into_inner()
+        .into_ordered_iter()
+        .eq(expected.into_ordered_iter()));
+}
+
+#[test]
+#[cfg(feature = "serde-json-core")]
+fn json_core_export_roundtrip() {
+    let mut buf = [0u8; 512];
+    let mut reservoir = SamplingReservoir::<i32, 16>::new();
+    let len = reservoir.json_export().to_slice(&mut buf).unwrap();
+    assert_eq!(&buf[..len], br#"{"seen":0,"values":[]}"#);
+
+    for i in 0..5000 {
+        let _ = reservoir.sample(1000 - i * 7);
+        if i % 333 != 0 {
+            continue;
+        }
+        let len = reservoir.json_export().to_slice(&mut buf).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&buf[..len]).unwrap();
+        assert_eq!(parsed["seen"], reservoir.samples_seen());
+        let values: Vec<i64> = parsed["values"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_i64().unwrap())
+            .collect();
+        assert!(values
+            .iter()
+            .map(|&value| value as i32)
+            .eq(reservoir.ordered_iter().copied()));
+    }
+
+    // Every truncated buffer is reported, never a partial document
+    let len = reservoir.json_export().to_slice(&mut buf).unwrap();
+    for short in 0..len {
+        assert_eq!(
+            reservoir.json_export().to_slice(&mut buf[..short]),
+            Err(BufferTooSmall)
+        );
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn prometheus_text_snapshot() {
+    /// Checks a line against the subset of the exposition grammar used here
+    fn valid_line(line: &str) -> bool {
+        fn valid_name(name: &str) -> bool {
+            let mut chars = name.chars();
+            chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+        }
+        if let Some(comment) = line.strip_prefix("# TYPE ") {
+            let mut parts = comment.split(' ');
+            return parts.next().is_some_and(valid_name)
+                && matches!(parts.next(), Some("summary" | "gauge"))
+                && parts.next().is_none();
+        }
+        let Some((series, value)) = line.split_once(' ') else {
+            return false;
+        };
+        let name = match series.split_once('{') {
+            Some((name, labels)) => {
+                let Some(quantile) = labels
+                    .strip_prefix("quantile=\"")
+                    .and_then(|rest| rest.strip_suffix("\"}"))
+                else {
+                    return false;
+                };
+                if quantile.parse::<f64>().is_err() {
+                    return false;
+                }
+                name
+            }
+            None => series,
+        };
+        valid_name(name)
+            && (matches!(value, "NaN" | "+Inf" | "-Inf") || value.parse::<f64>().is_ok())
+    }
+
+    let mut buf = SamplingReservoir::<f32, 8>::new();
+    for i in 0..40 {
+        let _ = buf.sample(i as f32 * 1.5 - 10.0);
+    }
+    let mut out = Vec::new();
+    buf.prometheus_text("9 request-latency.seconds", &mut out)
+        .unwrap();
+    let text = std::string::String::from_utf8(out).unwrap();
+    assert_eq!(
+        text,
+        "# TYPE _9_request_latency_seconds summary\n\
+         _9_request_latency_seconds{quantile=\"0\"} -10\n\
+         _9_request_latency_seconds{quantile=\"0.5\"} 14\n\
+         _9_request_latency_seconds{quantile=\"0.9\"} 38\n\
+         _9_request_latency_seconds{quantile=\"0.99\"} 38\n\
+         _9_request_latency_seconds{quantile=\"1\"} 38\n\
+         _9_request_latency_seconds_sum 130\n\
+         _9_request_latency_seconds_count 8\n\
+         # TYPE _9_request_latency_seconds_min gauge\n\
+         _9_request_latency_seconds_min -10\n\
+         # TYPE _9_request_latency_seconds_max gauge\n\
+         _9_request_latency_seconds_max 38\n\
+         # TYPE _9_request_latency_seconds_samples_seen gauge\n\
+         _9_request_latency_seconds_samples_seen 40\n"
+    );
+    assert!(text.lines().all(valid_line));
+
+    // No values
+    let mut out = Vec::new();
+    SamplingReservoir::<u8, 4>::new()
+        .prometheus_text("empty", &mut out)
+        .unwrap();
+    let text = std::string::String::from_utf8(out).unwrap();
+    assert!(text.contains("empty{quantile=\"0.5\"} NaN\n"));
+    assert!(text.contains("empty_count 0\n"));
+    assert!(text.lines().all(valid_line));
+}
+
+#[test]
+#[cfg(feature = "ffi")]
+fn ffi_header_matches_bindings() {
+    let header = include_str!("../include/infinity_sampler.h");
+    let declarations: Vec<&str> = header.lines().filter(|line| line.ends_with(");")).collect();
+    assert_eq!(declarations.len(), crate::ffi::PROTOTYPES.len());
+    for prototype in crate::ffi::PROTOTYPES {
+        assert!(declarations.contains(prototype), "{}", prototype);
+        let handle = prototype
+            .trim_start_matches("const ")
+            .split(|c: char| !c.is_alphanumeric())
+            .find(|word| word.starts_with("InfinitySampler"));
+        if let Some(handle) = handle {
+            let typedef = std::format!("typedef struct {} {};", handle, handle);
+            assert!(header.contains(&typedef), "{}", typedef);
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "ffi")]
+fn ffi_full_run() {
+    use crate::ffi::*;
+
+    unsafe {
+        let handle = infinity_sampler_new_f32_256();
+        assert!(!handle.is_null());
+        let mut reference = SamplingReservoir::<f32, 256>::new();
+        let mut reductions = 0;
+        for i in 0..100_000 {
+            let value = i as f32 * 0.25;
+            let code = infinity_sampler_sample_f32_256(handle, value);
+            let expected = match reference.sample(value) {
+                SamplingOutcome::Discarded(_) => 0,
+                SamplingOutcome::Consumed => 1,
+                SamplingOutcome::ConsumedAndRateReduced { .. } => 2,
+            };
+            assert_eq!(code, expected);
+            reductions += (code == 2) as usize;
+        }
+        assert!(reductions > 0);
+
+        let mut out = [0f32; 300];
+        let copied = infinity_sampler_ordered_copy_f32_256(handle, out.as_mut_ptr(), out.len());
+        assert_eq!(copied, 256);
+        assert!(out[..copied].iter().eq(reference.ordered_iter()));
+
+        // A short output only gets the oldest values
+        let mut short = [0f32; 10];
+        let copied = infinity_sampler_ordered_copy_f32_256(handle, short.as_mut_ptr(), 10);
+        assert_eq!(copied, 10);
+        assert_eq!(short[..], out[..10]);
+        infinity_sampler_free_f32_256(handle);
+
+        // Null handles and outputs are rejected
+        assert_eq!(infinity_sampler_sample_u32_64(core::ptr::null_mut(), 1), -1);
+        let handle = infinity_sampler_new_u32_64();
+        assert_eq!(infinity_sampler_sample_u32_64(handle, 7), 1);
+        assert_eq!(
+            infinity_sampler_ordered_copy_u32_64(handle, core::ptr::null_mut(), 4),
+            0
+        );
+        infinity_sampler_free_u32_64(handle);
+        infinity_sampler_free_u32_64(core::ptr::null_mut());
+    }
+}
+
+#[test]
+fn skip_discarded_matches_reference() {
+    let mut reference = SamplingReservoir::<u64, 16>::new();
+    let mut skipping = SamplingReservoir::<u64, 16>::new();
+    let mut pattern = 0x9e37_79b9_u32;
+    let mut i = 0u64;
+    let mut skipped_total = 0;
+    while i < 200_000 {
+        pattern = pattern.rotate_left(5) ^ 0x1234_5679;
+        if pattern & 3 == 0 {
+            // Skip ahead, the reference still sees the values
+            let expected = skipping.next_accepted_in();
+            let skipped = skipping.skip_discarded();
+            assert_eq!(skipped, expected);
+            assert_eq!(skipping.next_accepted_in(), 0);
+            for _ in 0..skipped {
+                assert!(reference.sample(i).is_discarded());
+                i += 1;
+            }
+            skipped_total += skipped;
+        }
+        let expected = reference.sample(i);
+        let outcome = skipping.sample(i);
+        assert_eq!(outcome.is_discarded(), expected.is_discarded());
+        assert_eq!(skipping.samples_seen(), reference.samples_seen());
+        assert_eq!(skipping.sampling_rate(), reference.sampling_rate());
+        i += 1;
+    }
+    assert!(skipped_total > 10_000);
+    assert!(skipping.ordered_iter().eq(reference.ordered_iter()));
+    skipping.debug_validate().unwrap();
+}
+
+#[test]
+fn sample_slice_matches_naive_loop() {
+    let mut sliced = SamplingReservoir::<u32, 32>::new();
+    let mut naive = SamplingReservoir::<u32, 32>::new();
+    let mut next = 0u32;
+    // Block sizes around the divisors, so that reductions fall inside blocks
+    for round in 0..400usize {
+        let len = [0, 1, 3, 31, 32, 33, 100, 1023, 4096][round % 9] + round;
+        let block: Vec<u32> = (next..next + len as u32).collect();
+        next += len as u32;
+
+        let stats = sliced.sample_slice(&block);
+        let expected = block.iter().copied().sample_into(&mut naive);
+        assert_eq!(stats, expected);
+        assert_eq!(sliced.as_unordered_slice(), naive.as_unordered_slice());
+        assert_eq!(sliced.samples_seen(), naive.samples_seen());
+        assert_eq!(sliced.sampling_rate(), naive.sampling_rate());
+        sliced.debug_validate().unwrap();
+    }
+    assert!(sliced.sampling_rate().divisor() > 1024);
+
+    // Only the accepted values are cloned
+    let clones = core::cell::Cell::new(0);
+    #[derive(Debug)]
+    struct Counted<'a>(&'a core::cell::Cell<usize>);
+    impl Clone for Counted<'_> {
+        fn clone(&self) -> Self {
+            self.0.set(self.0.get() + 1);
+            Self(self.0)
+        }
+    }
+    let block: Vec<_> = (0..10_000).map(|_| Counted(&clones)).collect();
+    let mut reservoir = SamplingReservoir::<Counted, 16>::new();
+    let stats = reservoir.sample_slice(&block);
+    assert_eq!(clones.get(), stats.stored);
+}
+
+#[test]
+fn lut_reservoir_matches_computed() {
+    fn check<const N: usize>(observations: usize) {
+        let mut lut = LutSamplingReservoir::<usize, N>::new();
+        let mut computed = SamplingReservoir::<usize, N>::new();
+        for i in 0..observations {
+            assert_eq!(lut.sample(i), computed.sample(i), "N={} i={}", N, i);
+            assert_eq!(lut.as_unordered_slice(), computed.as_unordered_slice());
+            assert_eq!(lut.samples_accepted(), computed.samples_accepted());
+            assert_eq!(lut.samples_seen(), computed.samples_seen());
+            assert_eq!(lut.sampling_rate(), computed.sampling_rate());
+            assert!(
+                lut.ordered_iter().eq(computed.ordered_iter()),
+                "N={} i={}",
+                N,
+                i
+            );
+            assert!(lut.ordered_iter().rev().eq(computed.ordered_iter().rev()));
+        }
+    }
+    let observations = if cfg!(miri) { 300 } else { 100_000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<16>(observations);
+    check::<32>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn lut_reservoir_saturated_count() {
+    let max = SamplingReservoir::<usize, 8>::MAX_SAMPLES_SEEN;
+    let mut computed = SamplingReservoir::<usize, 8>::new();
+    let mut lut = LutSamplingReservoir::<usize, 8>::new();
+    for i in 0..200 {
+        assert_eq!(computed.sample(i), lut.sample(i));
+    }
+    lut.set_samples_seen(max - 3);
+    for i in 200..2000 {
+        assert_eq!(computed.sample(i), lut.sample(i));
+        assert_eq!(lut.samples_seen(), (max - 3 + i - 199).min(max));
+        assert!(lut.ordered_iter().eq(computed.ordered_iter()));
+    }
+    assert_eq!(lut.samples_accepted(), computed.samples_accepted());
+}
+
+#[test]
+fn drain_ordered_into_moves_once() {
+    use std::rc::Rc;
+
+    fn check<const N: usize>(observations: usize) {
+        let marker = Rc::new(());
+        let mut buf = SamplingReservoir::<(Rc<()>, usize), N>::new();
+        for i in 0..observations {
+            let _ = buf.sample((marker.clone(), i));
+        }
+        let expected: Vec<_> = buf.ordered_iter().map(|(_, i)| *i).collect();
+
+        let mut out = heapless::Vec::new();
+        let _ = out.push((marker.clone(), usize::MAX));
+        buf.drain_ordered_into(&mut out);
+        assert_eq!(out.iter().map(|(_, i)| *i).collect::<Vec<_>>(), expected);
+        // Neither leaked nor dropped twice
+        assert_eq!(Rc::strong_count(&marker), 1 + expected.len());
+        drop(out);
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+    let deep = if cfg!(miri) { 100 } else { 5000 };
+    check::<4>(0);
+    check::<4>(3);
+    check::<4>(deep);
+    check::<16>(deep);
+
+    // Trivially copyable values
+    let mut buf = SamplingReservoir::<[u8; 64], 8>::new();
+    for i in 0..100 {
+        let _ = buf.sample([i; 64]);
+    }
+    let expected: Vec<_> = buf.ordered_iter().copied().collect();
+    let mut out = heapless::Vec::new();
+    buf.drain_ordered_into(&mut out);
+    assert_eq!(out[..], expected[..]);
+}
+
+#[test]
+fn ordered_runs_concatenate_to_ordered_iter() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for i in 0..observations {
+            let runs: Vec<_> = buf.ordered_runs().collect();
+            assert!(runs
+                .iter()
+                .flat_map(|run| run.iter())
+                .eq(buf.ordered_iter()));
+            assert!(runs.iter().all(|run| !run.is_empty()));
+            // Maximal: a run never continues where the previous one ended
+            let base = buf.as_unordered_slice().as_ptr();
+            for pair in runs.windows(2) {
+                let prev_end =
+                    unsafe { pair[0].as_ptr().offset_from(base) } as usize + pair[0].len();
+                assert_ne!(
+                    pair[1].as_ptr(),
+                    unsafe { base.add(prev_end) },
+                    "N={} seen={}",
+                    N,
+                    i
+                );
+            }
+            let _ = buf.sample(i);
+        }
+    }
+    let observations = if cfg!(miri) { 300 } else { 20_000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<8>(observations);
+    check::<64>(observations);
+}
+
+#[test]
+fn countdown_rate_matches_modulo_reference() {
+    // The previous representation, counting up modulo the divisor
+    struct Reference {
+        divisor: u32,
+        counter: u32,
+        pending_ratio: u32,
+    }
+
+    impl Reference {
+        fn step(&mut self) -> bool {
+            self.counter = (self.counter + 1) % self.divisor;
+            if self.counter != 0 {
+                return false;
+            }
+            self.divisor *= self.pending_ratio;
+            self.pending_ratio = 1;
+            true
+        }
+    }
+
+    let mut rng = 3u64;
+    let mut next = move || {
+        // SplitMix64
+        rng = rng.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    let steps = if cfg!(miri) { 20_000 } else { 5_000_000 };
+    let mut rate = SamplingRate::new(1);
+    let mut reference = Reference {
+        divisor: 1,
+        counter: 0,
+        pending_ratio: 1,
+    };
+    for i in 0..steps {
+        let op = next();
+        // Keep the divisor small enough to wrap often
+        if rate.divisor() > 1 << 12 {
+            let divisor = (op >> 32) as u32 % 16 + 1;
+            rate = SamplingRate::new(divisor);
+            reference = Reference {
+                divisor,
+                counter: 0,
+                pending_ratio: 1,
+            };
+        }
+        match op % 1024 {
+            0..=3 => {
+                let ratio = (op >> 16) as u32 % 3 + 1;
+                rate.div(ratio);
+                reference.divisor *= ratio;
+            }
+            4..=5 => {
+                let ratio = (op >> 16) as u32 % 3 + 1;
+                rate.div_deferred(ratio);
+                reference.pending_ratio *= ratio;
+            }
+            6 => {
+                let phase = (op >> 16) as u32 % reference.divisor;
+                rate.set_phase(phase);
+                reference.counter = phase;
+            }
+            7 => {
+                let n = (op >> 16) as u32 % 50;
+                let accepted = rate.step_n(n);
+                assert_eq!(accepted, (0..n).filter(|_| reference.step()).count() as u32);
+            }
+            _ => assert_eq!(rate.step(), reference.step(), "step {}", i),
+        }
+        assert_eq!(rate.divisor(), reference.divisor);
+        assert_eq!(rate.counter(), reference.counter);
+        assert_eq!(rate.pending_ratio().unwrap_or(1), reference.pending_ratio);
+        assert_eq!(
+            rate.samples_until_next(),
+            reference.divisor - reference.counter - 1
+        );
+    }
+}
+
+#[test]
+fn panic_safety() {
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    /// Panics on the clone or the drop at which the armed countdown runs out.
+    struct Fragile {
+        value: usize,
+        token: Rc<()>,
+        clones_left: Rc<Cell<usize>>,
+        drops_left: Rc<Cell<usize>>,
+    }
+
+    fn count_down(left: &Cell<usize>) -> bool {
+        match left.get() {
+            0 => false,
+            n => {
+                left.set(n - 1);
+                n == 1
+            }
+        }
+    }
+
+    impl Clone for Fragile {
+        fn clone(&self) -> Self {
+            if count_down(&self.clones_left) {
+                panic!("clone");
+            }
+            Self {
+                value: self.value,
+                token: self.token.clone(),
+                clones_left: self.clones_left.clone(),
+                drops_left: self.drops_left.clone(),
+            }
+        }
+    }
+
+    impl Drop for Fragile {
+        fn drop(&mut self) {
+            if count_down(&self.drops_left) {
+                panic!("drop");
+            }
+        }
+    }
+
+    let token = Rc::new(());
+    let clones_left = Rc::new(Cell::new(0));
+    let drops_left = Rc::new(Cell::new(0));
+    let fragile = |value| Fragile {
+        value,
+        token: token.clone(),
+        clones_left: clones_left.clone(),
+        drops_left: drops_left.clone(),
+    };
+    let values = |buf: &SamplingReservoir<Fragile, 8>| {
+        buf.ordered_iter().map(|v| v.value).collect::<Vec<_>>()
+    };
+
+    let mut buf = SamplingReservoir::<Fragile, 8>::new();
+    for i in 0..100 {
+        let _ = buf.sample(fragile(i));
+    }
+    let expected = values(&buf);
+    let live = || Rc::strong_count(&token) - 1;
+    assert_eq!(live(), 8);
+
+    for k in 1..=8 {
+        // Cloning the reservoir
+        clones_left.set(k);
+        assert!(catch_unwind(AssertUnwindSafe(|| buf.clone())).is_err());
+        assert_eq!(live(), 8);
+        assert_eq!(values(&buf), expected);
+
+        // Cloning while taking items out of the owning iterator
+        clones_left.set(0);
+        let iter = buf.clone().into_ordered_iter();
+        clones_left.set(k);
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            let mut copies = Vec::new();
+            for v in iter {
+                copies.push(v.clone());
+            }
+            copies
+        }));
+        assert!(result.is_err());
+        assert_eq!(live(), 8);
+
+        // Dropping the rest of the owning iterator, from the start or part way
+        for front in [0, 3] {
+            clones_left.set(0);
+            let mut iter = buf.clone().into_ordered_iter();
+            iter.by_ref().take(front).for_each(drop);
+            drops_left.set(k);
+            let result = catch_unwind(AssertUnwindSafe(|| drop(iter)));
+            assert_eq!(result.is_err(), k <= 8 - front);
+            drops_left.set(0);
+            assert_eq!(live(), 8);
+        }
+    }
+
+    // Cloning a block, the observations up to the panic stay counted
+    let block: Vec<_> = (100..200).map(fragile).collect();
+    for k in 1..=3 {
+        clones_left.set(k);
+        let result = catch_unwind(AssertUnwindSafe(|| buf.sample_slice(&block)));
+        assert!(result.is_err());
+        buf.debug_validate().unwrap();
+        assert_eq!(live(), 8 + block.len());
+    }
+    clones_left.set(0);
+    drop(block);
+
+    // Dropping the overwritten value, the new one still takes its slot
+    buf.skip_discarded();
+    let seen = buf.samples_seen();
+    drops_left.set(1);
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let _ = buf.sample(fragile(seen));
+    }));
+    assert!(result.is_err());
+    buf.debug_validate().unwrap();
+    assert_eq!(buf.ordered_iter().last().map(|v| v.value), Some(seen));
+    assert_eq!(live(), 8);
+
+    drop(buf);
+    assert_eq!(live(), 0);
+}
+
+#[test]
+fn zero_sized_elements() {
+    use std::cell::Cell;
+
+    std::thread_local! {
+        static LIVE: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// Zero-sized, but counts its live instances.
+    struct Unit;
+
+    impl Unit {
+        fn new() -> Self {
+            LIVE.with(|live| live.set(live.get() + 1));
+            Unit
+        }
+    }
+
+    impl Clone for Unit {
+        fn clone(&self) -> Self {
+            Unit::new()
+        }
+    }
+
+    impl Drop for Unit {
+        fn drop(&mut self) {
+            LIVE.with(|live| live.set(live.get() - 1));
+        }
+    }
+
+    fn live() -> isize {
+        LIVE.with(Cell::get)
+    }
+
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<Unit, N>::new();
+        let mut stored = 0;
+        for _ in 0..observations {
+            if buf.sample(Unit::new()).is_consumed() {
+                stored += 1;
+            }
+        }
+        assert_eq!(buf.samples_seen(), observations);
+        assert_eq!(buf.samples_accepted(), stored);
+        assert_eq!(buf.len(), stored.min(N));
+        assert_eq!(live(), buf.len() as isize);
+        assert_eq!(buf.as_unordered_slice().len(), buf.len());
+        assert_eq!(buf.ordered_iter().count(), buf.len());
+        assert_eq!(buf.ordered_iter().rev().count(), buf.len());
+        assert_eq!(
+            buf.ordered_runs().map(<[Unit]>::len).sum::<usize>(),
+            buf.len()
+        );
+
+        let copy = buf.clone();
+        assert_eq!(live(), 2 * buf.len() as isize);
+        let mut iter = copy.into_ordered_iter();
+        assert_eq!(iter.by_ref().take(N / 2).count(), buf.len().min(N / 2));
+        drop(iter);
+        assert_eq!(live(), buf.len() as isize);
+        assert_eq!(buf.clone().into_ordered_iter().count(), buf.len());
+
+        let mut out = heapless::Vec::new();
+        buf.clone().drain_ordered_into(&mut out);
+        assert_eq!(out.len(), buf.len());
+        drop(out);
+        drop(buf);
+        assert_eq!(live(), 0);
+    }
+    let observations = if cfg!(miri) { 5_000 } else { 1 << 22 };
+    check::<2>(observations);
+    check::<16>(observations);
+    check::<1024>(observations);
+    check::<4>(3);
+
+    // Millions of observations, skipping to the accepted ones
+    let mut buf = SamplingReservoir::<(), 64>::new();
+    while buf.samples_seen() < 1 << 30 {
+        buf.skip_discarded();
+        assert!(buf.sample(()).is_consumed());
+    }
+    assert_eq!(buf.ordered_iter().count(), 64);
+    assert_eq!(buf.into_ordered_iter().count(), 64);
+}
+
+#[test]
+fn clone_unwinds_cleanly() {
+    use std::cell::Cell;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    std::thread_local! {
+        static CLONES: Cell<usize> = const { Cell::new(0) };
+        static PANIC_AT: Cell<usize> = const { Cell::new(0) };
+        static LIVE: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts its clones and live instances, and panics on the `PANIC_AT`-th clone.
+    struct Counted;
+
+    impl Counted {
+        fn new() -> Self {
+            LIVE.with(|live| live.set(live.get() + 1));
+            Counted
+        }
+    }
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            let call = CLONES.with(|clones| clones.get()) + 1;
+            if call == PANIC_AT.with(Cell::get) {
+                panic!("clone");
+            }
+            CLONES.with(|clones| clones.set(call));
+            Counted::new()
+        }
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            LIVE.with(|live| live.set(live.get() - 1));
+        }
+    }
+
+    fn check<B: Clone>(reservoir: &B, len: usize) {
+        let live = || LIVE.with(Cell::get);
+        assert_eq!(live(), len);
+        for k in 1..=len {
+            CLONES.with(|clones| clones.set(0));
+            PANIC_AT.with(|panic_at| panic_at.set(k));
+            assert!(catch_unwind(AssertUnwindSafe(|| reservoir.clone())).is_err());
+            // The values cloned before the panic are dropped again
+            assert_eq!(CLONES.with(Cell::get), k - 1);
+            assert_eq!(live(), len);
+        }
+        PANIC_AT.with(|panic_at| panic_at.set(0));
+        let copy = reservoir.clone();
+        assert_eq!(live(), 2 * len);
+        drop(copy);
+        assert_eq!(live(), len);
+    }
+
+    for observations in [3, 100] {
+        let mut buf = SamplingReservoir::<Counted, 8>::new();
+        let mut indexed = IndexedSamplingReservoir::<Counted, 8>::new();
+        for _ in 0..observations {
+            let _ = buf.sample(Counted::new());
+        }
+        check(&buf, buf.len());
+        drop(buf);
+        for _ in 0..observations {
+            let _ = indexed.sample(Counted::new());
+        }
+        check(&indexed, indexed.len());
+        drop(indexed);
+        assert_eq!(LIVE.with(Cell::get), 0);
+    }
+}
+
+#[test]
+fn owning_iter_drop_paths() {
+    use std::rc::Rc;
+
+    fn check<const N: usize>(observations: usize) {
+        let marker = Rc::new(());
+        let mut buf = SamplingReservoir::<Rc<()>, N>::new();
+        for _ in 0..observations {
+            let _ = buf.sample(marker.clone());
+        }
+        let len = buf.len();
+        for front in 0..=len {
+            for skip in [0, 1, len / 2] {
+                let clone = buf.clone();
+                assert_eq!(Rc::strong_count(&marker), 1 + len * 2);
+                let mut iter = clone.into_ordered_iter();
+                let taken: Vec<_> = iter.by_ref().take(front).collect();
+                if skip > 0 {
+                    let _ = iter.nth(skip - 1);
+                }
+                drop(iter);
+                assert_eq!(Rc::strong_count(&marker), 1 + len + taken.len());
+            }
+        }
+        // Abandoned right away
+        drop(buf.clone().into_ordered_iter());
+        drop(buf);
+        assert_eq!(Rc::strong_count(&marker), 1);
+    }
+    let deep = if cfg!(miri) { 100 } else { 5000 };
+    check::<4>(0);
+    check::<4>(3);
+    check::<4>(deep);
+    check::<16>(deep);
+
+    // No drop glue
+    let mut buf = SamplingReservoir::<u16, 16>::new();
+    for i in 0..1000 {
+        let _ = buf.sample(i);
+    }
+    let mut iter = buf.into_ordered_iter();
+    assert_eq!(iter.next(), Some(0));
+    drop(iter);
+}
+
+#[test]
+fn write_at_fill_boundary() {
+    fn check<const N: usize>() {
+        let mut buf = SamplingReservoir::<Vec<usize>, N>::new();
+        for i in 0..N - 1 {
+            assert_eq!(buf.sample(vec![i]), SamplingOutcome::Consumed);
+        }
+        assert_eq!(buf.len(), N - 1);
+
+        // The last append fills the buffer
+        assert_eq!(buf.sample(vec![N - 1]), SamplingOutcome::Consumed);
+        assert_eq!(buf.len(), N);
+        assert!(buf
+            .as_unordered_slice()
+            .iter()
+            .enumerate()
+            .all(|(i, v)| v == &[i]));
+
+        // The first overwrite reduces the rate and replaces slot 1
+        assert!(matches!(
+            buf.sample(vec![N]),
+            SamplingOutcome::ConsumedAndRateReduced { .. }
+        ));
+        assert_eq!(buf.len(), N);
+        assert_eq!(buf.as_unordered_slice()[1], [N]);
+        assert!(buf.sample(vec![N + 1]).is_discarded());
+        assert!(buf.sample(vec![N + 2]).is_consumed());
+        assert_eq!(buf.len(), N);
+        assert_eq!(buf.ordered_iter().next(), Some(&vec![0]));
+        assert_eq!(buf.ordered_iter().last(), Some(&vec![N + 2]));
+        buf.debug_validate().unwrap();
+
+        // Restored right at the boundary, the next write appends or overwrites the same way
+        for seen in [N - 1, N, N + 1] {
+            let mut reference = SamplingReservoir::<usize, N>::new();
+            for i in 0..seen {
+                let _ = reference.sample(i);
+            }
+            let mut restored = SamplingReservoir::<usize, N>::from_unordered(
+                seen,
+                reference.samples_accepted(),
+                reference.as_unordered_slice().iter().copied().collect(),
+            )
+            .unwrap();
+            for i in seen..seen + 4 {
+                assert_eq!(restored.sample(i), reference.sample(i));
+                assert_eq!(
+                    restored.as_unordered_slice(),
+                    reference.as_unordered_slice()
+                );
+            }
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<64>();
+}
+
+#[test]
+fn accepted_values_are_never_dropped() {
+    fn check<const N: usize>() {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        for outer_index in 0..N + 2 {
+            let stored = buf.samples_accepted();
+            let outcome = buf.sample(outer_index);
+            assert_eq!(buf.len(), buf.samples_accepted().min(N));
+            // Up to N, every observation is accepted, and N + 1 is the first one discarded
+            if outer_index == N + 1 {
+                assert!(outcome.is_discarded());
+                assert_eq!(buf.samples_accepted(), stored);
+                continue;
+            }
+            assert!(outcome.is_consumed(), "N={} outer_index={}", N, outer_index);
+            assert_eq!(buf.samples_accepted(), stored + 1);
+            let slot = SamplingReservoir::<usize, N>::storage_index_for_outer_index(outer_index);
+            assert_eq!(buf.as_unordered_slice()[slot], outer_index);
+            assert_eq!(buf.ordered_iter().last(), Some(&outer_index));
+        }
+    }
+    check::<2>();
+    check::<4>();
+    check::<8>();
+    check::<16>();
+}
+
+#[test]
+#[should_panic]
+fn write_past_full_buffer_panics() {
+    let mut buf = SamplingReservoir::<usize, 4>::new();
+    for i in 0..4 {
+        let _ = buf.sample(i);
+    }
+    // An index equal to the length of a full buffer must not be silently dropped
+    buf.write_at_index(4, 4);
+}
+
+#[cfg(feature = "strict")]
+#[test]
+fn strict_sample_checked() {
+    // Same behavior as sample() on a consistent reservoir
+    let mut reference = SamplingReservoir::<usize, 8>::new();
+    let mut checked = SamplingReservoir::<usize, 8>::new();
+    for i in 0..10_000 {
+        assert_eq!(checked.sample_checked(i), Ok(reference.sample(i)));
+    }
+    assert!(checked.ordered_iter().eq(reference.ordered_iter()));
+
+    // Counters ahead of the buffer, so the insertion index skips the free slots
+    let mut skipped = SamplingReservoir::<usize, 8>::from_parts(
+        heapless::Vec::from_slice(&[0, 1]).unwrap(),
+        SamplingRate::new(1),
+        5,
+        5,
+    );
+    assert_eq!(skipped.sample_checked(5), Err(InvariantViolation));
+
+    // A divisor that isn't a power of two
+    let mut odd_rate =
+        SamplingReservoir::<usize, 4>::from_parts((0..4).collect(), SamplingRate::new(3), 4, 4);
+    let outcomes = (4..7)
+        .map(|i| odd_rate.sample_checked(i))
+        .collect::<Vec<_>>();
+    assert!(outcomes.contains(&Err(InvariantViolation)));
+
+    // More values accepted than observed
+    let mut diverged =
+        SamplingReservoir::<usize, 4>::from_parts((0..4).collect(), SamplingRate::new(1), 4, 2);
+    assert_eq!(diverged.sample_checked(2), Err(InvariantViolation));
+
+    // The rate halved more often than the schedule allows
+    let mut overreduced =
+        SamplingReservoir::<usize, 4>::from_parts((0..4).collect(), SamplingRate::new(4), 4, 4);
+    let outcomes = (4..8)
+        .map(|i| overreduced.sample_checked(i))
+        .collect::<Vec<_>>();
+    assert!(outcomes.contains(&Err(InvariantViolation)));
+}
+
+#[cfg(feature = "strict")]
+#[test]
+#[should_panic(expected = "sampler invariant violated")]
+fn strict_sample_panics_on_violation() {
+    let mut buf = SamplingReservoir::<usize, 8>::from_parts(
+        heapless::Vec::from_slice(&[0, 1]).unwrap(),
+        SamplingRate::new(1),
+        5,
+        5,
+    );
+    let _ = buf.sample(5);
+}
+
+#[test]
+fn cached_order_matches_fresh() {
+    fn check<const N: usize>(observations: usize) {
+        let mut buf = SamplingReservoir::<usize, N>::new();
+        let mut order = CachedOrder::new();
+        for i in 0..observations {
+            let _ = buf.sample(i);
+            // Repeated reads between writes, from both ends
+            for _ in 0..3 {
+                assert!(
+                    order.ordered_iter(&buf).eq(buf.ordered_iter()),
+                    "N={} i={}",
+                    N,
+                    i
+                );
+                assert!(order.ordered_iter(&buf).rev().eq(buf.ordered_iter().rev()));
+                assert_eq!(order.ordered_iter(&buf).len(), buf.len());
+            }
+            // A cache shared between reservoirs with the same number of stored values
+            let mut other = SamplingReservoir::<usize, N>::new();
+            for j in 0..i + 1 {
+                let _ = other.sample(j * 2);
+            }
+            assert!(order.ordered_iter(&other).eq(other.ordered_iter()));
+        }
+
+        let mut fresh = CachedOrder::new();
+        assert_eq!(order.indices(&buf), fresh.indices(&buf));
+        order.invalidate();
+        assert_eq!(order.indices(&buf), fresh.indices(&buf));
     }
+    let observations = if cfg!(miri) { 100 } else { 3000 };
+    check::<2>(observations);
+    check::<4>(observations);
+    check::<16>(observations);
+    check::<64>(observations);
 }
@@ -0,0 +1,80 @@
+/// A source of monotonic time for [TimedSamplingRate].
+///
+/// The tick unit is up to the implementor. The counter is allowed to wrap around
+/// `u64::MAX`, as long as less than `u64::MAX` ticks pass between two steps.
+///
+/// Implemented for any `Fn() -> u64`.
+pub trait Now {
+    fn now(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> Now for F {
+    fn now(&self) -> u64 {
+        self()
+    }
+}
+
+/// A time-based sampler that accepts at most one step per `interval` ticks.
+///
+/// ```
+/// use core::cell::Cell;
+/// use infinity_sampler::TimedSamplingRate;
+///
+/// let time = Cell::new(0);
+/// let mut sampler = TimedSamplingRate::new(|| time.get(), 100);
+/// assert_eq!(sampler.step(), true);
+/// time.set(50);
+/// assert_eq!(sampler.step(), false);
+/// time.set(100);
+/// assert_eq!(sampler.step(), true);
+/// ```
+#[derive(Clone)]
+pub struct TimedSamplingRate<C> {
+    clock: C,
+    interval: u64,
+    last: Option<u64>,
+}
+
+impl<C: Now> TimedSamplingRate<C> {
+    pub const fn new(clock: C, interval: u64) -> Self {
+        Self {
+            clock,
+            interval,
+            last: None,
+        }
+    }
+
+    /// Returns true if at least `interval` ticks have elapsed since the last
+    /// accepted step. The very first step is always accepted.
+    pub fn step(&mut self) -> bool {
+        let now = self.clock.now();
+        if let Some(last) = self.last {
+            if now.wrapping_sub(last) < self.interval {
+                return false;
+            }
+        }
+        self.last = Some(now);
+        true
+    }
+
+    /// Reduce the sampling rate by a ratio, stretching the interval.
+    /// Saturates at `u64::MAX` ticks.
+    pub fn div(&mut self, ratio: u32) {
+        assert!(ratio > 0);
+        self.interval = self.interval.saturating_mul(ratio as u64);
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Returns the timestamp of the last accepted step, if any.
+    pub fn last_accepted(&self) -> Option<u64> {
+        self.last
+    }
+
+    /// Forgets the last accepted step, so that the next step is accepted.
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
@@ -0,0 +1,145 @@
+use core::fmt::{self, Write};
+use std::string::String;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::vec::Vec;
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::SamplingReservoir;
+
+/// A [Layer] retaining an evenly spread subset of the events in a [SamplingReservoir].
+///
+/// Every event at or below the [maximum level](Self::with_max_level) is observed by the
+/// reservoir, and only the ones it stores get formatted, into a string of at most
+/// [max_event_len](Self::with_max_event_len) bytes.
+///
+/// Clones share the same reservoir, so keep one to [drain](Self::drain_ordered) it
+/// after installing the other:
+///
+/// ```
+/// use infinity_sampler::ReservoirLayer;
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// let layer = ReservoirLayer::<64>::new();
+/// let subscriber = tracing_subscriber::registry().with(layer.clone());
+/// tracing::subscriber::with_default(subscriber, || {
+///     for i in 0..1000 {
+///         tracing::info!(i, "request");
+///     }
+/// });
+/// let events = layer.drain_ordered();
+/// assert_eq!(events.len(), 64);
+/// assert_eq!(events[0], "INFO request i=0");
+/// ```
+#[derive(Clone)]
+pub struct ReservoirLayer<const N: usize> {
+    reservoir: Arc<Mutex<SamplingReservoir<String, N>>>,
+    max_level: Level,
+    max_event_len: usize,
+}
+
+impl<const N: usize> ReservoirLayer<N> {
+    /// Creates a layer sampling the events of all levels, truncated to 256 bytes.
+    pub fn new() -> Self {
+        Self {
+            reservoir: Arc::new(Mutex::new(SamplingReservoir::new())),
+            max_level: Level::TRACE,
+            max_event_len: 256,
+        }
+    }
+
+    /// Only samples the events at `level` or more severe ones.
+    pub fn with_max_level(mut self, level: Level) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Truncates the formatted events to `len` bytes.
+    pub fn with_max_event_len(mut self, len: usize) -> Self {
+        self.max_event_len = len;
+        self
+    }
+
+    /// Takes the retained events in chronological order, leaving the reservoir empty.
+    pub fn drain_ordered(&self) -> Vec<String> {
+        let mut reservoir = self
+            .reservoir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        core::mem::take(&mut *reservoir)
+            .into_ordered_iter()
+            .collect()
+    }
+}
+
+impl<const N: usize> Default for ReservoirLayer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Subscriber, const N: usize> Layer<S> for ReservoirLayer<N> {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > self.max_level {
+            return;
+        }
+        let mut reservoir = self
+            .reservoir
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // Don't format the events that would be discarded anyway
+        if reservoir.next_accepted_in() > 0 {
+            let _ = reservoir.sample(String::new());
+            return;
+        }
+        let mut out = BoundedString {
+            out: String::new(),
+            max_len: self.max_event_len,
+        };
+        let _ = write!(out, "{}", metadata.level());
+        event.record(&mut EventVisitor(&mut out));
+        let _ = reservoir.sample(out.out);
+    }
+}
+
+/// Drops whatever doesn't fit into `max_len` bytes.
+struct BoundedString {
+    out: String,
+    max_len: usize,
+}
+
+impl Write for BoundedString {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let available = self.max_len.saturating_sub(self.out.len());
+        let mut end = s.len().min(available);
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.out.push_str(&s[..end]);
+        Ok(())
+    }
+}
+
+struct EventVisitor<'a>(&'a mut BoundedString);
+
+impl Visit for EventVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {}", value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
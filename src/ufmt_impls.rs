@@ -0,0 +1,98 @@
+use ufmt::{uDebug, uDisplay, uWrite, uwrite, Formatter};
+
+use crate::rate::ReservoirRate;
+use crate::{SamplingOutcome, SamplingRate, SamplingReservoir};
+
+impl uDebug for SamplingRate {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        f.debug_struct("SamplingRate")?
+            .field("divisor", &self.divisor())?
+            .field("counter", &self.counter())?
+            .finish()
+    }
+}
+
+/// Writes the rate as `1/divisor`.
+impl uDisplay for SamplingRate {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(f, "1/{}", self.divisor())
+    }
+}
+
+impl<T: uDebug> uDebug for SamplingOutcome<T> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        match self {
+            Self::Consumed => f.write_str("Consumed"),
+            Self::ConsumedAndRateReduced {
+                old_divisor,
+                new_divisor,
+            } => f
+                .debug_struct("ConsumedAndRateReduced")?
+                .field("old_divisor", old_divisor)?
+                .field("new_divisor", new_divisor)?
+                .finish(),
+            Self::Discarded(value) => f.debug_tuple("Discarded")?.field(value)?.finish(),
+        }
+    }
+}
+
+/// Writes the variant only, without the divisors or the value, to avoid pulling in
+/// `u64` formatting.
+impl<T> uDisplay for SamplingOutcome<T> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        f.write_str(match self {
+            Self::Consumed => "consumed",
+            Self::ConsumedAndRateReduced { .. } => "consumed, rate reduced",
+            Self::Discarded(_) => "discarded",
+        })
+    }
+}
+
+/// Writes the counters and the values of a [SamplingReservoir] in chronological order,
+/// returned by [ordered_display()](SamplingReservoir::ordered_display).
+///
+/// ```
+/// use infinity_sampler::SamplingReservoir;
+/// use infinity_sampler::heapless::String;
+///
+/// let mut reservoir = SamplingReservoir::<u32, 4>::new();
+/// for i in 0..8 {
+///     let _ = reservoir.sample(i);
+/// }
+/// let mut out = String::<64>::new();
+/// ufmt::uwrite!(out, "{}", reservoir.ordered_display(", ")).unwrap();
+/// assert_eq!(out, "seen 8, accepted 6: 0, 2, 4, 6");
+/// ```
+pub struct OrderedDisplay<'a, T, const N: usize, R> {
+    reservoir: &'a SamplingReservoir<T, N, R>,
+    separator: &'a str,
+}
+
+impl<T: uDisplay, const N: usize, R: ReservoirRate> uDisplay for OrderedDisplay<'_, T, N, R> {
+    fn fmt<W: uWrite + ?Sized>(&self, f: &mut Formatter<'_, W>) -> Result<(), W::Error> {
+        uwrite!(
+            f,
+            "seen {}, accepted {}: ",
+            self.reservoir.samples_seen(),
+            self.reservoir.samples_accepted()
+        )?;
+        for (i, value) in self.reservoir.ordered_iter().enumerate() {
+            if i > 0 {
+                f.write_str(self.separator)?;
+            }
+            value.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, const N: usize, R: ReservoirRate> SamplingReservoir<T, N, R> {
+    /// Returns a [uDisplay] wrapper writing the values in chronological order,
+    /// joined by `separator`, see [OrderedDisplay].
+    pub fn ordered_display<'a>(&'a self, separator: &'a str) -> OrderedDisplay<'a, T, N, R> {
+        OrderedDisplay {
+            reservoir: self,
+            separator,
+        }
+    }
+}